@@ -0,0 +1,7 @@
+CREATE TABLE email_events (
+  id uuid NOT NULL PRIMARY KEY,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+  kind TEXT NOT NULL,
+  payload jsonb NOT NULL,
+  received_at timestamptz NOT NULL
+);
@@ -0,0 +1,5 @@
+CREATE TABLE suppressions (
+  email TEXT NOT NULL PRIMARY KEY,
+  reason TEXT NOT NULL,
+  created_at timestamptz NOT NULL
+);
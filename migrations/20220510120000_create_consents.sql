@@ -0,0 +1,7 @@
+CREATE TABLE consents (
+  id uuid NOT NULL PRIMARY KEY,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+  source TEXT NOT NULL,
+  note TEXT NOT NULL,
+  recorded_at timestamptz NOT NULL
+);
@@ -0,0 +1,13 @@
+-- Hashes of erased subscribers' emails, so they aren't re-imported
+CREATE TABLE erasure_tombstones (
+  email_hash TEXT NOT NULL PRIMARY KEY,
+  erased_at timestamptz NOT NULL
+);
+
+-- A log of erasures for compliance, deliberately without any personal data
+CREATE TABLE erasures (
+  id uuid NOT NULL PRIMARY KEY,
+  subscriber_id uuid NOT NULL,
+  requested_by TEXT NOT NULL,
+  erased_at timestamptz NOT NULL
+);
@@ -0,0 +1,17 @@
+CREATE TABLE subscription_events (
+  id uuid NOT NULL PRIMARY KEY,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+  from_status TEXT,
+  to_status TEXT NOT NULL,
+  actor TEXT NOT NULL,
+  cause TEXT NOT NULL,
+  occurred_at timestamptz NOT NULL
+);
+
+CREATE INDEX subscription_events_subscriber_id_idx
+  ON subscription_events (subscriber_id, occurred_at);
+
+-- Only checked for new writes until it's validated by a later migration, since each migration runs
+-- in a transaction that would hold this one's lock while existing rows are checked
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_status_check
+  CHECK (status IN ('pending', 'confirmed', 'bounced', 'complained', 'unsubscribed')) NOT VALID;
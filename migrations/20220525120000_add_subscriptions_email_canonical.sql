@@ -0,0 +1,24 @@
+-- The canonical form of each email, used to detect duplicates. Rows written before this column
+-- existed are backfilled with an approximation (trimmed and lower-cased), except where that would
+-- collide with another row.
+ALTER TABLE subscriptions ADD COLUMN email_canonical TEXT;
+
+UPDATE subscriptions
+SET email_canonical = lower(trim(email))
+WHERE lower(trim(email)) IN (
+  SELECT lower(trim(email))
+  FROM subscriptions
+  GROUP BY lower(trim(email))
+  HAVING count(*) = 1
+);
+
+CREATE UNIQUE INDEX subscriptions_email_canonical_idx ON subscriptions (email_canonical);
+
+-- Existing subscribers whose emails differ only by case or whitespace, which need to be resolved by
+-- hand (e.g. by erasing all but one) before they get a canonical email
+CREATE VIEW subscription_email_collisions AS
+SELECT lower(trim(email)) AS email_canonical, array_agg(id ORDER BY subscribed_at) AS subscriber_ids
+FROM subscriptions
+WHERE email_canonical IS NULL
+GROUP BY lower(trim(email))
+HAVING count(*) > 1;
@@ -0,0 +1,13 @@
+CREATE TABLE newsletter_issues (
+  id uuid NOT NULL PRIMARY KEY,
+  slug TEXT NOT NULL UNIQUE,
+  title TEXT NOT NULL,
+  html_body TEXT NOT NULL,
+  text_body TEXT NOT NULL,
+  created_at timestamptz NOT NULL,
+  published_at timestamptz
+);
+
+CREATE INDEX newsletter_issues_published_at_idx
+  ON newsletter_issues (published_at DESC)
+  WHERE published_at IS NOT NULL;
@@ -0,0 +1,13 @@
+CREATE TABLE blog_feeds (
+  url TEXT NOT NULL PRIMARY KEY,
+  first_polled_at timestamptz NOT NULL,
+  last_polled_at timestamptz NOT NULL
+);
+
+CREATE TABLE blog_feed_entries (
+  feed_url TEXT NOT NULL REFERENCES blog_feeds (url),
+  entry_id TEXT NOT NULL,
+  issue_id uuid REFERENCES newsletter_issues (id),
+  seen_at timestamptz NOT NULL,
+  PRIMARY KEY (feed_url, entry_id)
+);
@@ -0,0 +1,6 @@
+CREATE TABLE publish_webhook_nonces (
+  nonce TEXT NOT NULL PRIMARY KEY,
+  received_at timestamptz NOT NULL
+);
+
+CREATE INDEX publish_webhook_nonces_received_at_idx ON publish_webhook_nonces (received_at);
@@ -0,0 +1,6 @@
+-- When a draft should be published and sent automatically
+ALTER TABLE newsletter_issues ADD COLUMN send_at timestamptz;
+
+CREATE INDEX newsletter_issues_send_at_idx
+  ON newsletter_issues (send_at)
+  WHERE published_at IS NULL AND send_at IS NOT NULL;
@@ -0,0 +1,15 @@
+-- Responses to requests made with an `Idempotency-Key` header, replayed when the request is retried.
+-- A row without a response status is a request that is still in progress.
+CREATE TABLE idempotency_keys (
+  key TEXT NOT NULL,
+  method TEXT NOT NULL,
+  path TEXT NOT NULL,
+  request_hash BYTEA NOT NULL,
+  created_at timestamptz NOT NULL,
+  response_status SMALLINT,
+  response_headers BYTEA,
+  response_body BYTEA,
+  PRIMARY KEY (key, method, path)
+);
+
+CREATE INDEX idempotency_keys_created_at_idx ON idempotency_keys (created_at);
@@ -0,0 +1,16 @@
+CREATE TABLE email_deliveries (
+  id uuid NOT NULL PRIMARY KEY,
+  recipient TEXT NOT NULL,
+  kind TEXT NOT NULL,
+  -- Not a foreign key, since deliveries are logged before the issue's transaction commits
+  issue_id uuid,
+  message_id TEXT,
+  status SMALLINT,
+  error TEXT,
+  requested_at timestamptz NOT NULL,
+  completed_at timestamptz NOT NULL
+);
+
+CREATE INDEX email_deliveries_recipient_idx ON email_deliveries (lower(recipient), requested_at);
+CREATE INDEX email_deliveries_issue_id_idx ON email_deliveries (issue_id) WHERE issue_id IS NOT NULL;
+CREATE INDEX email_deliveries_requested_at_idx ON email_deliveries (requested_at);
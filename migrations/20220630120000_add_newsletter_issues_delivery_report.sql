@@ -0,0 +1,6 @@
+-- The outcome of sending an issue, recorded once delivery has finished
+ALTER TABLE newsletter_issues
+  ADD COLUMN delivered_at timestamptz,
+  ADD COLUMN delivered_count integer,
+  ADD COLUMN suppressed_count integer,
+  ADD COLUMN failed_count integer;
@@ -0,0 +1,9 @@
+-- The timestamp each nonce was signed with, which may be ahead of when it was received. Nonces
+-- must be kept until their signed timestamp expires, or a future-dated request could be replayed.
+--
+-- Existing rows, and rows inserted by replicas that don't set the column yet, are assumed to have
+-- been signed as far ahead as the tolerance allows (5 minutes).
+ALTER TABLE publish_webhook_nonces
+  ADD COLUMN signed_at timestamptz NOT NULL DEFAULT now() + interval '5 minutes';
+
+CREATE INDEX publish_webhook_nonces_signed_at_idx ON publish_webhook_nonces (signed_at);
@@ -0,0 +1,15 @@
+-- Backfill canonical emails that are still missing, now that every replica sets them. This covers
+-- rows inserted by old replicas during the deployment that added the column, using the same
+-- approximation as the original backfill, and again skipping rows that would collide.
+UPDATE subscriptions s
+SET email_canonical = lower(trim(s.email))
+WHERE s.email_canonical IS NULL
+  AND NOT EXISTS (
+    SELECT 1
+    FROM subscriptions other
+    WHERE other.id <> s.id
+      AND (
+        other.email_canonical = lower(trim(s.email))
+        OR (other.email_canonical IS NULL AND lower(trim(other.email)) = lower(trim(s.email)))
+      )
+  );
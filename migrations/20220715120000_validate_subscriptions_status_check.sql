@@ -0,0 +1,3 @@
+-- Check existing rows against the constraint added as NOT VALID, which only needs a lock that allows
+-- writes to continue
+ALTER TABLE subscriptions VALIDATE CONSTRAINT subscriptions_status_check;
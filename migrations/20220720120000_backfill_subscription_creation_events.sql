@@ -0,0 +1,14 @@
+-- Record the creation of subscribers that have no events, because they were created before events
+-- were recorded or by replicas that didn't record them yet. Their original status and who created
+-- them weren't recorded, so the event has their current status and an unknown actor.
+INSERT INTO subscription_events (id, subscriber_id, from_status, to_status, actor, cause, occurred_at)
+SELECT
+  md5(random()::text || s.id::text)::uuid,
+  s.id,
+  NULL,
+  s.status,
+  'unknown',
+  'created before events were recorded',
+  s.subscribed_at
+FROM subscriptions s
+WHERE NOT EXISTS (SELECT 1 FROM subscription_events e WHERE e.subscriber_id = s.id);
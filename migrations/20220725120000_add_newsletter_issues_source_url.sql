@@ -0,0 +1,4 @@
+-- The blog post an issue announces, so that a post picked up by both the feed poller and the
+-- publish webhook only becomes one issue. Existing issues don't record their post, so they're left
+-- without one.
+ALTER TABLE newsletter_issues ADD COLUMN source_url TEXT UNIQUE;
@@ -1,23 +1,91 @@
 use std::{net::SocketAddr, time::Duration};
 
-use axum::routing::{get, post};
+use axum::routing::{delete, get, post, put};
 use reqwest::Url;
-use sqlx::postgres::PgPoolOptions;
-use tracing::warn;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 
-use crate::{email_client::EmailClient, routes, telemetry, Config, Error};
+use crate::{
+    auth::{AdminToken, PostmarkWebhookCredentials, PublishWebhookSecret},
+    blog_feed::{self, BlogFeed},
+    bulk::BulkSender,
+    circuit_breaker::CircuitBreaker,
+    deliveries::Deliveries,
+    domain::EmailCanonicalizer,
+    email_client::EmailClient,
+    idempotency,
+    import::{self, ImportError, ImportOptions, ImportReport},
+    migrations::Migrations,
+    policy::SubscriptionPolicy,
+    routes,
+    scheduler::Scheduler,
+    signing::SigningKey,
+    suppressions::Suppressions,
+    telemetry, Config, Error,
+};
 
 fn routes() -> axum::Router {
     axum::Router::new()
         .route("/health", get(routes::health))
+        .route("/health/ready", get(routes::ready))
+        .route("/feed.rss", get(routes::rss_feed))
+        .route("/feed.atom", get(routes::atom_feed))
+        .route("/feed.json", get(routes::json_feed))
+        .route("/newsletters", get(routes::list_newsletters))
+        .route("/newsletters/:slug", get(routes::show_newsletter))
         .route("/subscriptions", post(routes::subscribe))
         .route("/subscriptions/confirm", get(routes::confirm))
+        .route(
+            "/subscriptions/data-export",
+            get(routes::data_export).post(routes::request_data_export),
+        )
+        .route("/subscriptions/erasure", post(routes::request_erasure))
+        .route(
+            "/subscriptions/erasure/confirm",
+            get(routes::erasure_form).post(routes::confirm_erasure),
+        )
+        .route("/webhooks/postmark", post(routes::postmark_webhook))
+        .route("/webhooks/publish", post(routes::publish_webhook))
+        .route("/admin/deliveries", get(routes::list_deliveries))
+        .route("/admin/newsletters", post(routes::publish_newsletter))
+        .route("/admin/newsletters/drafts", get(routes::list_drafts))
+        .route(
+            "/admin/newsletters/:slug/publish",
+            post(routes::publish_draft),
+        )
+        .route(
+            "/admin/newsletters/:slug/schedule",
+            put(routes::schedule_newsletter).delete(routes::cancel_schedule),
+        )
+        .route("/admin/subscribers/export", get(routes::export_subscribers))
+        .route(
+            "/admin/subscribers/import",
+            post(routes::import_subscribers),
+        )
+        .route("/admin/subscribers/:id", delete(routes::erase_subscriber))
+        .route(
+            "/admin/subscribers/:id/events",
+            get(routes::subscriber_events),
+        )
+        .route(
+            "/admin/suppressions",
+            get(routes::list_suppressions).post(routes::add_suppression),
+        )
+        .route(
+            "/admin/suppressions/:email",
+            delete(routes::remove_suppression),
+        )
 }
 
 pub struct App {
     addr: SocketAddr,
     pool: sqlx::PgPool,
-    ignore_missing_migrations: bool,
+    migrations: Migrations,
+    base_url: Url,
+    canonicalizer: EmailCanonicalizer,
+    signing_key: SigningKey,
+    sender: BulkSender,
+    scheduler: Scheduler,
+    blog_feed_poller: Option<blog_feed::Poller>,
     service: axum::routing::IntoMakeService<axum::Router>,
 }
 
@@ -37,15 +105,38 @@ impl std::ops::Deref for AppBaseUrl {
 
 impl App {
     pub fn new(config: Config) -> Self {
-        let pool = PgPoolOptions::new()
-            .connect_timeout(Duration::from_secs(2))
-            .connect_lazy_with(config.database_options());
+        let pool = connect_lazy(config.database_options());
+        let canonicalizer = EmailCanonicalizer {
+            gmail_dots: config.canonicalize_gmail_dots,
+        };
+        let signing_key = SigningKey::new(&config.signing_key);
 
         let email_client = EmailClient::new(
             config.email_base_url,
             config.email_sender,
             config.email_authorization_token,
             config.email_send_timeout,
+            config.email_retry_deadline,
+        )
+        .with_suppressions(Suppressions::new(pool.clone(), canonicalizer))
+        .with_deliveries(Deliveries::new(pool.clone()))
+        .with_circuit_breaker(CircuitBreaker::new(
+            "email",
+            f64::from(config.email_circuit_failure_rate) / 100.0,
+            config.email_circuit_window,
+            config.email_circuit_open_duration,
+        ));
+        let sender = BulkSender::new(
+            email_client.clone(),
+            config.email_max_concurrency,
+            config.email_max_rate,
+        );
+
+        let scheduler = Scheduler::new(
+            pool.clone(),
+            sender.clone(),
+            config.base_url.clone(),
+            config.scheduler_interval,
         );
 
         let service = routes()
@@ -53,16 +144,58 @@ impl App {
                 tower::ServiceBuilder::new()
                     .layer(telemetry::id_layer())
                     .layer(telemetry::trace_layer())
+                    // Outside the transaction layer, so responses are only stored once committed
+                    .layer(idempotency::Layer::new(
+                        pool.clone(),
+                        config.idempotency_key_ttl,
+                        config.idempotency_max_body_size,
+                    ))
                     .layer(axum_sqlx_tx::Layer::new_with_error::<Error>(pool.clone()))
-                    .layer(axum::Extension(AppBaseUrl(config.base_url)))
-                    .layer(axum::Extension(email_client)),
+                    .layer(axum::Extension(pool.clone()))
+                    .layer(axum::Extension(AppBaseUrl(config.base_url.clone())))
+                    .layer(axum::Extension(email_client.clone()))
+                    .layer(axum::Extension(scheduler.waker()))
+                    .layer(axum::Extension(sender.clone()))
+                    .layer(axum::Extension(canonicalizer))
+                    .layer(axum::Extension(SubscriptionPolicy::new(
+                        &config.subscription_allowed_domains,
+                        &config.subscription_denied_domains,
+                        &config.disposable_domains,
+                    )))
+                    .layer(axum::Extension(AdminToken(config.admin_token)))
+                    .layer(axum::Extension(PostmarkWebhookCredentials {
+                        username: config.postmark_webhook_username,
+                        password: config.postmark_webhook_password,
+                    }))
+                    .layer(axum::Extension(PublishWebhookSecret(
+                        config.publish_webhook_secret,
+                    )))
+                    .layer(axum::Extension(signing_key.clone())),
             )
             .into_make_service();
 
+        let blog_feed_poller = config.blog_feed_url.map(|url| {
+            blog_feed::Poller::new(
+                BlogFeed {
+                    url,
+                    poll_interval: config.blog_feed_poll_interval,
+                    auto_send: config.blog_feed_auto_send,
+                },
+                pool.clone(),
+                scheduler.waker(),
+            )
+        });
+
         Self {
             addr: config.address,
+            migrations: Migrations::from_pool(pool.clone(), config.ignore_missing_migrations),
             pool,
-            ignore_missing_migrations: config.ignore_missing_migrations,
+            base_url: config.base_url,
+            canonicalizer,
+            signing_key,
+            sender,
+            scheduler,
+            blog_feed_poller,
             service,
         }
     }
@@ -72,22 +205,50 @@ impl App {
     }
 
     pub async fn serve(self) -> Result<Server, sqlx::migrate::MigrateError> {
-        self.migrate().await.or_else(|error| match error {
-            sqlx::migrate::MigrateError::VersionMissing(_) if self.ignore_missing_migrations => {
-                warn!(
-                    ?error,
-                    "database state is ahead of that known by the app – \
-                    in a rollback scenario this is expected, but otherwise something may be wrong"
-                );
-                Ok(())
-            }
-            _ => Err(error),
-        })?;
-        Ok(axum::Server::bind(&self.addr).serve(self.service))
+        self.migrate().await?;
+        Ok(self.start())
     }
 
-    #[tracing::instrument(skip(self))]
-    async fn migrate(&self) -> Result<(), sqlx::migrate::MigrateError> {
-        sqlx::migrate!("./migrations").run(&self.pool).await
+    /// Start the server without running migrations.
+    ///
+    /// This also starts sending scheduled issues in the background, and polling the blog feed if
+    /// one is configured.
+    pub fn start(self) -> Server {
+        tokio::spawn(self.scheduler.run());
+        if let Some(poller) = self.blog_feed_poller {
+            tokio::spawn(poller.run());
+        }
+        axum::Server::bind(&self.addr).serve(self.service)
     }
+
+    pub async fn migrate(&self) -> Result<(), sqlx::migrate::MigrateError> {
+        self.migrations.run().await
+    }
+
+    /// Import subscribers from CSV, committing all accepted rows.
+    pub async fn import_subscribers(
+        &self,
+        csv: &[u8],
+        options: &ImportOptions,
+    ) -> Result<ImportReport, ImportError> {
+        let mut tx = self.pool.begin().await?;
+        let import = import::import(
+            &mut tx,
+            &self.canonicalizer,
+            &self.signing_key,
+            csv,
+            options,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(import
+            .send_confirmations(&self.sender, &self.base_url)
+            .await)
+    }
+}
+
+pub(crate) fn connect_lazy(options: PgConnectOptions) -> sqlx::PgPool {
+    PgPoolOptions::new()
+        .connect_timeout(Duration::from_secs(2))
+        .connect_lazy_with(options)
 }
@@ -0,0 +1,191 @@
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::{FromRequest, RequestParts, TypedHeader},
+    headers::{
+        authorization::{Basic, Bearer},
+        Authorization,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    BoxError, Extension,
+};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use time::{Duration, OffsetDateTime};
+
+/// How far a publish webhook's timestamp may be from our clock.
+///
+/// Nonces only need to be remembered until this long after their timestamp, since older requests
+/// are rejected anyway.
+pub(crate) const PUBLISH_WEBHOOK_TOLERANCE: Duration = Duration::minutes(5);
+
+#[derive(Clone)]
+pub(crate) struct AdminToken(pub(crate) String);
+
+/// Extractor that only succeeds for requests carrying the admin bearer token.
+pub(crate) struct Admin;
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Admin {
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(AdminToken(token)) = Extension::<AdminToken>::from_request(req)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        let TypedHeader(Authorization(bearer)) =
+            TypedHeader::<Authorization<Bearer>>::from_request(req)
+                .await
+                .map_err(|_| challenge("Bearer", "admin"))?;
+
+        if constant_time_eq(bearer.token(), &token) {
+            Ok(Self)
+        } else {
+            Err(challenge("Bearer", "admin"))
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct PostmarkWebhookCredentials {
+    pub(crate) username: String,
+    pub(crate) password: String,
+}
+
+/// Extractor that only succeeds for requests carrying the Postmark webhook's basic auth credentials.
+pub(crate) struct PostmarkWebhook;
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for PostmarkWebhook {
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(credentials) = Extension::<PostmarkWebhookCredentials>::from_request(req)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        let TypedHeader(Authorization(basic)) =
+            TypedHeader::<Authorization<Basic>>::from_request(req)
+                .await
+                .map_err(|_| challenge("Basic", "postmark"))?;
+
+        if constant_time_eq(basic.username(), &credentials.username)
+            && constant_time_eq(basic.password(), &credentials.password)
+        {
+            Ok(Self)
+        } else {
+            Err(challenge("Basic", "postmark"))
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct PublishWebhookSecret(pub(crate) String);
+
+/// Extractor for publish webhook requests with a valid signature and a recent timestamp.
+///
+/// The `X-Webhook-Signature` header must be `sha256=` followed by the hex-encoded HMAC-SHA256 of
+/// `{timestamp}.{nonce}.{body}`, using the values of the `X-Webhook-Timestamp` (Unix seconds) and
+/// `X-Webhook-Nonce` headers. Checking that the nonce hasn't been used before is up to the handler.
+pub(crate) struct PublishWebhook {
+    pub(crate) nonce: String,
+    pub(crate) signed_at: OffsetDateTime,
+    pub(crate) body: Bytes,
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for PublishWebhook
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(PublishWebhookSecret(secret)) =
+            Extension::<PublishWebhookSecret>::from_request(req)
+                .await
+                .map_err(IntoResponse::into_response)?;
+
+        let headers = req.headers();
+        let (timestamp, nonce, signature) = match (
+            header_str(headers, "x-webhook-timestamp"),
+            header_str(headers, "x-webhook-nonce"),
+            header_str(headers, "x-webhook-signature"),
+        ) {
+            (Some(timestamp), Some(nonce), Some(signature)) if !nonce.is_empty() => (
+                timestamp.to_string(),
+                nonce.to_string(),
+                signature.to_string(),
+            ),
+            _ => return Err(unauthorized("missing signature headers")),
+        };
+
+        let sent_at: i64 = timestamp
+            .parse()
+            .map_err(|_| unauthorized("invalid timestamp"))?;
+        let skew = i128::from(OffsetDateTime::now_utc().unix_timestamp()) - i128::from(sent_at);
+        if skew.abs() > i128::from(PUBLISH_WEBHOOK_TOLERANCE.whole_seconds()) {
+            return Err(unauthorized(
+                "timestamp is too old or too far in the future",
+            ));
+        }
+
+        let body = Bytes::from_request(req)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        let expected = publish_webhook_signature(&secret, &timestamp, &nonce, &body);
+        if constant_time_eq(&signature, &expected) {
+            Ok(Self {
+                nonce,
+                signed_at: OffsetDateTime::from_unix_timestamp(sent_at),
+                body,
+            })
+        } else {
+            Err(unauthorized("invalid signature"))
+        }
+    }
+}
+
+fn publish_webhook_signature(secret: &str, timestamp: &str, nonce: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(nonce.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+fn header_str<'h>(headers: &'h HeaderMap, name: &str) -> Option<&'h str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn unauthorized(reason: &'static str) -> Response {
+    (StatusCode::UNAUTHORIZED, reason).into_response()
+}
+
+fn challenge(scheme: &str, realm: &str) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(
+            header::WWW_AUTHENTICATE,
+            format!("{} realm=\"{}\"", scheme, realm),
+        )],
+    )
+        .into_response()
+}
+
+// Compare secrets without short-circuiting, so response times don't leak how much of a guess was correct.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
@@ -0,0 +1,327 @@
+//! Turning new blog posts into newsletter issues, by polling the blog's RSS or Atom feed.
+
+use std::time::Duration;
+
+use feed_rs::model::{Entry, Text};
+use reqwest::Url;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::{error, info, warn};
+
+use crate::{
+    html::{sanitize, to_text, Escaped},
+    newsletters::{self, Inserted, NewIssue},
+    scheduler::SchedulerWaker,
+    Error,
+};
+
+/// How long to wait for the blog to respond.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) struct BlogFeed {
+    pub(crate) url: Url,
+    pub(crate) poll_interval: Duration,
+
+    /// Publish and send issues immediately, rather than leaving drafts for an admin to approve.
+    pub(crate) auto_send: bool,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct PollReport {
+    pub(crate) seen: usize,
+    pub(crate) drafted: usize,
+    pub(crate) scheduled: usize,
+    pub(crate) skipped: usize,
+}
+
+pub(crate) struct Poller {
+    feed: BlogFeed,
+    pool: PgPool,
+    http_client: reqwest::Client,
+    scheduler: SchedulerWaker,
+}
+
+impl Poller {
+    pub(crate) fn new(feed: BlogFeed, pool: PgPool, scheduler: SchedulerWaker) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .unwrap();
+
+        Self {
+            feed,
+            pool,
+            http_client,
+            scheduler,
+        }
+    }
+
+    /// Poll the feed forever, logging any failures.
+    pub(crate) async fn run(self) {
+        let mut interval = tokio::time::interval(self.feed.poll_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            match self.poll().await {
+                Ok(report) => info!(?report, "polled blog feed"),
+                Err(error) => error!(%error, "failed to poll blog feed"),
+            }
+        }
+    }
+
+    /// Create an issue for each entry in the feed that hasn't been seen before.
+    ///
+    /// The first time a feed is polled its existing entries are only recorded as seen, so that
+    /// subscribers aren't sent the blog's entire back catalogue.
+    #[tracing::instrument(skip_all, fields(feed_url = %self.feed.url))]
+    pub(crate) async fn poll(&self) -> Result<PollReport, Error> {
+        let body = self
+            .http_client
+            .get(self.feed.url.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|error| Error::Internal(error.into()))?
+            .bytes()
+            .await
+            .map_err(|error| Error::Internal(error.into()))?;
+        let mut entries = feed_rs::parser::parse(&body[..])
+            .map_err(|error| Error::Internal(error.into()))?
+            .entries;
+
+        // Create issues in the order the posts were published
+        entries.sort_by_key(|entry| entry.published.or(entry.updated));
+
+        let mut report = PollReport::default();
+        let mut tx = self.pool.begin().await?;
+
+        let now = OffsetDateTime::now_utc();
+
+        // Lock the feed, so that concurrent pollers take turns
+        let first_poll = sqlx::query!(
+            "SELECT url FROM blog_feeds WHERE url = $1 FOR UPDATE",
+            self.feed.url.as_str(),
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .is_none();
+        sqlx::query!(
+            r#"
+            INSERT INTO blog_feeds (url, first_polled_at, last_polled_at)
+            VALUES ($1, $2, $2)
+            ON CONFLICT (url) DO UPDATE SET last_polled_at = EXCLUDED.last_polled_at
+            "#,
+            self.feed.url.as_str(),
+            now,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        for entry in &entries {
+            let inserted = sqlx::query!(
+                r#"
+                INSERT INTO blog_feed_entries (feed_url, entry_id, seen_at)
+                VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING
+                "#,
+                self.feed.url.as_str(),
+                entry.id,
+                now,
+            )
+            .execute(&mut tx)
+            .await?
+            .rows_affected()
+                > 0;
+            if !inserted {
+                continue;
+            }
+
+            report.seen += 1;
+            if first_poll {
+                continue;
+            }
+
+            let new_issue = match new_issue(entry) {
+                Some(new_issue) => new_issue,
+                None => {
+                    warn!(entry_id = %entry.id, "skipping blog post without a title or link");
+                    report.skipped += 1;
+                    continue;
+                }
+            };
+            let (issue, created) =
+                match newsletters::insert_issue(&mut tx, &new_issue, None).await? {
+                    Inserted::Created(issue) => (issue, true),
+                    Inserted::Existing(issue) => (issue, false),
+                };
+
+            sqlx::query!(
+                "UPDATE blog_feed_entries SET issue_id = $3 WHERE feed_url = $1 AND entry_id = $2",
+                self.feed.url.as_str(),
+                entry.id,
+                issue.id,
+            )
+            .execute(&mut tx)
+            .await?;
+
+            if !created {
+                info!(entry_id = %entry.id, slug = %issue.slug, "blog post already has an issue");
+                report.skipped += 1;
+                continue;
+            }
+
+            info!(entry_id = %entry.id, slug = %issue.slug, "created issue for blog post");
+            if self.feed.auto_send {
+                newsletters::schedule(&mut tx, &issue.slug, Some(now)).await?;
+                report.scheduled += 1;
+            } else {
+                report.drafted += 1;
+            }
+        }
+
+        tx.commit().await?;
+
+        // Issues are sent by the scheduler once committed, so that a failed commit can't lead to
+        // an issue being sent again on the next poll, and failed sends don't stop the rest
+        if report.scheduled > 0 {
+            self.scheduler.wake();
+        }
+
+        Ok(report)
+    }
+}
+
+/// An issue announcing a blog post, from its title, summary and link.
+fn new_issue(entry: &Entry) -> Option<NewIssue> {
+    let title = entry.title.as_ref()?.content.trim();
+    let link = &entry.links.first()?.href;
+    if title.is_empty() {
+        return None;
+    }
+
+    let (mut html_body, mut text_body) = match &entry.summary {
+        // The feed is outside our control, so its HTML is sanitized before it's shown on our site
+        Some(summary) if is_html(summary) => {
+            let html = sanitize(summary.content.trim());
+            let text = to_text(&html);
+            (format!("{}\n", html), format!("{}\n\n", text))
+        }
+        Some(summary) => (
+            format!("<p>{}</p>\n", Escaped(summary.content.trim())),
+            format!("{}\n\n", summary.content.trim()),
+        ),
+        None => (String::new(), String::new()),
+    };
+    html_body.push_str(&format!(
+        r#"<p><a href="{}">Read the full post</a></p>"#,
+        Escaped(link)
+    ));
+    text_body.push_str(&format!("Read the full post: {}", link));
+
+    Some(NewIssue {
+        title: title.to_string(),
+        html_body,
+        text_body,
+        slug: None,
+        source_url: Some(link.clone()),
+    })
+}
+
+fn is_html(text: &Text) -> bool {
+    text.content_type.to_string().starts_with("text/html")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::new_issue;
+
+    fn entries(xml: &str) -> Vec<feed_rs::model::Entry> {
+        feed_rs::parser::parse(xml.as_bytes()).unwrap().entries
+    }
+
+    #[test]
+    fn rss_items_become_issues_with_summary_and_link() {
+        let entries = entries(
+            r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>Blog</title>
+<item>
+  <title>Zero to production</title>
+  <link>https://blog.example/zero-to-production</link>
+  <description>&lt;p&gt;Shipping &lt;em&gt;Rust&lt;/em&gt; &amp;amp; more&lt;/p&gt;</description>
+</item>
+</channel></rss>"#,
+        );
+
+        let issue = new_issue(&entries[0]).unwrap();
+
+        assert_eq!(issue.title, "Zero to production");
+        assert_eq!(
+            issue.html_body,
+            "<p>Shipping <em>Rust</em> &amp; more</p>\n\
+            <p><a href=\"https://blog.example/zero-to-production\">Read the full post</a></p>"
+        );
+        assert_eq!(
+            issue.text_body,
+            "Shipping Rust & more\n\n\
+            Read the full post: https://blog.example/zero-to-production"
+        );
+        assert!(issue.slug.is_none());
+    }
+
+    #[test]
+    fn atom_entries_with_text_summaries_are_escaped() {
+        let entries = entries(
+            r#"<?xml version="1.0"?>
+<feed xmlns="http://www.w3.org/2005/Atom"><title>Blog</title><id>urn:blog</id>
+<updated>2022-06-01T00:00:00Z</updated>
+<entry>
+  <title>Generics</title>
+  <id>urn:post:1</id>
+  <updated>2022-06-01T00:00:00Z</updated>
+  <link href="https://blog.example/generics"/>
+  <summary>Vec&lt;T&gt; explained</summary>
+</entry>
+</feed>"#,
+        );
+
+        let issue = new_issue(&entries[0]).unwrap();
+
+        assert!(issue
+            .html_body
+            .starts_with("<p>Vec&lt;T&gt; explained</p>\n"));
+        assert!(issue.text_body.starts_with("Vec<T> explained\n\n"));
+    }
+
+    #[test]
+    fn html_summaries_are_sanitized() {
+        let entries = entries(
+            r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>Blog</title>
+<item>
+  <title>Zero to production</title>
+  <link>https://blog.example/zero-to-production</link>
+  <description>&lt;p onclick="steal()"&gt;Shipping&lt;/p&gt;&lt;script&gt;steal()&lt;/script&gt;</description>
+</item>
+</channel></rss>"#,
+        );
+
+        let issue = new_issue(&entries[0]).unwrap();
+
+        assert!(issue.html_body.starts_with("<p>Shipping</p>\n"));
+        assert!(!issue.text_body.contains("steal"));
+    }
+
+    #[test]
+    fn entries_without_a_title_or_link_are_skipped() {
+        let entries = entries(
+            r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>Blog</title>
+<item><link>https://blog.example/untitled</link></item>
+<item><title>Unlinked</title></item>
+</channel></rss>"#,
+        );
+
+        assert!(entries.iter().all(|entry| new_issue(entry).is_none()));
+    }
+}
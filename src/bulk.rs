@@ -0,0 +1,211 @@
+//! Sending large numbers of emails without overwhelming the provider.
+//!
+//! Emails are sent in batches, with at most a configured number of batches in flight and a cap on
+//! messages per second that's shared by every bulk send. If the provider rate limits us, every
+//! send waits out the `Retry-After` delay before continuing.
+
+use std::{
+    cmp,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::StreamExt as _;
+use tokio::time::Instant;
+use tracing::{info, warn, Span};
+
+use crate::email_client::{self, Email, EmailClient, MAX_BATCH_SIZE};
+
+/// How long to back off if the provider rate limits us without saying for how long.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// How many times a batch is retried after being rate limited, before giving up on it.
+const MAX_RATE_LIMITED_RETRIES: usize = 5;
+
+/// What happened to a single email in a bulk send.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Delivered,
+    Suppressed,
+    Failed,
+}
+
+#[derive(Clone)]
+pub(crate) struct BulkSender {
+    email_client: EmailClient,
+    max_concurrency: usize,
+    batch_size: usize,
+    limiter: Arc<RateLimiter>,
+}
+
+impl BulkSender {
+    /// Send with at most `max_concurrency` batches in flight, and `max_rate` messages per second.
+    pub(crate) fn new(email_client: EmailClient, max_concurrency: usize, max_rate: u32) -> Self {
+        Self {
+            email_client,
+            max_concurrency,
+            // Batches no bigger than a second's worth of messages keep the rate smooth
+            batch_size: cmp::min(MAX_BATCH_SIZE, max_rate as usize),
+            limiter: Arc::new(RateLimiter::new(max_rate)),
+        }
+    }
+
+    /// Send every email, returning the outcome of each in the same order.
+    ///
+    /// Progress is recorded on the span, and logged after each batch.
+    #[tracing::instrument(skip_all, fields(total = emails.len(), delivered = 0, failed = 0))]
+    pub(crate) async fn send(&self, emails: &[Email<'_>]) -> Vec<Outcome> {
+        let started = Instant::now();
+        let span = Span::current();
+        let mut outcomes = Vec::with_capacity(emails.len());
+        let (mut delivered, mut failed) = (0, 0);
+
+        // Collected first, since mapping the stream with a closure upsets the `Send` check for
+        // handlers that call this. Futures are lazy, so this doesn't start any sends.
+        let batches: Vec<_> = emails
+            .chunks(self.batch_size)
+            .map(|batch| self.send_batch(batch))
+            .collect();
+        let mut batches = futures::stream::iter(batches).buffered(self.max_concurrency);
+        while let Some(batch) = batches.next().await {
+            delivered += batch.iter().filter(|o| **o == Outcome::Delivered).count();
+            failed += batch.iter().filter(|o| **o == Outcome::Failed).count();
+            outcomes.extend(batch);
+
+            span.record("delivered", &delivered);
+            span.record("failed", &failed);
+            let per_second = outcomes.len() as f64 / started.elapsed().as_secs_f64();
+            info!(
+                processed = outcomes.len(),
+                per_second = %format!("{:.1}", per_second),
+                "bulk send progress"
+            );
+        }
+
+        outcomes
+    }
+
+    async fn send_batch(&self, batch: &[Email<'_>]) -> Vec<Outcome> {
+        let mut retries = 0;
+        loop {
+            self.limiter.acquire(batch.len()).await;
+            match self.email_client.send_batch(batch).await {
+                Ok(results) => {
+                    return results
+                        .into_iter()
+                        .map(|result| match result {
+                            Ok(()) => Outcome::Delivered,
+                            Err(email_client::Error::Suppressed) => Outcome::Suppressed,
+                            Err(error) => {
+                                warn!(?error, "failed to send email");
+                                Outcome::Failed
+                            }
+                        })
+                        .collect()
+                }
+                Err(email_client::Error::RateLimited { retry_after })
+                    if retries < MAX_RATE_LIMITED_RETRIES =>
+                {
+                    let delay = retry_after.unwrap_or(DEFAULT_RETRY_AFTER);
+                    warn!(
+                        ?delay,
+                        "rate limited by the email provider, pausing all sends"
+                    );
+                    self.limiter.pause(delay);
+                    retries += 1;
+                }
+                Err(error) => {
+                    warn!(?error, count = batch.len(), "failed to send batch");
+                    return vec![Outcome::Failed; batch.len()];
+                }
+            }
+        }
+    }
+}
+
+/// Spaces out messages to a maximum rate, shared between all the tasks using it.
+struct RateLimiter {
+    per_message: Duration,
+    state: Mutex<RateLimiterState>,
+}
+
+struct RateLimiterState {
+    /// When the next message may be sent.
+    next: Instant,
+    /// When the provider said we could continue, after rate limiting us.
+    paused_until: Instant,
+}
+
+impl RateLimiter {
+    fn new(max_rate: u32) -> Self {
+        let now = Instant::now();
+        Self {
+            per_message: Duration::from_secs(1) / max_rate,
+            state: Mutex::new(RateLimiterState {
+                next: now,
+                paused_until: now,
+            }),
+        }
+    }
+
+    /// Wait until `count` messages may be sent.
+    async fn acquire(&self, count: usize) {
+        let at = {
+            let mut state = self.state.lock().unwrap();
+            let at = cmp::max(state.next, Instant::now());
+            state.next = at + self.per_message * count as u32;
+            at
+        };
+        tokio::time::sleep_until(at).await;
+
+        // Wait out any pause that started while we were waiting for our turn
+        loop {
+            let paused_until = self.state.lock().unwrap().paused_until;
+            if paused_until <= Instant::now() {
+                return;
+            }
+            tokio::time::sleep_until(paused_until).await;
+        }
+    }
+
+    /// Stop all sends for `delay`.
+    fn pause(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        let mut state = self.state.lock().unwrap();
+        state.paused_until = cmp::max(state.paused_until, until);
+        state.next = cmp::max(state.next, until);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::RateLimiter;
+
+    #[tokio::test]
+    async fn messages_are_spaced_out_to_the_maximum_rate() {
+        let limiter = RateLimiter::new(100);
+        let started = Instant::now();
+
+        limiter.acquire(10).await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        // The first 10 messages take 100ms at 100 per second
+        limiter.acquire(10).await;
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn pauses_delay_every_later_message() {
+        let limiter = RateLimiter::new(1000);
+        let started = Instant::now();
+
+        limiter.pause(Duration::from_millis(100));
+        limiter.acquire(1).await;
+
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+}
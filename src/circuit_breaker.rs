@@ -0,0 +1,271 @@
+//! A circuit breaker, to fail fast rather than wait on a dependency that's down.
+//!
+//! The breaker tracks the outcome of the last `window` calls, and opens once the proportion that
+//! failed reaches `failure_rate`. While open, calls fail immediately. After `open_duration`, a
+//! single trial call is allowed through: if it succeeds the breaker closes, otherwise it opens
+//! again.
+
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+pub(crate) struct CircuitBreaker {
+    name: &'static str,
+    failure_rate: f64,
+    window: usize,
+    open_duration: Duration,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    state: State,
+    /// Whether each of the last `window` calls failed, while closed.
+    failures: VecDeque<bool>,
+}
+
+enum State {
+    Closed,
+    Open { until: Instant },
+    HalfOpen { trial_in_flight: bool },
+}
+
+impl CircuitBreaker {
+    /// Open after `failure_rate` (between 0 and 1) of the last `window` calls failed, for
+    /// `open_duration`.
+    pub(crate) fn new(
+        name: &'static str,
+        failure_rate: f64,
+        window: usize,
+        open_duration: Duration,
+    ) -> Self {
+        Self {
+            name,
+            failure_rate,
+            window,
+            open_duration,
+            inner: Mutex::new(Inner {
+                state: State::Closed,
+                failures: VecDeque::with_capacity(window),
+            }),
+        }
+    }
+
+    pub(crate) fn state(&self) -> CircuitState {
+        match self.inner.lock().unwrap().state {
+            State::Closed => CircuitState::Closed,
+            State::Open { until } if until > Instant::now() => CircuitState::Open,
+            State::Open { .. } | State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Get permission to make a call, or `None` if calls should fail fast.
+    ///
+    /// The outcome of the call must be reported through the returned permit.
+    pub(crate) fn acquire(&self) -> Option<Permit<'_>> {
+        let mut inner = self.inner.lock().unwrap();
+        let trial = match inner.state {
+            State::Closed => false,
+            State::Open { until } if until > Instant::now() => return None,
+            State::Open { .. } => {
+                info!(
+                    circuit = self.name,
+                    state = "half_open",
+                    "circuit breaker half-open"
+                );
+                true
+            }
+            State::HalfOpen { trial_in_flight } if trial_in_flight => return None,
+            State::HalfOpen { .. } => true,
+        };
+        if trial {
+            inner.state = State::HalfOpen {
+                trial_in_flight: true,
+            };
+        }
+
+        Some(Permit {
+            breaker: self,
+            trial,
+            reported: false,
+        })
+    }
+
+    fn record(&self, trial: bool, failed: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        if trial {
+            if failed {
+                self.open(&mut inner, "trial call failed");
+            } else {
+                info!(
+                    circuit = self.name,
+                    state = "closed",
+                    "circuit breaker closed"
+                );
+                inner.state = State::Closed;
+                inner.failures.clear();
+            }
+            return;
+        }
+
+        // Calls that started before the breaker opened don't count towards the next window
+        if !matches!(inner.state, State::Closed) {
+            return;
+        }
+        if inner.failures.len() == self.window {
+            inner.failures.pop_front();
+        }
+        inner.failures.push_back(failed);
+
+        let failures = inner.failures.iter().filter(|failed| **failed).count();
+        if inner.failures.len() == self.window
+            && failures as f64 >= self.failure_rate * self.window as f64
+        {
+            self.open(&mut inner, "failure rate exceeded");
+        }
+    }
+
+    fn open(&self, inner: &mut Inner, reason: &str) {
+        warn!(
+            circuit = self.name,
+            state = "open",
+            reason,
+            open_for = ?self.open_duration,
+            "circuit breaker opened"
+        );
+        inner.state = State::Open {
+            until: Instant::now() + self.open_duration,
+        };
+        inner.failures.clear();
+    }
+
+    /// Allow another trial call, if one was abandoned without reporting its outcome.
+    fn release_trial(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if let State::HalfOpen { .. } = inner.state {
+            inner.state = State::HalfOpen {
+                trial_in_flight: false,
+            };
+        }
+    }
+}
+
+/// Permission to make a single call.
+pub(crate) struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    trial: bool,
+    reported: bool,
+}
+
+impl Permit<'_> {
+    pub(crate) fn success(mut self) {
+        self.reported = true;
+        self.breaker.record(self.trial, false);
+    }
+
+    pub(crate) fn failure(mut self) {
+        self.reported = true;
+        self.breaker.record(self.trial, true);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.trial && !self.reported {
+            self.breaker.release_trial();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CircuitBreaker, CircuitState};
+
+    fn breaker(open_duration: Duration) -> CircuitBreaker {
+        CircuitBreaker::new("test", 0.5, 4, open_duration)
+    }
+
+    #[test]
+    fn opens_once_the_failure_rate_is_reached() {
+        let breaker = breaker(Duration::from_secs(60));
+
+        breaker.acquire().unwrap().failure();
+        breaker.acquire().unwrap().success();
+        breaker.acquire().unwrap().failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        breaker.acquire().unwrap().success();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.acquire().is_none());
+    }
+
+    #[test]
+    fn stays_closed_below_the_failure_rate() {
+        let breaker = breaker(Duration::from_secs(60));
+
+        for _ in 0..3 {
+            breaker.acquire().unwrap().success();
+        }
+        for _ in 0..10 {
+            breaker.acquire().unwrap().failure();
+            breaker.acquire().unwrap().success();
+            breaker.acquire().unwrap().success();
+            breaker.acquire().unwrap().success();
+        }
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn allows_a_single_trial_call_once_the_open_duration_has_passed() {
+        let breaker = breaker(Duration::from_millis(10));
+        for _ in 0..4 {
+            breaker.acquire().unwrap().failure();
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        let trial = breaker.acquire().unwrap();
+        assert!(breaker.acquire().is_none());
+        trial.success();
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.acquire().is_some());
+    }
+
+    #[tokio::test]
+    async fn reopens_if_the_trial_call_fails() {
+        let breaker = breaker(Duration::from_millis(10));
+        for _ in 0..4 {
+            breaker.acquire().unwrap().failure();
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        breaker.acquire().unwrap().failure();
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn abandoned_trial_calls_allow_another_trial() {
+        let breaker = breaker(Duration::from_millis(10));
+        for _ in 0..4 {
+            breaker.acquire().unwrap().failure();
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        drop(breaker.acquire().unwrap());
+
+        assert!(breaker.acquire().is_some());
+    }
+}
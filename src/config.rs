@@ -1,19 +1,52 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
 use reqwest::Url;
 use sqlx::postgres::PgConnectOptions;
 
-use crate::domain::SubscriberEmail;
+use crate::{domain::SubscriberEmail, policy};
 
 pub struct Config {
     pub(crate) address: SocketAddr,
     pub(crate) base_url: Url,
     pub(crate) database_options: PgConnectOptions,
     pub(crate) ignore_missing_migrations: bool,
+    pub(crate) canonicalize_gmail_dots: bool,
+    pub(crate) subscription_allowed_domains: Vec<String>,
+    pub(crate) subscription_denied_domains: Vec<String>,
+    pub(crate) disposable_domains: String,
     pub(crate) email_base_url: Url,
     pub(crate) email_sender: SubscriberEmail,
     pub(crate) email_authorization_token: String,
     pub(crate) email_send_timeout: Duration,
+    pub(crate) email_retry_deadline: Duration,
+    pub(crate) email_max_concurrency: usize,
+    pub(crate) email_max_rate: u32,
+    pub(crate) email_circuit_failure_rate: u32,
+    pub(crate) email_circuit_window: usize,
+    pub(crate) email_circuit_open_duration: Duration,
+    pub(crate) admin_token: String,
+    pub(crate) postmark_webhook_username: String,
+    pub(crate) postmark_webhook_password: String,
+    pub(crate) signing_key: String,
+    pub(crate) publish_webhook_secret: String,
+    pub(crate) scheduler_interval: Duration,
+    pub(crate) idempotency_key_ttl: Duration,
+    pub(crate) idempotency_max_body_size: usize,
+    pub(crate) blog_feed_url: Option<Url>,
+    pub(crate) blog_feed_poll_interval: Duration,
+    pub(crate) blog_feed_auto_send: bool,
+}
+
+pub struct DatabaseConfig {
+    pub(crate) database_options: PgConnectOptions,
+    pub(crate) ignore_missing_migrations: bool,
+}
+
+impl DatabaseConfig {
+    /// Create a connection pool for the database, which connects on first use.
+    pub fn pool(&self) -> sqlx::PgPool {
+        crate::app::connect_lazy(self.database_options.clone())
+    }
 }
 
 impl Config {
@@ -45,6 +78,18 @@ pub struct ConfigBuilder {
     #[serde(default)]
     ignore_missing_migrations: Option<bool>,
 
+    #[serde(default)]
+    canonicalize_gmail_dots: Option<bool>,
+
+    #[serde(default)]
+    subscription_allowed_domains: Option<Vec<String>>,
+
+    #[serde(default)]
+    subscription_denied_domains: Option<Vec<String>>,
+
+    #[serde(default)]
+    disposable_domains_path: Option<PathBuf>,
+
     #[serde(default, deserialize_with = "parse_optional")]
     email_base_url: Option<Url>,
 
@@ -60,6 +105,77 @@ pub struct ConfigBuilder {
         deserialize_with = "parse_millis_optional"
     )]
     email_send_timeout: Option<Duration>,
+
+    #[serde(
+        default,
+        rename = "email_retry_deadline_ms",
+        deserialize_with = "parse_millis_optional"
+    )]
+    email_retry_deadline: Option<Duration>,
+
+    #[serde(default)]
+    email_max_concurrency: Option<usize>,
+
+    #[serde(default)]
+    email_max_rate: Option<u32>,
+
+    #[serde(default)]
+    email_circuit_failure_rate: Option<u32>,
+
+    #[serde(default)]
+    email_circuit_window: Option<usize>,
+
+    #[serde(
+        default,
+        rename = "email_circuit_open_duration_ms",
+        deserialize_with = "parse_millis_optional"
+    )]
+    email_circuit_open_duration: Option<Duration>,
+
+    #[serde(default)]
+    admin_token: Option<String>,
+
+    #[serde(default)]
+    postmark_webhook_username: Option<String>,
+
+    #[serde(default)]
+    postmark_webhook_password: Option<String>,
+
+    #[serde(default)]
+    signing_key: Option<String>,
+
+    #[serde(default)]
+    publish_webhook_secret: Option<String>,
+
+    #[serde(
+        default,
+        rename = "scheduler_interval_ms",
+        deserialize_with = "parse_millis_optional"
+    )]
+    scheduler_interval: Option<Duration>,
+
+    #[serde(
+        default,
+        rename = "idempotency_key_ttl_ms",
+        deserialize_with = "parse_millis_optional"
+    )]
+    idempotency_key_ttl: Option<Duration>,
+
+    #[serde(default)]
+    idempotency_max_body_size: Option<usize>,
+
+    #[serde(default, deserialize_with = "parse_optional")]
+    blog_feed_url: Option<Url>,
+
+    #[serde(
+        default,
+        rename = "blog_feed_poll_interval_ms",
+        deserialize_with = "parse_millis_optional"
+    )]
+    blog_feed_poll_interval: Option<Duration>,
+
+    #[serde(default)]
+    blog_feed_auto_send: Option<bool>,
 }
 
 impl ConfigBuilder {
@@ -69,16 +185,51 @@ impl ConfigBuilder {
             base_url: None,
             database_options: None,
             ignore_missing_migrations: None,
+            canonicalize_gmail_dots: None,
+            subscription_allowed_domains: None,
+            subscription_denied_domains: None,
+            disposable_domains_path: None,
             email_base_url: None,
             email_sender: None,
             email_authorization_token: None,
             email_send_timeout: None,
+            email_retry_deadline: None,
+            email_max_concurrency: None,
+            email_max_rate: None,
+            email_circuit_failure_rate: None,
+            email_circuit_window: None,
+            email_circuit_open_duration: None,
+            admin_token: None,
+            postmark_webhook_username: None,
+            postmark_webhook_password: None,
+            signing_key: None,
+            publish_webhook_secret: None,
+            scheduler_interval: None,
+            idempotency_key_ttl: None,
+            idempotency_max_body_size: None,
+            blog_feed_url: None,
+            blog_feed_poll_interval: None,
+            blog_feed_auto_send: None,
         }
     }
 
     fn default() -> Self {
         Self {
             ignore_missing_migrations: Some(false),
+            canonicalize_gmail_dots: Some(false),
+            subscription_allowed_domains: Some(Vec::new()),
+            subscription_denied_domains: Some(Vec::new()),
+            email_retry_deadline: Some(Duration::from_secs(2)),
+            email_max_concurrency: Some(4),
+            email_max_rate: Some(50),
+            email_circuit_failure_rate: Some(50),
+            email_circuit_window: Some(20),
+            email_circuit_open_duration: Some(Duration::from_secs(30)),
+            scheduler_interval: Some(Duration::from_secs(30)),
+            idempotency_key_ttl: Some(Duration::from_secs(24 * 60 * 60)),
+            idempotency_max_body_size: Some(2 * 1024 * 1024),
+            blog_feed_poll_interval: Some(Duration::from_secs(15 * 60)),
+            blog_feed_auto_send: Some(false),
             ..Self::empty()
         }
     }
@@ -103,6 +254,33 @@ impl ConfigBuilder {
         self
     }
 
+    /// Treat Gmail addresses that differ only by dots as the same subscriber.
+    pub fn canonicalize_gmail_dots(mut self, canonicalize_gmail_dots: bool) -> Self {
+        self.canonicalize_gmail_dots = Some(canonicalize_gmail_dots);
+        self
+    }
+
+    /// Domains that may subscribe even if they're denied or disposable.
+    pub fn subscription_allowed_domains(
+        mut self,
+        subscription_allowed_domains: Vec<String>,
+    ) -> Self {
+        self.subscription_allowed_domains = Some(subscription_allowed_domains);
+        self
+    }
+
+    /// Domains that may not subscribe.
+    pub fn subscription_denied_domains(mut self, subscription_denied_domains: Vec<String>) -> Self {
+        self.subscription_denied_domains = Some(subscription_denied_domains);
+        self
+    }
+
+    /// A file of disposable domains to use instead of the bundled list.
+    pub fn disposable_domains_path(mut self, disposable_domains_path: PathBuf) -> Self {
+        self.disposable_domains_path = Some(disposable_domains_path);
+        self
+    }
+
     pub fn email_base_url(mut self, email_base_url: Url) -> Self {
         self.email_base_url = Some(email_base_url);
         self
@@ -123,58 +301,365 @@ impl ConfigBuilder {
         self
     }
 
+    /// How long to keep retrying an email that failed transiently, e.g. because the provider was
+    /// unreachable, before giving up.
+    ///
+    /// Request handlers wait for their emails while holding a transaction, so this should be short.
+    pub fn email_retry_deadline(mut self, email_retry_deadline: Duration) -> Self {
+        self.email_retry_deadline = Some(email_retry_deadline);
+        self
+    }
+
+    /// The most bulk email batches to have in flight at once.
+    pub fn email_max_concurrency(mut self, email_max_concurrency: usize) -> Self {
+        self.email_max_concurrency = Some(email_max_concurrency);
+        self
+    }
+
+    /// The most bulk emails to send per second.
+    pub fn email_max_rate(mut self, email_max_rate: u32) -> Self {
+        self.email_max_rate = Some(email_max_rate);
+        self
+    }
+
+    /// Stop sending email once this percentage of recent sends have failed.
+    pub fn email_circuit_failure_rate(mut self, email_circuit_failure_rate: u32) -> Self {
+        self.email_circuit_failure_rate = Some(email_circuit_failure_rate);
+        self
+    }
+
+    /// How many recent sends the failure rate is calculated over.
+    pub fn email_circuit_window(mut self, email_circuit_window: usize) -> Self {
+        self.email_circuit_window = Some(email_circuit_window);
+        self
+    }
+
+    /// How long to stop sending email for, before trying again.
+    pub fn email_circuit_open_duration(mut self, email_circuit_open_duration: Duration) -> Self {
+        self.email_circuit_open_duration = Some(email_circuit_open_duration);
+        self
+    }
+
+    pub fn admin_token(mut self, admin_token: String) -> Self {
+        self.admin_token = Some(admin_token);
+        self
+    }
+
+    pub fn postmark_webhook_username(mut self, postmark_webhook_username: String) -> Self {
+        self.postmark_webhook_username = Some(postmark_webhook_username);
+        self
+    }
+
+    pub fn postmark_webhook_password(mut self, postmark_webhook_password: String) -> Self {
+        self.postmark_webhook_password = Some(postmark_webhook_password);
+        self
+    }
+
+    pub fn signing_key(mut self, signing_key: String) -> Self {
+        self.signing_key = Some(signing_key);
+        self
+    }
+
+    /// The secret shared with the blog's CI, used to sign requests to the publish webhook.
+    pub fn publish_webhook_secret(mut self, publish_webhook_secret: String) -> Self {
+        self.publish_webhook_secret = Some(publish_webhook_secret);
+        self
+    }
+
+    /// How often to check for scheduled issues that are due to be sent.
+    pub fn scheduler_interval(mut self, scheduler_interval: Duration) -> Self {
+        self.scheduler_interval = Some(scheduler_interval);
+        self
+    }
+
+    /// How long responses are replayed for requests retried with the same `Idempotency-Key`.
+    pub fn idempotency_key_ttl(mut self, idempotency_key_ttl: Duration) -> Self {
+        self.idempotency_key_ttl = Some(idempotency_key_ttl);
+        self
+    }
+
+    /// The largest request body, in bytes, that's accepted with an `Idempotency-Key`, and the
+    /// largest response body that's stored to be replayed.
+    pub fn idempotency_max_body_size(mut self, idempotency_max_body_size: usize) -> Self {
+        self.idempotency_max_body_size = Some(idempotency_max_body_size);
+        self
+    }
+
+    /// The blog's RSS or Atom feed, to poll for new posts to turn into newsletter issues.
+    pub fn blog_feed_url(mut self, blog_feed_url: Url) -> Self {
+        self.blog_feed_url = Some(blog_feed_url);
+        self
+    }
+
+    pub fn blog_feed_poll_interval(mut self, blog_feed_poll_interval: Duration) -> Self {
+        self.blog_feed_poll_interval = Some(blog_feed_poll_interval);
+        self
+    }
+
+    /// Send issues for new blog posts immediately, rather than leaving them as drafts.
+    pub fn blog_feed_auto_send(mut self, blog_feed_auto_send: bool) -> Self {
+        self.blog_feed_auto_send = Some(blog_feed_auto_send);
+        self
+    }
+
     pub fn build(self) -> Result<Config, envy::Error> {
+        let config = self.merge()?;
+
+        Ok(Config {
+            address: config.address.ok_or(envy::Error::MissingValue("address"))?,
+            base_url: config
+                .base_url
+                .ok_or(envy::Error::MissingValue("base_url"))?,
+            database_options: config
+                .database_options
+                .ok_or(envy::Error::MissingValue("database_url"))?,
+            ignore_missing_migrations: config
+                .ignore_missing_migrations
+                .ok_or(envy::Error::MissingValue("ignore_missing_migrations"))?,
+            canonicalize_gmail_dots: config
+                .canonicalize_gmail_dots
+                .ok_or(envy::Error::MissingValue("canonicalize_gmail_dots"))?,
+            subscription_allowed_domains: config
+                .subscription_allowed_domains
+                .ok_or(envy::Error::MissingValue("subscription_allowed_domains"))?,
+            subscription_denied_domains: config
+                .subscription_denied_domains
+                .ok_or(envy::Error::MissingValue("subscription_denied_domains"))?,
+            disposable_domains: match config.disposable_domains_path {
+                None => policy::BUNDLED_DISPOSABLE_DOMAINS.to_string(),
+                Some(path) => std::fs::read_to_string(&path).map_err(|error| {
+                    envy::Error::Custom(format!(
+                        "failed to read disposable domains from {}: {}",
+                        path.display(),
+                        error
+                    ))
+                })?,
+            },
+            email_base_url: config
+                .email_base_url
+                .ok_or(envy::Error::MissingValue("email_base_url"))?,
+            email_sender: config
+                .email_sender
+                .ok_or(envy::Error::MissingValue("email_sender"))?,
+            email_authorization_token: config
+                .email_authorization_token
+                .ok_or(envy::Error::MissingValue("email_authorization_token"))?,
+            email_send_timeout: config
+                .email_send_timeout
+                .ok_or(envy::Error::MissingValue("email_send_timeout_ms"))?,
+            email_retry_deadline: config
+                .email_retry_deadline
+                .ok_or(envy::Error::MissingValue("email_retry_deadline_ms"))?,
+            email_max_concurrency: positive("email_max_concurrency", config.email_max_concurrency)?,
+            email_max_rate: positive("email_max_rate", config.email_max_rate)?,
+            email_circuit_failure_rate: match positive(
+                "email_circuit_failure_rate",
+                config.email_circuit_failure_rate,
+            )? {
+                rate if rate > 100 => {
+                    return Err(envy::Error::Custom(
+                        "email_circuit_failure_rate must be a percentage".to_string(),
+                    ))
+                }
+                rate => rate,
+            },
+            email_circuit_window: positive("email_circuit_window", config.email_circuit_window)?,
+            email_circuit_open_duration: config
+                .email_circuit_open_duration
+                .ok_or(envy::Error::MissingValue("email_circuit_open_duration_ms"))?,
+            admin_token: config
+                .admin_token
+                .ok_or(envy::Error::MissingValue("admin_token"))?,
+            postmark_webhook_username: config
+                .postmark_webhook_username
+                .ok_or(envy::Error::MissingValue("postmark_webhook_username"))?,
+            postmark_webhook_password: config
+                .postmark_webhook_password
+                .ok_or(envy::Error::MissingValue("postmark_webhook_password"))?,
+            signing_key: config
+                .signing_key
+                .ok_or(envy::Error::MissingValue("signing_key"))?,
+            publish_webhook_secret: config
+                .publish_webhook_secret
+                .ok_or(envy::Error::MissingValue("publish_webhook_secret"))?,
+            scheduler_interval: config
+                .scheduler_interval
+                .ok_or(envy::Error::MissingValue("scheduler_interval_ms"))?,
+            idempotency_key_ttl: config
+                .idempotency_key_ttl
+                .ok_or(envy::Error::MissingValue("idempotency_key_ttl_ms"))?,
+            idempotency_max_body_size: positive(
+                "idempotency_max_body_size",
+                config.idempotency_max_body_size,
+            )?,
+            blog_feed_url: config.blog_feed_url,
+            blog_feed_poll_interval: config
+                .blog_feed_poll_interval
+                .ok_or(envy::Error::MissingValue("blog_feed_poll_interval_ms"))?,
+            blog_feed_auto_send: config
+                .blog_feed_auto_send
+                .ok_or(envy::Error::MissingValue("blog_feed_auto_send"))?,
+        })
+    }
+
+    /// Build only the address the server listens on.
+    pub fn build_address(self) -> Result<SocketAddr, envy::Error> {
+        self.merge()?
+            .address
+            .ok_or(envy::Error::MissingValue("address"))
+    }
+
+    /// Build only the configuration needed to connect to and migrate the database.
+    pub fn build_database(self) -> Result<DatabaseConfig, envy::Error> {
+        let config = self.merge()?;
+
+        Ok(DatabaseConfig {
+            database_options: config
+                .database_options
+                .ok_or(envy::Error::MissingValue("database_url"))?,
+            ignore_missing_migrations: config
+                .ignore_missing_migrations
+                .ok_or(envy::Error::MissingValue("ignore_missing_migrations"))?,
+        })
+    }
+
+    fn merge(self) -> Result<Self, envy::Error> {
         // Get any overrides from the environment
         let overrides: Self = envy::from_env()?;
 
         // Get any default configuration
         let default = Self::default();
 
-        Ok(Config {
-            address: overrides
-                .address
-                .or(self.address)
-                .or(default.address)
-                .ok_or(envy::Error::MissingValue("address"))?,
-            base_url: overrides
-                .base_url
-                .or(self.base_url)
-                .or(default.base_url)
-                .ok_or(envy::Error::MissingValue("base_url"))?,
+        Ok(Self {
+            address: overrides.address.or(self.address).or(default.address),
+            base_url: overrides.base_url.or(self.base_url).or(default.base_url),
             database_options: overrides
                 .database_options
                 .or(self.database_options)
-                .or(default.database_options)
-                .ok_or(envy::Error::MissingValue("database_url"))?,
+                .or(default.database_options),
             ignore_missing_migrations: overrides
                 .ignore_missing_migrations
                 .or(self.ignore_missing_migrations)
-                .or(default.ignore_missing_migrations)
-                .ok_or(envy::Error::MissingValue("ignore_missing_migrations"))?,
+                .or(default.ignore_missing_migrations),
+            canonicalize_gmail_dots: overrides
+                .canonicalize_gmail_dots
+                .or(self.canonicalize_gmail_dots)
+                .or(default.canonicalize_gmail_dots),
+            subscription_allowed_domains: overrides
+                .subscription_allowed_domains
+                .or(self.subscription_allowed_domains)
+                .or(default.subscription_allowed_domains),
+            subscription_denied_domains: overrides
+                .subscription_denied_domains
+                .or(self.subscription_denied_domains)
+                .or(default.subscription_denied_domains),
+            disposable_domains_path: overrides
+                .disposable_domains_path
+                .or(self.disposable_domains_path)
+                .or(default.disposable_domains_path),
             email_base_url: overrides
                 .email_base_url
                 .or(self.email_base_url)
-                .or(default.email_base_url)
-                .ok_or(envy::Error::MissingValue("email_base_url"))?,
+                .or(default.email_base_url),
             email_sender: overrides
                 .email_sender
                 .or(self.email_sender)
-                .or(default.email_sender)
-                .ok_or(envy::Error::MissingValue("email_sender"))?,
+                .or(default.email_sender),
             email_authorization_token: overrides
                 .email_authorization_token
                 .or(self.email_authorization_token)
-                .or(default.email_authorization_token)
-                .ok_or(envy::Error::MissingValue("email_authorization_token"))?,
+                .or(default.email_authorization_token),
             email_send_timeout: overrides
                 .email_send_timeout
                 .or(self.email_send_timeout)
-                .or(default.email_send_timeout)
-                .ok_or(envy::Error::MissingValue("email_send_timeout_ms"))?,
+                .or(default.email_send_timeout),
+            email_retry_deadline: overrides
+                .email_retry_deadline
+                .or(self.email_retry_deadline)
+                .or(default.email_retry_deadline),
+            email_max_concurrency: overrides
+                .email_max_concurrency
+                .or(self.email_max_concurrency)
+                .or(default.email_max_concurrency),
+            email_max_rate: overrides
+                .email_max_rate
+                .or(self.email_max_rate)
+                .or(default.email_max_rate),
+            email_circuit_failure_rate: overrides
+                .email_circuit_failure_rate
+                .or(self.email_circuit_failure_rate)
+                .or(default.email_circuit_failure_rate),
+            email_circuit_window: overrides
+                .email_circuit_window
+                .or(self.email_circuit_window)
+                .or(default.email_circuit_window),
+            email_circuit_open_duration: overrides
+                .email_circuit_open_duration
+                .or(self.email_circuit_open_duration)
+                .or(default.email_circuit_open_duration),
+            admin_token: overrides
+                .admin_token
+                .or(self.admin_token)
+                .or(default.admin_token),
+            postmark_webhook_username: overrides
+                .postmark_webhook_username
+                .or(self.postmark_webhook_username)
+                .or(default.postmark_webhook_username),
+            postmark_webhook_password: overrides
+                .postmark_webhook_password
+                .or(self.postmark_webhook_password)
+                .or(default.postmark_webhook_password),
+            signing_key: overrides
+                .signing_key
+                .or(self.signing_key)
+                .or(default.signing_key),
+            publish_webhook_secret: overrides
+                .publish_webhook_secret
+                .or(self.publish_webhook_secret)
+                .or(default.publish_webhook_secret),
+            scheduler_interval: overrides
+                .scheduler_interval
+                .or(self.scheduler_interval)
+                .or(default.scheduler_interval),
+            idempotency_key_ttl: overrides
+                .idempotency_key_ttl
+                .or(self.idempotency_key_ttl)
+                .or(default.idempotency_key_ttl),
+            idempotency_max_body_size: overrides
+                .idempotency_max_body_size
+                .or(self.idempotency_max_body_size)
+                .or(default.idempotency_max_body_size),
+            blog_feed_url: overrides
+                .blog_feed_url
+                .or(self.blog_feed_url)
+                .or(default.blog_feed_url),
+            blog_feed_poll_interval: overrides
+                .blog_feed_poll_interval
+                .or(self.blog_feed_poll_interval)
+                .or(default.blog_feed_poll_interval),
+            blog_feed_auto_send: overrides
+                .blog_feed_auto_send
+                .or(self.blog_feed_auto_send)
+                .or(default.blog_feed_auto_send),
         })
     }
 }
 
+/// Require a value that's greater than zero.
+fn positive<T>(name: &'static str, value: Option<T>) -> Result<T, envy::Error>
+where
+    T: Default + PartialOrd,
+{
+    match value {
+        None => Err(envy::Error::MissingValue(name)),
+        Some(value) if value <= T::default() => Err(envy::Error::Custom(format!(
+            "{} must be greater than 0",
+            name
+        ))),
+        Some(value) => Ok(value),
+    }
+}
+
 fn parse_optional<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
 where
     D: serde::Deserializer<'de>,
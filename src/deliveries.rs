@@ -0,0 +1,137 @@
+//! A log of every email we've tried to send, and what the provider said about it.
+
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::error;
+use uuid::Uuid;
+
+/// What an email was sent for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EmailKind {
+    Confirmation,
+    Newsletter { issue_id: Uuid },
+    DataExport,
+    Erasure,
+}
+
+impl EmailKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Confirmation => "confirmation",
+            Self::Newsletter { .. } => "newsletter",
+            Self::DataExport => "data_export",
+            Self::Erasure => "erasure",
+        }
+    }
+
+    fn issue_id(self) -> Option<Uuid> {
+        match self {
+            Self::Newsletter { issue_id } => Some(issue_id),
+            _ => None,
+        }
+    }
+}
+
+/// The outcome of a single send.
+#[derive(Debug)]
+pub(crate) struct Delivery<'a> {
+    pub(crate) recipient: &'a str,
+    pub(crate) kind: EmailKind,
+    /// The provider's `MessageID`, if it accepted the email.
+    pub(crate) message_id: Option<String>,
+    /// The provider's HTTP status, if we got a response.
+    pub(crate) status: Option<u16>,
+    pub(crate) error: Option<String>,
+    pub(crate) requested_at: OffsetDateTime,
+    pub(crate) completed_at: OffsetDateTime,
+}
+
+/// Handle to the delivery log.
+///
+/// Deliveries are recorded on their own connection rather than in the caller's transaction, since
+/// the email has been sent (or not) regardless of whether the transaction commits.
+#[derive(Clone)]
+pub(crate) struct Deliveries {
+    pool: PgPool,
+}
+
+impl Deliveries {
+    pub(crate) fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Record a delivery, logging rather than failing if that isn't possible.
+    pub(crate) async fn record(&self, delivery: &Delivery<'_>) {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO email_deliveries (
+              id, recipient, kind, issue_id, message_id, status, error, requested_at, completed_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            Uuid::new_v4(),
+            delivery.recipient,
+            delivery.kind.as_str(),
+            delivery.kind.issue_id(),
+            delivery.message_id,
+            delivery.status.map(|status| status as i16),
+            delivery.error,
+            delivery.requested_at,
+            delivery.completed_at,
+        )
+        .execute(&self.pool)
+        .await;
+
+        if let Err(error) = result {
+            error!(%error, kind = delivery.kind.as_str(), "failed to record email delivery");
+        }
+    }
+
+    /// Record several deliveries with one query, logging rather than failing if that isn't
+    /// possible.
+    pub(crate) async fn record_all(&self, deliveries: &[Delivery<'_>]) {
+        if deliveries.is_empty() {
+            return;
+        }
+
+        let ids: Vec<_> = deliveries.iter().map(|_| Uuid::new_v4()).collect();
+        let recipients: Vec<_> = deliveries.iter().map(|d| d.recipient.to_string()).collect();
+        let kinds: Vec<_> = deliveries.iter().map(|d| d.kind.as_str()).collect();
+        let issue_ids: Vec<_> = deliveries.iter().map(|d| d.kind.issue_id()).collect();
+        let message_ids: Vec<_> = deliveries.iter().map(|d| d.message_id.clone()).collect();
+        let statuses: Vec<_> = deliveries
+            .iter()
+            .map(|d| d.status.map(|status| status as i16))
+            .collect();
+        let errors: Vec<_> = deliveries.iter().map(|d| d.error.clone()).collect();
+        let requested_ats: Vec<_> = deliveries.iter().map(|d| d.requested_at).collect();
+        let completed_ats: Vec<_> = deliveries.iter().map(|d| d.completed_at).collect();
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO email_deliveries (
+              id, recipient, kind, issue_id, message_id, status, error, requested_at, completed_at
+            )
+            SELECT * FROM UNNEST(
+              $1::uuid[], $2::text[], $3::text[], $4::uuid[], $5::text[], $6::int2[], $7::text[],
+              $8::timestamptz[], $9::timestamptz[]
+            )
+            "#,
+            &ids[..],
+            &recipients[..],
+            &kinds as &[&str],
+            &issue_ids as &[Option<Uuid>],
+            &message_ids as &[Option<String>],
+            &statuses as &[Option<i16>],
+            &errors as &[Option<String>],
+            &requested_ats[..],
+            &completed_ats[..],
+        )
+        .execute(&self.pool)
+        .await;
+
+        if let Err(error) = result {
+            error!(%error, count = deliveries.len(), "failed to record email deliveries");
+        }
+    }
+}
@@ -6,7 +6,9 @@ mod subscriber_status;
 use std::fmt;
 
 pub(crate) use self::{
-    new_subscriber::NewSubscriber, subscriber_name::SubscriberName,
+    new_subscriber::NewSubscriber,
+    subscriber_email::{CanonicalEmail, EmailCanonicalizer},
+    subscriber_name::SubscriberName,
     subscriber_status::SubscriberStatus,
 };
 
@@ -11,14 +11,168 @@ impl SubscriberEmail {
         S: Into<Cow<'s, str>>,
     {
         let s = s.into();
-        if validator::validate_email(s.as_ref()) {
-            Ok(Self(s.into_owned()))
+        let trimmed = s.trim();
+        if validator::validate_email(trimmed) {
+            Ok(Self(trimmed.to_string()))
         } else {
             Err(Error(format!("{} is not a valid email", s)))
         }
     }
+
+    /// Suggest a correction if the domain looks like a typo of a popular email domain.
+    pub(crate) fn suggestion(&self) -> Option<Self> {
+        let (local, domain) = self.0.rsplit_once('@')?;
+        let domain = domain.to_lowercase();
+        if POPULAR_DOMAINS.contains(&domain.as_str()) {
+            return None;
+        }
+
+        let mut candidates = POPULAR_DOMAINS
+            .iter()
+            .map(|popular| (edit_distance(&domain, popular), popular))
+            .filter(|(distance, popular)| *distance <= max_suggestion_distance(&domain, popular));
+        let (mut best_distance, mut best) = candidates.next()?;
+        let mut ambiguous = false;
+        for (distance, popular) in candidates {
+            if distance < best_distance {
+                (best_distance, best, ambiguous) = (distance, popular, false);
+            } else if distance == best_distance {
+                ambiguous = true;
+            }
+        }
+
+        if ambiguous {
+            None
+        } else {
+            Some(Self(format!("{}@{}", local, best)))
+        }
+    }
+}
+
+/// Domains that are common enough that a near miss is probably a typo.
+///
+/// This includes some smaller providers whose domains are a typo away from a bigger one (e.g.
+/// `mail.com` and `gmail.com`), and regional variants of popular providers (e.g. `gmx.at` and
+/// `gmx.de`), so that they aren't "corrected".
+const POPULAR_DOMAINS: [&str; 36] = [
+    "aol.com",
+    "comcast.net",
+    "email.com",
+    "fastmail.com",
+    "gmail.com",
+    "gmx.at",
+    "gmx.ch",
+    "gmx.com",
+    "gmx.de",
+    "gmx.net",
+    "googlemail.com",
+    "hey.com",
+    "hotmail.co.uk",
+    "hotmail.com",
+    "hotmail.de",
+    "hotmail.fr",
+    "hotmail.it",
+    "icloud.com",
+    "live.com",
+    "mac.com",
+    "mail.com",
+    "me.com",
+    "msn.com",
+    "outlook.com",
+    "outlook.de",
+    "proton.me",
+    "protonmail.com",
+    "web.de",
+    "yahoo.ca",
+    "yahoo.co.uk",
+    "yahoo.com",
+    "yahoo.de",
+    "yahoo.fr",
+    "yahoo.it",
+    "ymail.com",
+    "zoho.com",
+];
+
+/// How many edits away from a popular domain a domain may be to get a suggestion.
+///
+/// Only a single edit is allowed when the top-level domains differ, since other countries' domains
+/// of the same provider (and unrelated providers) are often two edits away.
+fn max_suggestion_distance(domain: &str, popular: &str) -> usize {
+    let tld = |domain: &str| domain.rsplit('.').next().unwrap_or_default().to_string();
+    if tld(domain) == tld(popular) {
+        2
+    } else {
+        1
+    }
+}
+
+/// The number of insertions, deletions, substitutions and adjacent transpositions needed to turn
+/// `a` into `b` (the "optimal string alignment" distance).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<_>, Vec<_>) = (a.chars().collect(), b.chars().collect());
+
+    // Rows of the distance matrix for the previous two prefixes of `a`
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// The form of an address used to detect duplicates, with the original spelling discarded.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct CanonicalEmail(String);
+
+impl AsRef<str> for CanonicalEmail {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Rules for turning addresses into their [`CanonicalEmail`].
+///
+/// Changing the rules doesn't update canonical addresses that have already been stored.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct EmailCanonicalizer {
+    /// Ignore dots in the local part of Gmail addresses, which Gmail itself ignores.
+    pub(crate) gmail_dots: bool,
+}
+
+impl EmailCanonicalizer {
+    /// Lower-case the address and convert an internationalized domain to punycode.
+    ///
+    /// The local part is technically case-sensitive, but no mainstream provider treats it that way.
+    pub(crate) fn canonicalize(&self, email: &SubscriberEmail) -> CanonicalEmail {
+        // Parsed addresses always contain an `@`
+        let (local, domain) = email.0.rsplit_once('@').unwrap_or(("", &email.0));
+
+        let domain = idna::domain_to_ascii(domain).unwrap_or_else(|_| domain.to_lowercase());
+        let mut local = local.to_lowercase();
+        if self.gmail_dots && GMAIL_DOMAINS.contains(&domain.as_str()) {
+            local.retain(|c| c != '.');
+        }
+
+        CanonicalEmail(format!("{}@{}", local, domain))
+    }
 }
 
+const GMAIL_DOMAINS: [&str; 2] = ["gmail.com", "googlemail.com"];
+
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
         &self.0
@@ -38,7 +192,7 @@ mod tests {
     use claim::assert_err;
     use fake::{faker::internet::en::FreeEmail, Fake};
 
-    use super::SubscriberEmail;
+    use super::{edit_distance, EmailCanonicalizer, SubscriberEmail};
 
     #[derive(Clone, Debug)]
     struct ValidEmailFixture(pub String);
@@ -68,6 +222,109 @@ mod tests {
         assert_err!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let email = SubscriberEmail::parse(" ursula@example.com\n").unwrap();
+        assert_eq!(email.as_ref(), "ursula@example.com");
+    }
+
+    #[test]
+    fn canonical_emails_are_lowercase_and_keep_the_original_spelling() {
+        let email = SubscriberEmail::parse("Ursula@Example.com").unwrap();
+        let canonical = EmailCanonicalizer::default().canonicalize(&email);
+
+        assert_eq!(canonical.as_ref(), "ursula@example.com");
+        assert_eq!(email.as_ref(), "Ursula@Example.com");
+    }
+
+    #[test]
+    fn canonical_emails_have_punycode_domains() {
+        let email = SubscriberEmail::parse("ursula@Bücher.example").unwrap();
+        let canonical = EmailCanonicalizer::default().canonicalize(&email);
+
+        assert_eq!(canonical.as_ref(), "ursula@xn--bcher-kva.example");
+    }
+
+    #[test]
+    fn gmail_dots_are_only_ignored_when_enabled() {
+        let email = SubscriberEmail::parse("Ursula.Le.Guin@gmail.com").unwrap();
+        let other = SubscriberEmail::parse("ursula.le.guin@example.com").unwrap();
+        let canonicalizer = EmailCanonicalizer { gmail_dots: true };
+
+        assert_eq!(
+            EmailCanonicalizer::default().canonicalize(&email).as_ref(),
+            "ursula.le.guin@gmail.com"
+        );
+        assert_eq!(
+            canonicalizer.canonicalize(&email).as_ref(),
+            "ursulaleguin@gmail.com"
+        );
+        assert_eq!(
+            canonicalizer.canonicalize(&other).as_ref(),
+            "ursula.le.guin@example.com"
+        );
+    }
+
+    #[test]
+    fn common_domain_typos_get_suggestions() {
+        for (typo, suggestion) in [
+            ("ursula@gmial.com", "ursula@gmail.com"),
+            ("ursula@hotmial.com", "ursula@hotmail.com"),
+            ("ursula@gmail.con", "ursula@gmail.com"),
+            ("Ursula@YAHO.com", "Ursula@yahoo.com"),
+            ("ursula@outlok.com", "ursula@outlook.com"),
+            ("ursula@gmail.co", "ursula@gmail.com"),
+            ("ursula@gmxx.net", "ursula@gmx.net"),
+        ] {
+            let email = SubscriberEmail::parse(typo).unwrap();
+            assert_eq!(
+                email.suggestion().as_ref().map(AsRef::as_ref),
+                Some(suggestion),
+                "wrong suggestion for {}",
+                typo
+            );
+        }
+    }
+
+    #[test]
+    fn popular_and_unrelated_domains_get_no_suggestion() {
+        for email in [
+            "ursula@gmail.com",
+            "ursula@GMAIL.com",
+            "ursula@mail.com",
+            "ursula@example.com",
+            "ursula@le-guin.org",
+            "ursula@gmx.net",
+            "ursula@gmx.at",
+            "ursula@yahoo.ca",
+        ] {
+            let email = SubscriberEmail::parse(email).unwrap();
+            assert!(email.suggestion().is_none(), "{:?} got a suggestion", email);
+        }
+    }
+
+    #[test]
+    fn unknown_regional_domains_are_not_corrected_to_another_country() {
+        for email in [
+            "ursula@gmx.es",
+            "ursula@yahoo.es",
+            "ursula@yahoo.com.au",
+            "ursula@hotmail.es",
+        ] {
+            let email = SubscriberEmail::parse(email).unwrap();
+            assert!(email.suggestion().is_none(), "{:?} got a suggestion", email);
+        }
+    }
+
+    #[test]
+    fn edit_distance_counts_transpositions_as_one_edit() {
+        assert_eq!(edit_distance("gmail.com", "gmail.com"), 0);
+        assert_eq!(edit_distance("gmial.com", "gmail.com"), 1);
+        assert_eq!(edit_distance("gmai.com", "gmail.com"), 1);
+        assert_eq!(edit_distance("gnail.cim", "gmail.com"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
     #[quickcheck_macros::quickcheck]
     fn valid_emails_are_parsed_successfully(email: ValidEmailFixture) -> bool {
         SubscriberEmail::parse(email.0).is_ok()
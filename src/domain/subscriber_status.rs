@@ -1,7 +1,12 @@
-#[derive(Clone, Copy)]
+use super::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum SubscriberStatus {
     Pending,
     Confirmed,
+    Bounced,
+    Complained,
+    Unsubscribed,
 }
 
 impl SubscriberStatus {
@@ -9,6 +14,32 @@ impl SubscriberStatus {
         match self {
             Self::Pending => "pending",
             Self::Confirmed => "confirmed",
+            Self::Bounced => "bounced",
+            Self::Complained => "complained",
+            Self::Unsubscribed => "unsubscribed",
+        }
+    }
+
+    /// Check that a subscriber may move from this status to `to`.
+    ///
+    /// Bounces and complaints can happen at any time, but otherwise subscribers only move forward
+    /// from pending, to confirmed, to unsubscribed.
+    pub(crate) fn transition(self, to: Self) -> Result<Self, Error> {
+        use SubscriberStatus::*;
+
+        match (self, to) {
+            (_, Bounced | Complained)
+            | (Pending, Confirmed | Unsubscribed)
+            | (Confirmed, Unsubscribed)
+                if self != to =>
+            {
+                Ok(to)
+            }
+            _ => Err(Error(format!(
+                "subscriber status cannot change from {} to {}",
+                self.as_str(),
+                to.as_str()
+            ))),
         }
     }
 }
@@ -20,6 +51,9 @@ impl std::str::FromStr for SubscriberStatus {
         match s {
             "pending" => Ok(Self::Pending),
             "confirmed" => Ok(Self::Confirmed),
+            "bounced" => Ok(Self::Bounced),
+            "complained" => Ok(Self::Complained),
+            "unsubscribed" => Ok(Self::Unsubscribed),
             _ => Err(crate::Error::Internal(eyre::Report::msg(format!(
                 "unknown subscriber status: {}",
                 s
@@ -27,3 +61,50 @@ impl std::str::FromStr for SubscriberStatus {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok_eq};
+
+    use super::SubscriberStatus::{self, *};
+
+    const ALL: [SubscriberStatus; 5] = [Pending, Confirmed, Bounced, Complained, Unsubscribed];
+
+    #[test]
+    fn subscribers_move_forward_through_confirmation() {
+        assert_ok_eq!(Pending.transition(Confirmed), Confirmed);
+        assert_ok_eq!(Pending.transition(Unsubscribed), Unsubscribed);
+        assert_ok_eq!(Confirmed.transition(Unsubscribed), Unsubscribed);
+
+        assert_err!(Confirmed.transition(Pending));
+        assert_err!(Unsubscribed.transition(Confirmed));
+        assert_err!(Unsubscribed.transition(Pending));
+    }
+
+    #[test]
+    fn any_subscriber_can_bounce_or_complain() {
+        for from in ALL {
+            for to in [Bounced, Complained] {
+                if from != to {
+                    assert_ok_eq!(from.transition(to), to);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bounced_and_complained_subscribers_cannot_be_reinstated() {
+        for from in [Bounced, Complained] {
+            for to in [Pending, Confirmed, Unsubscribed] {
+                assert_err!(from.transition(to));
+            }
+        }
+    }
+
+    #[test]
+    fn transitions_to_the_same_status_are_rejected() {
+        for status in ALL {
+            assert_err!(status.transition(status));
+        }
+    }
+}
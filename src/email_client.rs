@@ -1,14 +1,32 @@
-#![allow(dead_code)]
-
-use std::{fmt, sync::Arc, time::Duration};
+use std::{cmp, collections::BTreeMap, fmt, future::Future, sync::Arc, time::Duration};
 
 use reqwest::Url;
+use time::OffsetDateTime;
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::{
+    circuit_breaker::{CircuitBreaker, CircuitState},
+    deliveries::{Deliveries, Delivery, EmailKind},
+    domain::SubscriberEmail,
+    suppressions::Suppressions,
+};
+
+/// The most emails the provider accepts in a single batch.
+pub(crate) const MAX_BATCH_SIZE: usize = 500;
 
-use crate::domain::SubscriberEmail;
+/// How long to wait before the first retry of a transient failure, which doubles each retry.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+
+/// The longest to wait between retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(2);
 
 #[derive(Clone)]
 pub struct EmailClient {
     inner: Arc<EmailClientInner>,
+    suppressions: Option<Suppressions>,
+    deliveries: Option<Deliveries>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
 }
 
 struct EmailClientInner {
@@ -16,6 +34,8 @@ struct EmailClientInner {
     base_url: Url,
     sender: SubscriberEmail,
     authorization_token: String,
+    timeout: Duration,
+    retry_deadline: Duration,
 }
 
 impl EmailClient {
@@ -24,6 +44,7 @@ impl EmailClient {
         sender: SubscriberEmail,
         authorization_token: String,
         timeout: Duration,
+        retry_deadline: Duration,
     ) -> Self {
         Self {
             inner: Arc::new(EmailClientInner {
@@ -31,38 +52,510 @@ impl EmailClient {
                 base_url,
                 sender,
                 authorization_token,
+                timeout,
+                retry_deadline,
             }),
+            suppressions: None,
+            deliveries: None,
+            circuit_breaker: None,
+        }
+    }
+
+    /// Check recipients against the given suppression list before sending.
+    pub(crate) fn with_suppressions(mut self, suppressions: Suppressions) -> Self {
+        self.suppressions = Some(suppressions);
+        self
+    }
+
+    /// Record every send in the given delivery log.
+    pub(crate) fn with_deliveries(mut self, deliveries: Deliveries) -> Self {
+        self.deliveries = Some(deliveries);
+        self
+    }
+
+    /// Fail fast when the provider appears to be down, according to the given breaker.
+    pub(crate) fn with_circuit_breaker(mut self, circuit_breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(Arc::new(circuit_breaker));
+        self
+    }
+
+    /// The state of the circuit breaker, if there is one.
+    pub(crate) fn circuit_state(&self) -> Option<CircuitState> {
+        self.circuit_breaker
+            .as_ref()
+            .map(|circuit_breaker| circuit_breaker.state())
+    }
+
+    /// Send an email, retrying transient failures until the client's retry deadline.
+    ///
+    /// The first attempt always gets the full send timeout, but retries are cut short at the
+    /// deadline, so callers wait at most the longer of the two. Permanent failures, such as the
+    /// provider rejecting the recipient, are returned immediately.
+    pub(crate) async fn send_email(&self, email: &Email<'_>) -> Result<(), Error> {
+        let requested_at = OffsetDateTime::now_utc();
+        let mut response = ProviderResponse::default();
+        let result = self.try_send_email(email, &mut response).await;
+
+        if let Some(deliveries) = &self.deliveries {
+            deliveries
+                .record(&self.delivery(email, requested_at, &response, result.as_ref()))
+                .await;
         }
+        result
     }
 
-    pub(crate) async fn send_email(
+    /// Send up to [`MAX_BATCH_SIZE`] emails in a single request, returning the outcome of each in
+    /// the same order.
+    ///
+    /// Suppressed recipients are skipped. An error is only returned if the whole batch failed, in
+    /// which case nothing was sent.
+    pub(crate) async fn send_batch(
         &self,
-        recipient: SubscriberEmail,
-        subject: &str,
-        html_content: &str,
-        text_content: &str,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<Result<(), Error>>, Error> {
+        assert!(
+            emails.len() <= MAX_BATCH_SIZE,
+            "batches are limited to {} emails",
+            MAX_BATCH_SIZE
+        );
+        let requested_at = OffsetDateTime::now_utc();
+        // Every delivery is recorded at once, when the batch is done
+        let mut deliveries = Vec::with_capacity(emails.len());
+
+        let suppressed = match self.batch_suppressions(emails).await {
+            Ok(suppressed) => suppressed,
+            Err(error) => {
+                let response = ProviderResponse::default();
+                for email in emails {
+                    deliveries.push(self.delivery(email, requested_at, &response, Err(&error)));
+                }
+                self.record_all(&deliveries).await;
+                return Err(error);
+            }
+        };
+
+        // Outcomes are filled in for suppressed recipients first, then from the provider
+        let mut results = Vec::with_capacity(emails.len());
+        let mut requests = Vec::with_capacity(emails.len());
+        for (email, suppressed) in emails.iter().zip(suppressed) {
+            if suppressed {
+                let error = Error::Suppressed;
+                let response = ProviderResponse::default();
+                deliveries.push(self.delivery(email, requested_at, &response, Err(&error)));
+                results.push(Some(Err(error)));
+            } else {
+                requests.push(self.request(email));
+                results.push(None);
+            }
+        }
+
+        if !requests.is_empty() {
+            let mut status = None;
+            let responses = match self.guarded(self.post_batch(&requests, &mut status)).await {
+                Ok(responses) => responses,
+                Err(error) => {
+                    let response = ProviderResponse {
+                        status,
+                        ..ProviderResponse::default()
+                    };
+                    for (email, _) in emails.iter().zip(&results).filter(|(_, r)| r.is_none()) {
+                        deliveries.push(self.delivery(email, requested_at, &response, Err(&error)));
+                    }
+                    self.record_all(&deliveries).await;
+                    return Err(error);
+                }
+            };
+
+            // Results should match the requests one-to-one, but if any are missing we can't tell
+            // whether they were sent, so assume they were rather than risk sending them twice
+            let mut responses = responses.into_iter();
+            for (email, result) in emails.iter().zip(&mut results) {
+                if result.is_some() {
+                    continue;
+                }
+                let response = ProviderResponse::from_body(status, responses.next());
+                let outcome = match response.error_code {
+                    Some(code) if code != 0 => Err(Error::Rejected {
+                        code: Some(code.into()),
+                        message: response.message.clone(),
+                    }),
+                    _ => Ok(()),
+                };
+                deliveries.push(self.delivery(email, requested_at, &response, outcome.as_ref()));
+                *result = Some(outcome);
+            }
+        }
+
+        self.record_all(&deliveries).await;
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    /// Send an email, retrying transient failures, and filling in whatever we learn of the
+    /// provider's `response` to the last attempt.
+    async fn try_send_email(
+        &self,
+        email: &Email<'_>,
+        response: &mut ProviderResponse,
+    ) -> Result<(), Error> {
+        self.check_suppressions(&email.recipient).await?;
+
+        let deadline = Instant::now() + self.inner.retry_deadline;
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+        let mut timeout = self.inner.timeout;
+        loop {
+            let error = match self
+                .guarded(self.post_email(email, timeout, response))
+                .await
+            {
+                Err(error) if error.is_transient() => error,
+                result => return result,
+            };
+
+            let delay = match error {
+                Error::RateLimited {
+                    retry_after: Some(retry_after),
+                } => retry_after,
+                _ => jittered(backoff),
+            };
+            if Instant::now() + delay >= deadline {
+                return Err(error);
+            }
+            warn!(
+                ?error,
+                attempt,
+                ?delay,
+                "retrying email after transient failure"
+            );
+            tokio::time::sleep(delay).await;
+            backoff = cmp::min(backoff * 2, MAX_BACKOFF);
+            attempt += 1;
+            timeout = cmp::min(
+                self.inner.timeout,
+                deadline.saturating_duration_since(Instant::now()),
+            );
+        }
+    }
+
+    async fn post_email(
+        &self,
+        email: &Email<'_>,
+        timeout: Duration,
+        response: &mut ProviderResponse,
     ) -> Result<(), Error> {
         let inner = &self.inner;
 
         let url = inner.base_url.join("/email").unwrap();
-        let body = SendEmailRequest {
-            from: inner.sender.as_ref(),
-            to: recipient.as_ref(),
-            subject,
-            text_body: text_content,
-            html_body: html_content,
-        };
-        inner
+        let body = self.request(email);
+        let http_response = inner
             .http_client
             .post(url)
             .header("X-Postmark-Server-Token", &inner.authorization_token)
             .json(&body)
+            .timeout(timeout)
             .send()
-            .await?
-            .error_for_status()?;
+            .await?;
+
+        let status = http_response.status();
+        *response = ProviderResponse {
+            status: Some(status.as_u16()),
+            ..ProviderResponse::default()
+        };
+        if let Some(error) = rate_limited(&http_response) {
+            return Err(error);
+        }
+
+        // The body is informational, so a missing or malformed one isn't an error in itself
+        *response =
+            ProviderResponse::from_body(Some(status.as_u16()), http_response.json().await.ok());
 
+        if !status.is_success() {
+            return Err(status_error(status, response));
+        }
         Ok(())
     }
+
+    /// Post a batch, returning the provider's per-message results.
+    async fn post_batch(
+        &self,
+        requests: &[SendEmailRequest<'_>],
+        status: &mut Option<u16>,
+    ) -> Result<Vec<SendEmailResponse>, Error> {
+        let inner = &self.inner;
+
+        let url = inner.base_url.join("/email/batch").unwrap();
+        let http_response = inner
+            .http_client
+            .post(url)
+            .header("X-Postmark-Server-Token", &inner.authorization_token)
+            .json(requests)
+            .send()
+            .await?;
+
+        let http_status = http_response.status();
+        *status = Some(http_status.as_u16());
+        if let Some(error) = rate_limited(&http_response) {
+            return Err(error);
+        }
+        if !http_status.is_success() {
+            let response = ProviderResponse::from_body(*status, http_response.json().await.ok());
+            return Err(status_error(http_status, &response));
+        }
+
+        // The batch was accepted, so retrying it would send duplicates. Without results, every
+        // email is assumed to have been sent.
+        match http_response.json().await {
+            Ok(responses) => Ok(responses),
+            Err(error) => {
+                warn!(%error, "provider accepted batch with an unreadable response");
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Make a call to the provider through the circuit breaker, if there is one.
+    async fn guarded<T>(&self, call: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+        let permit = match &self.circuit_breaker {
+            None => None,
+            Some(circuit_breaker) => Some(circuit_breaker.acquire().ok_or(Error::CircuitOpen)?),
+        };
+
+        let result = call.await;
+        if let Some(permit) = permit {
+            match &result {
+                Err(error) if error.is_outage() => permit.failure(),
+                _ => permit.success(),
+            }
+        }
+        result
+    }
+
+    async fn check_suppressions(&self, recipient: &SubscriberEmail) -> Result<(), Error> {
+        if let Some(suppressions) = &self.suppressions {
+            if suppressions
+                .contains(recipient)
+                .await
+                .map_err(Error::Suppressions)?
+            {
+                return Err(Error::Suppressed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether each email's recipient is suppressed, in the same order.
+    async fn batch_suppressions(&self, emails: &[Email<'_>]) -> Result<Vec<bool>, Error> {
+        match &self.suppressions {
+            Some(suppressions) => {
+                let recipients: Vec<_> = emails.iter().map(|email| &email.recipient).collect();
+                suppressions
+                    .contains_each(&recipients)
+                    .await
+                    .map_err(Error::Suppressions)
+            }
+            None => Ok(vec![false; emails.len()]),
+        }
+    }
+
+    fn request<'a>(&'a self, email: &'a Email<'_>) -> SendEmailRequest<'a> {
+        SendEmailRequest {
+            from: self.inner.sender.as_ref(),
+            to: email.recipient.as_ref(),
+            reply_to: email.reply_to.as_ref().map(AsRef::as_ref),
+            subject: email.subject,
+            text_body: email.text_content,
+            html_body: email.html_content,
+            tag: email.tag.as_deref(),
+            headers: &email.headers,
+            metadata: &email.metadata,
+            attachments: &email.attachments,
+        }
+    }
+
+    async fn record_all(&self, deliveries: &[Delivery<'_>]) {
+        if let Some(log) = &self.deliveries {
+            log.record_all(deliveries).await;
+        }
+    }
+
+    /// The delivery log entry for an email, with whatever we learned of the provider's response.
+    fn delivery<'a>(
+        &self,
+        email: &'a Email<'_>,
+        requested_at: OffsetDateTime,
+        response: &ProviderResponse,
+        result: Result<&(), &Error>,
+    ) -> Delivery<'a> {
+        let error = result.err().map(|error| match error {
+            // Prefer the provider's explanation over our generic description
+            Error::Server {
+                message: Some(message),
+                ..
+            }
+            | Error::Rejected {
+                message: Some(message),
+                ..
+            } => message.clone(),
+            _ => error_chain(error),
+        });
+        Delivery {
+            recipient: email.recipient.as_ref(),
+            kind: email.kind,
+            message_id: response.message_id.clone(),
+            status: response.status,
+            error,
+            requested_at,
+            completed_at: OffsetDateTime::now_utc(),
+        }
+    }
+}
+
+/// An email to send, with any optional extras added through the builder methods.
+pub(crate) struct Email<'a> {
+    recipient: SubscriberEmail,
+    kind: EmailKind,
+    subject: &'a str,
+    html_content: &'a str,
+    text_content: &'a str,
+    reply_to: Option<SubscriberEmail>,
+    tag: Option<String>,
+    headers: Vec<Header>,
+    metadata: BTreeMap<String, String>,
+    attachments: Vec<Attachment<'a>>,
+}
+
+impl<'a> Email<'a> {
+    pub(crate) fn new(
+        recipient: SubscriberEmail,
+        kind: EmailKind,
+        subject: &'a str,
+        html_content: &'a str,
+        text_content: &'a str,
+    ) -> Self {
+        Self {
+            recipient,
+            kind,
+            subject,
+            html_content,
+            text_content,
+            reply_to: None,
+            tag: None,
+            headers: Vec::new(),
+            metadata: BTreeMap::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    /// Add a header, such as `List-Id`.
+    ///
+    /// Headers the provider sets itself, like `From` and `Subject`, should be set through their
+    /// own fields instead.
+    pub(crate) fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push(Header {
+            name: name.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// Direct replies somewhere other than the sender.
+    #[allow(dead_code)] // part of the message API, though nothing sets a reply address yet
+    pub(crate) fn reply_to(mut self, reply_to: SubscriberEmail) -> Self {
+        self.reply_to = Some(reply_to);
+        self
+    }
+
+    /// Categorise the email in the provider's statistics.
+    pub(crate) fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// Attach a value that the provider returns with events about the email, such as bounces.
+    pub(crate) fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Attach a file.
+    #[allow(dead_code)] // part of the message API, though nothing sends attachments yet
+    pub(crate) fn attachment(mut self, attachment: Attachment<'a>) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header {
+    name: String,
+    value: String,
+}
+
+/// A file attached to an email.
+///
+/// The content is borrowed, so the same file can be attached to a batch of emails without copying
+/// it for each.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct Attachment<'a> {
+    name: String,
+    #[serde(serialize_with = "serialize_base64")]
+    content: &'a [u8],
+    content_type: String,
+}
+
+impl<'a> Attachment<'a> {
+    /// Attach `content` as a file called `name`, with the given MIME type, e.g. `application/pdf`.
+    #[allow(dead_code)] // as for `Email::attachment`
+    pub(crate) fn new(
+        name: impl Into<String>,
+        content_type: impl Into<String>,
+        content: &'a [u8],
+    ) -> Self {
+        Self {
+            name: name.into(),
+            content,
+            content_type: content_type.into(),
+        }
+    }
+}
+
+fn serialize_base64<S: serde::Serializer>(
+    content: &&[u8],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&base64::encode(content))
+}
+
+/// What the provider told us about a send.
+#[derive(Default)]
+struct ProviderResponse {
+    status: Option<u16>,
+    message_id: Option<String>,
+    error_code: Option<i64>,
+    message: Option<String>,
+}
+
+impl ProviderResponse {
+    fn from_body(status: Option<u16>, body: Option<SendEmailResponse>) -> Self {
+        let body = body.unwrap_or_default();
+        Self {
+            status,
+            message_id: body.message_id,
+            error_code: body.error_code,
+            message: body.message,
+        }
+    }
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    error_code: Option<i64>,
+    message: Option<String>,
 }
 
 #[derive(serde::Serialize)]
@@ -70,41 +563,208 @@ impl EmailClient {
 struct SendEmailRequest<'a> {
     from: &'a str,
     to: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
     subject: &'a str,
     text_body: &'a str,
     html_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [Header],
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    attachments: &'a [Attachment<'a>],
 }
 
 #[derive(Debug)]
-pub(crate) struct Error(reqwest::Error);
+pub(crate) enum Error {
+    /// The recipient is on the suppression list, so nothing was sent.
+    Suppressed,
+    Suppressions(sqlx::Error),
+    /// The provider appears to be down, so nothing was sent.
+    CircuitOpen,
+    /// The provider didn't respond within the send timeout.
+    Timeout(reqwest::Error),
+    /// We couldn't connect to the provider.
+    Connect(reqwest::Error),
+    /// Any other failure to make the request or read the response.
+    Request(reqwest::Error),
+    /// The provider is rate limiting us, and asked us to wait for `retry_after` if given.
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// The provider failed to handle the request.
+    Server {
+        status: u16,
+        message: Option<String>,
+    },
+    /// The provider refused the email, either for the whole request or one email in a batch.
+    Rejected {
+        code: Option<ErrorCode>,
+        message: Option<String>,
+    },
+}
+
+impl Error {
+    /// Whether the same email might succeed if sent again.
+    pub(crate) fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout(_) | Self::Connect(_) | Self::RateLimited { .. } | Self::Server { .. }
+        )
+    }
+
+    /// Whether the error suggests the provider is down, rather than that it refused the email.
+    fn is_outage(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout(_) | Self::Connect(_) | Self::Server { .. }
+        )
+    }
+}
 
 impl From<reqwest::Error> for Error {
     fn from(error: reqwest::Error) -> Self {
-        Self(error)
+        if error.is_timeout() {
+            Self::Timeout(error)
+        } else if error.is_connect() {
+            Self::Connect(error)
+        } else {
+            Self::Request(error)
+        }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "an error occurred when sending an email")
+        match self {
+            Self::Suppressed => write!(f, "the recipient has opted out of all email"),
+            Self::Suppressions(_) => write!(f, "failed to check the suppression list"),
+            Self::CircuitOpen => write!(f, "the email provider is unavailable"),
+            Self::Timeout(_) => write!(f, "the email provider didn't respond in time"),
+            Self::Connect(_) => write!(f, "failed to connect to the email provider"),
+            Self::Request(_) => write!(f, "an error occurred when sending an email"),
+            Self::RateLimited { .. } => write!(f, "the email provider is rate limiting us"),
+            Self::Server { status, .. } => {
+                write!(f, "the email provider failed with status {}", status)
+            }
+            Self::Rejected { code, message } => {
+                write!(f, "the email was rejected")?;
+                if let Some(code) = code {
+                    write!(f, " ({:?})", code)?;
+                }
+                if let Some(message) = message {
+                    write!(f, ": {}", message)?;
+                }
+                Ok(())
+            }
+        }
     }
 }
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        Some(&self.0)
+        match self {
+            Self::Suppressions(error) => Some(error),
+            Self::Timeout(error) | Self::Connect(error) | Self::Request(error) => Some(error),
+            Self::Suppressed
+            | Self::CircuitOpen
+            | Self::RateLimited { .. }
+            | Self::Server { .. }
+            | Self::Rejected { .. } => None,
+        }
     }
 }
 
+/// Postmark's error codes, for those we might handle differently.
+///
+/// See <https://postmarkapp.com/developer/api/overview#error-codes>.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ErrorCode {
+    InvalidApiToken,
+    InvalidEmailRequest,
+    SenderSignatureNotFound,
+    SenderSignatureNotConfirmed,
+    InactiveRecipient,
+    Other(i64),
+}
+
+impl From<i64> for ErrorCode {
+    fn from(code: i64) -> Self {
+        match code {
+            10 => Self::InvalidApiToken,
+            300 => Self::InvalidEmailRequest,
+            400 => Self::SenderSignatureNotFound,
+            401 => Self::SenderSignatureNotConfirmed,
+            406 => Self::InactiveRecipient,
+            code => Self::Other(code),
+        }
+    }
+}
+
+/// Turn an unsuccessful status into an error, with the provider's explanation from the `response`.
+fn status_error(status: reqwest::StatusCode, response: &ProviderResponse) -> Error {
+    if status.is_client_error() {
+        Error::Rejected {
+            code: response.error_code.map(ErrorCode::from),
+            message: response.message.clone(),
+        }
+    } else {
+        Error::Server {
+            status: status.as_u16(),
+            message: response.message.clone(),
+        }
+    }
+}
+
+/// Pick a delay between half and all of `backoff`, so that clients retrying at the same time
+/// spread out.
+fn jittered(backoff: Duration) -> Duration {
+    backoff / 2 + backoff.mul_f64(rand::random::<f64>() / 2.0)
+}
+
+/// Check for a `429 Too Many Requests`, along with how long the provider wants us to wait.
+fn rate_limited(response: &reqwest::Response) -> Option<Error> {
+    if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+
+    // Only delays in seconds are supported, rather than dates, which Postmark doesn't use
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs);
+    Some(Error::RateLimited { retry_after })
+}
+
+/// Describe an error along with its causes, e.g. for the delivery log.
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut description = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        description.push_str(": ");
+        description.push_str(&error.to_string());
+        source = error.source();
+    }
+    description
+}
+
 impl From<Error> for crate::Error {
     fn from(error: Error) -> Self {
-        crate::Error::Internal(error.into())
+        match error {
+            Error::Suppressed => crate::Error::Validation(error.to_string()),
+            _ => crate::Error::Internal(error.into()),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
 
     use claim::{assert_err, assert_ok};
     use fake::{
@@ -119,9 +779,9 @@ mod tests {
         Mock, MockServer, ResponseTemplate,
     };
 
-    use crate::domain::SubscriberEmail;
+    use crate::{deliveries::EmailKind, domain::SubscriberEmail};
 
-    use super::EmailClient;
+    use super::{Attachment, Email, EmailClient, Error, ErrorCode};
 
     #[tokio::test]
     async fn send_email_sends_the_expected_request() {
@@ -139,8 +799,91 @@ mod tests {
             .await;
 
         let _ = email_client
-            .send_email(email(), &subject(), &content(), &content())
+            .send_email(&Email::new(
+                email(),
+                EmailKind::Confirmation,
+                &subject(),
+                &content(),
+                &content(),
+            ))
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_includes_headers_metadata_and_attachments() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
             .await;
+
+        let reply_to = email();
+        let (subject, content) = (subject(), content());
+        let email = Email::new(
+            email(),
+            EmailKind::Confirmation,
+            &subject,
+            &content,
+            &content,
+        )
+        .header("List-Id", "<newsletter.example.com>")
+        .reply_to(reply_to.clone())
+        .tag("welcome")
+        .metadata("subscriber_id", "42")
+        .attachment(Attachment::new("hello.txt", "text/plain", b"hello"));
+        assert_ok!(email_client.send_email(&email).await);
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([{ "Name": "List-Id", "Value": "<newsletter.example.com>" }])
+        );
+        assert_eq!(body["ReplyTo"], reply_to.as_ref());
+        assert_eq!(body["Tag"], "welcome");
+        assert_eq!(
+            body["Metadata"],
+            serde_json::json!({ "subscriber_id": "42" })
+        );
+        assert_eq!(
+            body["Attachments"],
+            serde_json::json!([{
+                "Name": "hello.txt",
+                "Content": "aGVsbG8=",
+                "ContentType": "text/plain",
+            }])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_omits_unset_optional_fields() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_email(&Email::new(
+                email(),
+                EmailKind::Confirmation,
+                &subject(),
+                &content(),
+                &content(),
+            ))
+            .await;
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        for field in ["ReplyTo", "Tag", "Headers", "Metadata", "Attachments"] {
+            assert!(body.get(field).is_none(), "{} should be omitted", field);
+        }
     }
 
     #[tokio::test]
@@ -155,7 +898,13 @@ mod tests {
             .await;
 
         let result = email_client
-            .send_email(email(), &subject(), &content(), &content())
+            .send_email(&Email::new(
+                email(),
+                EmailKind::Confirmation,
+                &subject(),
+                &content(),
+                &content(),
+            ))
             .await;
 
         assert_ok!(result);
@@ -173,10 +922,16 @@ mod tests {
             .await;
 
         let result = email_client
-            .send_email(email(), &subject(), &content(), &content())
+            .send_email(&Email::new(
+                email(),
+                EmailKind::Confirmation,
+                &subject(),
+                &content(),
+                &content(),
+            ))
             .await;
 
-        assert_err!(result);
+        assert!(matches!(result, Err(Error::Server { status: 500, .. })));
     }
 
     #[tokio::test]
@@ -191,12 +946,249 @@ mod tests {
             .await;
 
         let result = email_client
-            .send_email(email(), &subject(), &content(), &content())
+            .send_email(&Email::new(
+                email(),
+                EmailKind::Confirmation,
+                &subject(),
+                &content(),
+                &content(),
+            ))
+            .await;
+
+        assert!(matches!(result, Err(Error::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_to_connect_if_the_server_is_down() {
+        // Mock servers are pooled, so find a port that nothing is listening on
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let email_client = email_client(uri);
+
+        let result = email_client
+            .send_email(&Email::new(
+                email(),
+                EmailKind::Confirmation,
+                &subject(),
+                &content(),
+                &content(),
+            ))
+            .await;
+
+        assert!(matches!(result, Err(Error::Connect(_))));
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_transient_failures() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), Duration::from_secs(5));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_client
+            .send_email(&Email::new(
+                email(),
+                EmailKind::Confirmation,
+                &subject(),
+                &content(),
+                &content(),
+            ))
+            .await;
+
+        assert_ok!(result);
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_rejections() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), Duration::from_secs(5));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 406,
+                "Message": "Inactive recipient",
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_client
+            .send_email(&Email::new(
+                email(),
+                EmailKind::Confirmation,
+                &subject(),
+                &content(),
+                &content(),
+            ))
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::Rejected {
+                code: Some(ErrorCode::InactiveRecipient),
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_stops_retrying_at_the_deadline() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), Duration::from_millis(300));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(2..)
+            .mount(&mock_server)
+            .await;
+
+        let started = Instant::now();
+        let result = email_client
+            .send_email(&Email::new(
+                email(),
+                EmailKind::Confirmation,
+                &subject(),
+                &content(),
+                &content(),
+            ))
+            .await;
+
+        assert!(matches!(result, Err(Error::Server { status: 500, .. })));
+        assert!(started.elapsed() < Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn send_email_cuts_retries_short_at_the_deadline() {
+        let mock_server = MockServer::start().await;
+        let email_client = EmailClient::new(
+            mock_server.uri().parse().unwrap(),
+            email(),
+            Faker.fake(),
+            Duration::from_secs(10),
+            Duration::from_millis(300),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&mock_server)
+            .await;
+
+        let started = Instant::now();
+        let result = email_client
+            .send_email(&Email::new(
+                email(),
+                EmailKind::Confirmation,
+                &subject(),
+                &content(),
+                &content(),
+            ))
+            .await;
+
+        assert!(matches!(result, Err(Error::Timeout(_))));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_each_result() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "MessageID": "1", "ErrorCode": 0, "Message": "OK" },
+                { "ErrorCode": 406, "Message": "Inactive recipient" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (subject, content) = (subject(), content());
+        let emails = [
+            batch_email(&subject, &content),
+            batch_email(&subject, &content),
+        ];
+        let results = email_client.send_batch(&emails).await.unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_ok!(&results[0]);
+        assert!(matches!(
+            &results[1],
+            Err(Error::Rejected {
+                code: Some(ErrorCode::InactiveRecipient),
+                message: Some(message),
+            }) if message == "Inactive recipient"
+        ));
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body[1]["To"], emails[1].recipient.as_ref());
+    }
+
+    #[tokio::test]
+    async fn send_batch_treats_accepted_batches_with_unreadable_results_as_sent() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (subject, content) = (subject(), content());
+        let results = email_client
+            .send_batch(&[
+                batch_email(&subject, &content),
+                batch_email(&subject, &content),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(Result::is_ok));
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (subject, content) = (subject(), content());
+        let result = email_client
+            .send_batch(&[batch_email(&subject, &content)])
             .await;
 
         assert_err!(result);
     }
 
+    fn batch_email<'a>(subject: &'a str, content: &'a str) -> Email<'a> {
+        Email::new(email(), EmailKind::Confirmation, subject, content, content)
+    }
+
     fn subject() -> String {
         Sentence(1..2).fake()
     }
@@ -210,12 +1202,18 @@ mod tests {
         SubscriberEmail::parse(email).unwrap()
     }
 
+    /// A client that doesn't retry, so each test sees a single attempt.
     fn email_client(base_url: String) -> EmailClient {
+        retrying_email_client(base_url, Duration::ZERO)
+    }
+
+    fn retrying_email_client(base_url: String, retry_deadline: Duration) -> EmailClient {
         EmailClient::new(
             base_url.parse().unwrap(),
             email(),
             Faker.fake(),
             Duration::from_millis(200),
+            retry_deadline,
         )
     }
 
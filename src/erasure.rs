@@ -0,0 +1,188 @@
+//! Erasure of all personal data held about a subscriber.
+
+use sha2::{Digest as _, Sha256};
+use sqlx::{postgres::PgExecutor, PgConnection};
+use time::OffsetDateTime;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{domain::CanonicalEmail, signing::SigningKey, subscribers::Actor, Error};
+
+/// The purpose of [`SigningKey::hash`]es of erased emails.
+const PURPOSE: &str = "erasure";
+
+/// Delete everything we hold about a subscriber, returning `false` if they don't exist.
+///
+/// All that is kept is a keyed hash of their email, so they aren't re-imported, and a log entry that
+/// records the erasure. Suppressions are kept too, since they must outlive the subscriber. Stored
+/// idempotent responses that mention the subscriber are deleted, so they can no longer be replayed.
+///
+/// The caller is responsible for running this in a transaction.
+#[tracing::instrument(skip(conn, signing_key))]
+pub(crate) async fn erase(
+    conn: &mut PgConnection,
+    signing_key: &SigningKey,
+    subscriber_id: &Uuid,
+    requested_by: Actor,
+) -> Result<bool, Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"DELETE FROM consents WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"DELETE FROM email_events WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM email_deliveries
+        WHERE lower(recipient) = (SELECT lower(email) FROM subscriptions WHERE id = $1)
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // Responses are matched case-insensitively on the email as given, or the subscriber's ID, which
+    // covers the JSON and HTML that our routes return
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency_keys k
+        USING subscriptions s
+        WHERE s.id = $1
+          AND (
+            strpos(lower(encode(k.response_body, 'escape')), lower(s.email)) > 0
+            OR strpos(encode(k.response_body, 'escape'), s.id::text) > 0
+          )
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"DELETE FROM subscription_events WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // Subscribers whose email collided with another's when canonical emails were introduced have
+    // no canonical email, so fall back to the same approximation used for the backfill
+    let email = match sqlx::query!(
+        r#"
+        DELETE FROM subscriptions WHERE id = $1
+        RETURNING COALESCE(email_canonical, lower(trim(email))) AS "email!"
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    {
+        None => return Ok(false),
+        Some(row) => row.email,
+    };
+
+    let now = OffsetDateTime::now_utc();
+    sqlx::query!(
+        r#"
+        INSERT INTO erasure_tombstones (email_hash, erased_at)
+        VALUES ($1, $2)
+        ON CONFLICT (email_hash) DO UPDATE SET erased_at = EXCLUDED.erased_at
+        "#,
+        email_hash(signing_key, &email),
+        now,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO erasures (id, subscriber_id, requested_by, erased_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        requested_by.as_str(),
+        now,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    info!("erased subscriber");
+    Ok(true)
+}
+
+/// Whether a subscriber with an equivalent email has been erased.
+///
+/// Tombstones written before emails were hashed with the signing key hold a plain SHA-256 hash,
+/// which is checked too.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn is_erased<'c>(
+    executor: impl PgExecutor<'c>,
+    signing_key: &SigningKey,
+    email: &CanonicalEmail,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+          SELECT 1 FROM erasure_tombstones WHERE email_hash IN ($1, $2)
+        ) AS "exists!"
+        "#,
+        email_hash(signing_key, email.as_ref()),
+        legacy_email_hash(email.as_ref()),
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(row.exists)
+}
+
+fn email_hash(signing_key: &SigningKey, canonical_email: &str) -> String {
+    signing_key.hash(PURPOSE, canonical_email)
+}
+
+fn legacy_email_hash(canonical_email: &str) -> String {
+    format!("{:x}", Sha256::digest(canonical_email.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::signing::SigningKey;
+
+    use super::{email_hash, legacy_email_hash};
+
+    #[test]
+    fn email_hash_is_keyed() {
+        let key = SigningKey::new("secret");
+        let email = "ursula_le_guin@gmail.com";
+
+        assert_eq!(email_hash(&key, email), email_hash(&key, email));
+        assert_ne!(
+            email_hash(&key, email),
+            email_hash(&key, "octavia_butler@gmail.com")
+        );
+        assert_ne!(
+            email_hash(&key, email),
+            email_hash(&SigningKey::new("other"), email)
+        );
+        assert_ne!(email_hash(&key, email), legacy_email_hash(email));
+        assert_eq!(email_hash(&key, email).len(), 64);
+        assert!(email_hash(&key, email)
+            .chars()
+            .all(|c| c.is_ascii_hexdigit()));
+    }
+}
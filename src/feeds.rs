@@ -0,0 +1,298 @@
+//! RSS, Atom and JSON Feed renderings of published newsletter issues.
+
+use std::fmt::Write as _;
+
+use reqwest::Url;
+use time::{OffsetDateTime, UtcOffset};
+use uuid::Uuid;
+
+use crate::{html::Escaped, newsletters};
+
+const TITLE: &str = "Newsletter";
+
+pub(crate) struct FeedIssue {
+    pub(crate) id: Uuid,
+    pub(crate) slug: String,
+    pub(crate) title: String,
+    pub(crate) html_body: String,
+    pub(crate) published_at: OffsetDateTime,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    pub(crate) fn content_type(self) -> &'static str {
+        match self {
+            Self::Rss => "application/rss+xml; charset=utf-8",
+            Self::Atom => "application/atom+xml; charset=utf-8",
+            Self::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+
+    fn path(self) -> &'static str {
+        match self {
+            Self::Rss => "/feed.rss",
+            Self::Atom => "/feed.atom",
+            Self::Json => "/feed.json",
+        }
+    }
+
+    /// Render a feed of `issues`, which should be ordered newest first.
+    pub(crate) fn render(self, base_url: &Url, issues: &[FeedIssue]) -> String {
+        let feed = Feed { base_url, issues };
+        match self {
+            Self::Rss => feed.rss(),
+            Self::Atom => feed.atom(),
+            Self::Json => feed.json(),
+        }
+    }
+}
+
+struct Feed<'a> {
+    base_url: &'a Url,
+    issues: &'a [FeedIssue],
+}
+
+impl Feed<'_> {
+    fn home_url(&self) -> Url {
+        self.base_url.join("/newsletters").unwrap()
+    }
+
+    fn feed_url(&self, format: FeedFormat) -> Url {
+        self.base_url.join(format.path()).unwrap()
+    }
+
+    fn issue_url(&self, issue: &FeedIssue) -> Url {
+        newsletters::issue_url(self.base_url, &issue.slug)
+    }
+
+    fn updated_at(&self) -> Option<OffsetDateTime> {
+        self.issues.iter().map(|issue| issue.published_at).max()
+    }
+
+    fn rss(&self) -> String {
+        let mut xml = String::new();
+        writeln!(
+            xml,
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+<title>{}</title>
+<link>{}</link>
+<description>Every issue of the {}</description>
+<atom:link href="{}" rel="self" type="application/rss+xml"/>"#,
+            TITLE,
+            Escaped(self.home_url().as_str()),
+            TITLE.to_lowercase(),
+            Escaped(self.feed_url(FeedFormat::Rss).as_str()),
+        )
+        .unwrap();
+        if let Some(updated_at) = self.updated_at() {
+            writeln!(
+                xml,
+                "<lastBuildDate>{}</lastBuildDate>",
+                rfc2822(updated_at)
+            )
+            .unwrap();
+        }
+        for issue in self.issues {
+            let url = self.issue_url(issue);
+            writeln!(
+                xml,
+                r#"<item>
+<title>{}</title>
+<link>{}</link>
+<guid isPermaLink="true">{}</guid>
+<pubDate>{}</pubDate>
+<description>{}</description>
+</item>"#,
+                Escaped(&issue.title),
+                Escaped(url.as_str()),
+                Escaped(url.as_str()),
+                rfc2822(issue.published_at),
+                Escaped(&issue.html_body),
+            )
+            .unwrap();
+        }
+        xml.push_str("</channel>\n</rss>\n");
+        xml
+    }
+
+    fn atom(&self) -> String {
+        let mut xml = String::new();
+        writeln!(
+            xml,
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<title>{}</title>
+<id>{}</id>
+<link href="{}"/>
+<link href="{}" rel="self"/>
+<updated>{}</updated>"#,
+            TITLE,
+            Escaped(self.feed_url(FeedFormat::Atom).as_str()),
+            Escaped(self.home_url().as_str()),
+            Escaped(self.feed_url(FeedFormat::Atom).as_str()),
+            // Atom requires an updated time, even for an empty feed
+            self.updated_at()
+                .unwrap_or(OffsetDateTime::unix_epoch())
+                .format(time::Format::Rfc3339),
+        )
+        .unwrap();
+        for issue in self.issues {
+            writeln!(
+                xml,
+                r#"<entry>
+<title>{}</title>
+<id>urn:uuid:{}</id>
+<link href="{}"/>
+<published>{published}</published>
+<updated>{published}</updated>
+<author><name>{}</name></author>
+<content type="html">{}</content>
+</entry>"#,
+                Escaped(&issue.title),
+                issue.id,
+                Escaped(self.issue_url(issue).as_str()),
+                TITLE,
+                Escaped(&issue.html_body),
+                published = issue.published_at.format(time::Format::Rfc3339),
+            )
+            .unwrap();
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+
+    fn json(&self) -> String {
+        let items: Vec<_> = self
+            .issues
+            .iter()
+            .map(|issue| {
+                let url = self.issue_url(issue);
+                serde_json::json!({
+                    "id": url.as_str(),
+                    "url": url.as_str(),
+                    "title": issue.title,
+                    "content_html": issue.html_body,
+                    "date_published": issue.published_at.format(time::Format::Rfc3339),
+                })
+            })
+            .collect();
+
+        let feed = serde_json::json!({
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": TITLE,
+            "home_page_url": self.home_url().as_str(),
+            "feed_url": self.feed_url(FeedFormat::Json).as_str(),
+            "items": items,
+        });
+        feed.to_string()
+    }
+}
+
+/// Format a date for RSS, which uses RFC 822 (as updated by RFC 2822).
+fn rfc2822(datetime: OffsetDateTime) -> String {
+    datetime
+        .to_offset(UtcOffset::UTC)
+        .format("%a, %d %b %Y %H:%M:%S +0000")
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Date, Time, UtcOffset};
+    use uuid::Uuid;
+
+    use super::{rfc2822, FeedFormat, FeedIssue};
+
+    fn issues() -> Vec<FeedIssue> {
+        let published_at = |day| {
+            Date::try_from_ymd(2022, 6, day)
+                .unwrap()
+                .with_time(Time::try_from_hms(9, 30, 0).unwrap())
+                .assume_offset(UtcOffset::UTC)
+        };
+
+        vec![
+            FeedIssue {
+                id: Uuid::nil(),
+                slug: "second".to_string(),
+                title: "Second & last".to_string(),
+                html_body: "<p>Two</p>".to_string(),
+                published_at: published_at(2),
+            },
+            FeedIssue {
+                id: Uuid::nil(),
+                slug: "first".to_string(),
+                title: "First".to_string(),
+                html_body: "<p>One</p>".to_string(),
+                published_at: published_at(1),
+            },
+        ]
+    }
+
+    fn base_url() -> reqwest::Url {
+        "https://example.com".parse().unwrap()
+    }
+
+    #[test]
+    fn rfc2822_dates_are_in_utc() {
+        assert_eq!(
+            rfc2822(issues()[0].published_at),
+            "Thu, 02 Jun 2022 09:30:00 +0000"
+        );
+    }
+
+    #[test]
+    fn rss_has_absolute_links_and_escaped_content() {
+        let rss = FeedFormat::Rss.render(&base_url(), &issues());
+
+        assert!(rss.contains("<link>https://example.com/newsletters/second</link>"));
+        assert!(rss.contains("<title>Second &amp; last</title>"));
+        assert!(rss.contains("<description>&lt;p&gt;Two&lt;/p&gt;</description>"));
+        assert!(rss.contains("<lastBuildDate>Thu, 02 Jun 2022 09:30:00 +0000</lastBuildDate>"));
+        assert!(rss.contains(r#"<atom:link href="https://example.com/feed.rss" rel="self""#));
+    }
+
+    #[test]
+    fn atom_has_absolute_links_and_the_latest_update() {
+        let atom = FeedFormat::Atom.render(&base_url(), &issues());
+
+        assert!(atom.contains("<updated>2022-06-02T09:30:00+00:00</updated>"));
+        assert!(atom.contains(r#"<link href="https://example.com/newsletters/first"/>"#));
+        assert!(atom.contains(r#"<content type="html">&lt;p&gt;One&lt;/p&gt;</content>"#));
+        assert_eq!(atom.matches("<entry>").count(), 2);
+    }
+
+    #[test]
+    fn json_feed_has_absolute_links() {
+        let json = FeedFormat::Json.render(&base_url(), &issues());
+        let feed: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(feed["feed_url"], "https://example.com/feed.json");
+        assert_eq!(
+            feed["items"][1]["url"],
+            "https://example.com/newsletters/first"
+        );
+        assert_eq!(feed["items"][1]["content_html"], "<p>One</p>");
+        assert_eq!(
+            feed["items"][1]["date_published"],
+            "2022-06-01T09:30:00+00:00"
+        );
+    }
+
+    #[test]
+    fn empty_feeds_are_valid() {
+        let atom = FeedFormat::Atom.render(&base_url(), &[]);
+        assert!(atom.contains("<updated>1970-01-01T00:00:00+00:00</updated>"));
+
+        let rss = FeedFormat::Rss.render(&base_url(), &[]);
+        assert!(!rss.contains("<item>"));
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+/// Check the health of a server listening on `address`, optionally including its readiness.
+///
+/// This allows the (shell-less) container image to implement health checks with the app binary
+/// itself.
+pub async fn healthcheck(address: SocketAddr, ready: bool) -> Result<(), HealthcheckError> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(2))
+        .build()
+        .map_err(HealthcheckError::Request)?;
+
+    let address = connectable(address);
+    let paths: &[&str] = if ready {
+        &["/health", "/health/ready"]
+    } else {
+        &["/health"]
+    };
+    for path in paths {
+        let url = format!("http://{}{}", address, path);
+        let response = client
+            .get(&url)
+            .send()
+            .await
+            .map_err(HealthcheckError::Request)?;
+        if !response.status().is_success() {
+            return Err(HealthcheckError::Unhealthy(url, response.status()));
+        }
+    }
+
+    Ok(())
+}
+
+// Servers bound to an unspecified address are reachable via loopback.
+fn connectable(mut address: SocketAddr) -> SocketAddr {
+    match address.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => address.set_ip(Ipv4Addr::LOCALHOST.into()),
+        IpAddr::V6(ip) if ip.is_unspecified() => address.set_ip(Ipv6Addr::LOCALHOST.into()),
+        _ => {}
+    }
+    address
+}
+
+#[derive(Debug)]
+pub enum HealthcheckError {
+    Request(reqwest::Error),
+    Unhealthy(String, reqwest::StatusCode),
+}
+
+impl fmt::Display for HealthcheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Request(error) => write!(f, "healthcheck request failed: {}", error),
+            Self::Unhealthy(url, status) => write!(f, "{} responded with {}", url, status),
+        }
+    }
+}
+
+impl std::error::Error for HealthcheckError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Request(error) => Some(error),
+            Self::Unhealthy(_, _) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use super::connectable;
+
+    #[test]
+    fn unspecified_addresses_are_replaced_with_loopback() {
+        let v4: SocketAddr = (Ipv4Addr::UNSPECIFIED, 8000).into();
+        let v6: SocketAddr = (Ipv6Addr::UNSPECIFIED, 8000).into();
+
+        assert_eq!(connectable(v4), (Ipv4Addr::LOCALHOST, 8000).into());
+        assert_eq!(connectable(v6), (Ipv6Addr::LOCALHOST, 8000).into());
+    }
+
+    #[test]
+    fn specified_addresses_are_unchanged() {
+        let address: SocketAddr = "10.0.0.1:8000".parse().unwrap();
+
+        assert_eq!(connectable(address), address);
+    }
+}
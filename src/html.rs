@@ -0,0 +1,268 @@
+//! Rendering for the app's few public HTML pages.
+
+use std::fmt::{self, Write as _};
+
+use axum::response::Html;
+
+/// Wrap page content in the layout shared by all pages.
+///
+/// `title` is escaped, but `body` is included as-is.
+pub(crate) fn layout(title: &str, body: &str) -> Html<String> {
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>{title}</title>
+    <style>
+      body {{ font-family: sans-serif; line-height: 1.5; margin: 0 auto; max-width: 40em; padding: 1em; }}
+      nav {{ border-bottom: 1px solid #ccc; margin-bottom: 1em; padding-bottom: 0.5em; }}
+    </style>
+  </head>
+  <body>
+    <nav><a href="/newsletters">Newsletter archive</a></nav>
+    <main>
+{body}
+    </main>
+  </body>
+</html>
+"#,
+        title = Escaped(title),
+        body = body,
+    ))
+}
+
+/// Displays text with HTML special characters escaped.
+pub(crate) struct Escaped<'a>(pub(crate) &'a str);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                '\'' => f.write_str("&#39;")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Make untrusted HTML safe to show on our pages, by keeping only an allow-list of formatting
+/// elements and attributes.
+///
+/// Scripts, styles, event handlers and `javascript:` links are all removed.
+pub(crate) fn sanitize(html: &str) -> String {
+    ammonia::clean(html)
+}
+
+/// Elements that start a new paragraph when converting HTML to text.
+const BLOCK_ELEMENTS: [&str; 17] = [
+    "article",
+    "blockquote",
+    "br",
+    "div",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "ul",
+];
+
+/// Elements whose content isn't text, and is dropped along with them.
+const HIDDEN_ELEMENTS: [&str; 2] = ["script", "style"];
+
+/// Roughly convert HTML to plain text, e.g. for the text part of an email.
+///
+/// Tags are dropped, along with scripts and styles, block elements become paragraphs, entities
+/// are decoded, and whitespace (including non-breaking spaces) is collapsed.
+pub(crate) fn to_text(html: &str) -> String {
+    let mut paragraphs = Vec::new();
+    let mut paragraph = String::new();
+    let mut flush = |paragraph: &mut String| {
+        let text = unescape(paragraph)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !text.is_empty() {
+            paragraphs.push(text);
+        }
+        paragraph.clear();
+    };
+
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        paragraph.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('>')
+            .map_or(rest.len(), |end| start + end + 1);
+        let name = rest[start + 1..end]
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        let closing = rest[start + 1..].starts_with('/');
+
+        if BLOCK_ELEMENTS.contains(&name.as_str()) {
+            flush(&mut paragraph);
+        } else {
+            paragraph.push(' ');
+        }
+        rest = &rest[end..];
+
+        // Skip to the end of the element, or of the document if it's never closed
+        if !closing && HIDDEN_ELEMENTS.contains(&name.as_str()) {
+            let close = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                None => "",
+                Some(close_start) => {
+                    let close_end = rest[close_start..]
+                        .find('>')
+                        .map_or(rest.len(), |end| close_start + end + 1);
+                    &rest[close_end..]
+                }
+            };
+        }
+    }
+    paragraph.push_str(rest);
+    flush(&mut paragraph);
+
+    paragraphs.join("\n\n")
+}
+
+/// The longest entity name to look for, which is enough for those in [`entity`].
+const MAX_ENTITY_LEN: usize = 10;
+
+/// Decode numeric entities and common named ones, leaving anything else as it is.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        let decoded = rest
+            .find(';')
+            .filter(|&end| end <= MAX_ENTITY_LEN)
+            .and_then(|end| Some((entity(&rest[..end])?, end)));
+        match decoded {
+            Some((c, end)) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => unescaped.push('&'),
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// The character for an entity, given what's between the `&` and `;`.
+fn entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number
+            .strip_prefix('x')
+            .or_else(|| number.strip_prefix('X'))
+        {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "copy" => '©',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{layout, sanitize, to_text, Escaped};
+
+    #[test]
+    fn escaped_replaces_html_special_characters() {
+        assert_eq!(
+            Escaped(r#"<a href="x">Tom & Jerry's</a>"#).to_string(),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn layout_escapes_the_title_but_not_the_body() {
+        let page = layout("<b>Title</b>", "<p>Body</p>").0;
+
+        assert!(page.contains("<title>&lt;b&gt;Title&lt;/b&gt;</title>"));
+        assert!(page.contains("<p>Body</p>"));
+    }
+
+    #[test]
+    fn sanitize_keeps_formatting_but_not_scripts() {
+        assert_eq!(
+            sanitize(r#"<p onclick="steal()">Shipping <em>Rust</em></p><script>steal()</script>"#),
+            "<p>Shipping <em>Rust</em></p>"
+        );
+        assert_eq!(
+            sanitize(r#"<a href="javascript:steal()">link</a><img src="x" onerror="steal()">"#),
+            r#"<a rel="noopener noreferrer">link</a><img src="x">"#
+        );
+    }
+
+    #[test]
+    fn to_text_drops_tags_and_separates_blocks() {
+        assert_eq!(
+            to_text("<h1>Title</h1><p>One\n  <b>two</b></p><ul><li>three&nbsp;</li></ul>"),
+            "Title\n\nOne two\n\nthree"
+        );
+        assert_eq!(to_text("a &lt;b&gt; &amp;amp;<br/>c"), "a <b> &amp;\n\nc");
+    }
+
+    #[test]
+    fn to_text_decodes_entities() {
+        assert_eq!(
+            to_text("Le&nbsp;Guin&rsquo;s &#8220;Earthsea&#x201D; &mdash; &copy;"),
+            "Le Guin’s “Earthsea” — ©"
+        );
+        assert_eq!(
+            to_text("AT&T & &unknown; &#xZZ; &amp"),
+            "AT&T & &unknown; &#xZZ; &amp"
+        );
+    }
+
+    #[test]
+    fn to_text_drops_scripts_and_styles() {
+        assert_eq!(
+            to_text(
+                "<style>p { color: red; }</style><p>Hello</p>\
+                <SCRIPT>if (a < b) alert('hi')</SCRIPT> world<script>unclosed"
+            ),
+            "Hello\n\nworld"
+        );
+    }
+}
@@ -0,0 +1,545 @@
+//! Replaying responses to retried requests that carry an `Idempotency-Key` header.
+//!
+//! The first response to a state-changing request with a given key, method and path is stored, and
+//! returned verbatim (with an `Idempotent-Replayed: true` header) for any retries until the key
+//! expires. Retries that arrive while the first request is still in progress get a `409 Conflict`.
+//!
+//! Server errors aren't stored, since the request's transaction will have been rolled back, so the
+//! request can be retried with the same key. If a successful response can't be stored, though, the
+//! key stays claimed until it expires, and retries keep getting a `409 Conflict`. The request's
+//! transaction has already committed by then, so running it again could repeat its side effects.
+//!
+//! Request bodies are buffered to be hashed, so requests with a key are rejected with a
+//! `413 Payload Too Large` if their body is over the configured limit. Responses over the limit are
+//! passed on without being stored, and are treated like responses that couldn't be stored.
+//!
+//! Replays skip the handler, and so its authentication. Credentials are part of the request's
+//! identity along with its body, so a stored response is only replayed to a caller that presents
+//! the same credentials as the original request.
+
+use std::{convert::Infallible, task, time::Duration};
+
+use axum::{
+    body::{self, Body, BoxBody, Bytes, HttpBody},
+    http::{HeaderMap, HeaderValue, Method, Request, StatusCode},
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use futures::{future::BoxFuture, StreamExt as _};
+use sha2::{Digest as _, Sha256};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tower::Service;
+use tracing::{error, info, warn};
+
+const HEADER: &str = "idempotency-key";
+const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// The longest key we accept, which comfortably fits a UUID or similar.
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Headers that authenticate requests, which must match for a response to be replayed.
+const CREDENTIAL_HEADERS: [&str; 4] = [
+    "authorization",
+    "x-webhook-timestamp",
+    "x-webhook-nonce",
+    "x-webhook-signature",
+];
+
+#[derive(Clone)]
+pub(crate) struct Layer {
+    pool: PgPool,
+    ttl: Duration,
+    max_body_size: usize,
+}
+
+impl Layer {
+    /// Store responses in `pool`, and replay them for `ttl` after the original request.
+    ///
+    /// Request and response bodies are limited to `max_body_size` bytes.
+    pub(crate) fn new(pool: PgPool, ttl: Duration, max_body_size: usize) -> Self {
+        Self {
+            pool,
+            ttl,
+            max_body_size,
+        }
+    }
+}
+
+impl<S> tower::Layer<S> for Layer {
+    type Service = Middleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Middleware {
+            pool: self.pool.clone(),
+            ttl: self.ttl,
+            max_body_size: self.max_body_size,
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Middleware<S> {
+    pool: PgPool,
+    ttl: Duration,
+    max_body_size: usize,
+    inner: S,
+}
+
+impl<S, ResBody> Service<Request<Body>> for Middleware<S>
+where
+    S: Service<Request<Body>, Response = Response<ResBody>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    ResBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // Take the service that was driven to readiness, leaving a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let key = match req.headers().get(HEADER) {
+            Some(key) if is_state_changing(req.method()) => key.clone(),
+            _ => {
+                let res = inner.call(req);
+                return Box::pin(async move { Ok(res.await?.map(body::boxed)) });
+            }
+        };
+        let pool = self.pool.clone();
+        let ttl = self.ttl;
+        let max_body_size = self.max_body_size;
+
+        Box::pin(async move {
+            let key = match parse_key(&key) {
+                Some(key) => key,
+                None => {
+                    return Ok((
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "Idempotency-Key must be 1 to 255 visible ASCII characters",
+                    )
+                        .into_response())
+                }
+            };
+
+            match handle(pool, ttl, max_body_size, key, req, inner).await {
+                Ok(res) => Ok(res),
+                Err(error) => {
+                    error!(%error, "idempotency key handling failed");
+                    Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+                }
+            }
+        })
+    }
+}
+
+/// The identity of a request, for the purposes of idempotency.
+struct Scope {
+    key: String,
+    method: String,
+    path: String,
+}
+
+/// A claimed key, which is released if the request doesn't finish (e.g. if the client goes away).
+struct Claim {
+    pool: PgPool,
+    scope: Option<Scope>,
+}
+
+impl Claim {
+    fn scope(&self) -> &Scope {
+        self.scope.as_ref().unwrap()
+    }
+
+    /// Store the response.
+    ///
+    /// If that fails for a successful response, the key is left claimed so that retries are
+    /// rejected rather than repeating the request. Otherwise it's released, so the request can be
+    /// retried.
+    async fn complete(mut self, status: StatusCode, headers: &HeaderMap, body: &[u8]) {
+        let scope = self.scope();
+        let stored = sqlx::query!(
+            r#"
+            UPDATE idempotency_keys
+            SET response_status = $4, response_headers = $5, response_body = $6
+            WHERE key = $1 AND method = $2 AND path = $3
+            "#,
+            scope.key,
+            scope.method,
+            scope.path,
+            status.as_u16() as i16,
+            encode_headers(headers),
+            body,
+        )
+        .execute(&self.pool)
+        .await;
+
+        match stored {
+            Ok(_) => self.scope = None,
+            Err(error) if status.is_success() => {
+                error!(%error, key = %scope.key, "failed to store idempotent response, leaving the key claimed");
+                self.scope = None;
+            }
+            Err(error) => error!(%error, "failed to store idempotent response"),
+        }
+    }
+
+    /// Give up on storing a response that's too big, in the same way as if storing it failed.
+    async fn abandon(mut self, status: StatusCode) -> Result<(), sqlx::Error> {
+        if status.is_success() {
+            warn!(
+                key = %self.scope().key,
+                "idempotent response is too big to store, leaving the key claimed"
+            );
+            self.scope = None;
+            Ok(())
+        } else {
+            self.release().await
+        }
+    }
+
+    async fn release(mut self) -> Result<(), sqlx::Error> {
+        let scope = self.scope.take().unwrap();
+        release(&self.pool, &scope).await
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if let Some(scope) = self.scope.take() {
+            let pool = self.pool.clone();
+            tokio::spawn(async move {
+                if let Err(error) = release(&pool, &scope).await {
+                    error!(%error, "failed to release idempotency key");
+                }
+            });
+        }
+    }
+}
+
+async fn handle<S, ResBody>(
+    pool: PgPool,
+    ttl: Duration,
+    max_body_size: usize,
+    key: String,
+    req: Request<Body>,
+    mut inner: S,
+) -> Result<Response<BoxBody>, BoxError>
+where
+    S: Service<Request<Body>, Response = Response<ResBody>, Error = Infallible>,
+    ResBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    let (parts, req_body) = req.into_parts();
+    let req_body = match buffer(req_body, max_body_size).await? {
+        Buffered::Complete(body) => body,
+        Buffered::TooBig { .. } => {
+            return Ok((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!(
+                    "requests with an Idempotency-Key are limited to {} bytes",
+                    max_body_size
+                ),
+            )
+                .into_response())
+        }
+    };
+    let request_hash = request_hash(&parts.headers, &req_body);
+    let scope = Scope {
+        key,
+        method: parts.method.to_string(),
+        path: parts.uri.path().to_string(),
+    };
+
+    let now = OffsetDateTime::now_utc();
+    let expired = now - ttl;
+    sqlx::query!(
+        "DELETE FROM idempotency_keys WHERE created_at < $1",
+        expired
+    )
+    .execute(&pool)
+    .await?;
+
+    let claimed = sqlx::query!(
+        r#"
+        INSERT INTO idempotency_keys (key, method, path, request_hash, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        "#,
+        scope.key,
+        scope.method,
+        scope.path,
+        request_hash,
+        now,
+    )
+    .execute(&pool)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if !claimed {
+        return replay(&pool, &scope, &request_hash).await;
+    }
+    let claim = Claim {
+        pool,
+        scope: Some(scope),
+    };
+
+    let res = inner
+        .call(Request::from_parts(parts, Body::from(req_body)))
+        .await?;
+    let (parts, res_body) = res.into_parts();
+    let res_body = match buffer(Box::pin(res_body), max_body_size).await? {
+        Buffered::Complete(body) => body,
+        Buffered::TooBig { start, rest } => {
+            claim.abandon(parts.status).await?;
+
+            // Pass on what's been read so far, followed by the rest of the body
+            let rest = futures::stream::unfold(rest, |mut rest| async move {
+                let chunk = rest.data().await?.map_err(Into::<BoxError>::into);
+                Some((chunk, rest))
+            });
+            let body = futures::stream::once(async move { Ok(start) }).chain(rest);
+            return Ok(Response::from_parts(
+                parts,
+                body::boxed(body::StreamBody::new(body)),
+            ));
+        }
+    };
+
+    if parts.status.is_server_error() {
+        claim.release().await?;
+    } else {
+        info!(key = %claim.scope().key, status = %parts.status, "storing idempotent response");
+        claim
+            .complete(parts.status, &parts.headers, &res_body)
+            .await;
+    }
+
+    Ok(Response::from_parts(
+        parts,
+        body::boxed(body::Full::from(res_body)),
+    ))
+}
+
+/// A body read into memory, up to a limit.
+enum Buffered<B> {
+    Complete(Bytes),
+
+    /// The body is over the limit, so only its `start` has been read.
+    TooBig {
+        start: Bytes,
+        rest: B,
+    },
+}
+
+/// Read a body into memory, unless it's over `limit` bytes.
+async fn buffer<B>(mut body: B, limit: usize) -> Result<Buffered<B>, BoxError>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+    B::Error: Into<BoxError>,
+{
+    // Don't bother reading a body that's declared to be too big
+    if body.size_hint().lower() > limit as u64 {
+        return Ok(Buffered::TooBig {
+            start: Bytes::new(),
+            rest: body,
+        });
+    }
+
+    let mut buffered = Vec::new();
+    while let Some(chunk) = body.data().await {
+        buffered.extend_from_slice(&chunk.map_err(Into::into)?);
+        if buffered.len() > limit {
+            return Ok(Buffered::TooBig {
+                start: buffered.into(),
+                rest: body,
+            });
+        }
+    }
+    Ok(Buffered::Complete(buffered.into()))
+}
+
+/// Respond to a retry with the stored response, if the original request has finished.
+async fn replay(
+    pool: &PgPool,
+    scope: &Scope,
+    request_hash: &[u8],
+) -> Result<Response<BoxBody>, BoxError> {
+    let stored = sqlx::query!(
+        r#"
+        SELECT request_hash, response_status, response_headers, response_body
+        FROM idempotency_keys
+        WHERE key = $1 AND method = $2 AND path = $3
+        "#,
+        scope.key,
+        scope.method,
+        scope.path,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let stored = match stored {
+        Some(stored) if stored.request_hash != request_hash => {
+            return Ok((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency-Key has already been used for a different request",
+            )
+                .into_response())
+        }
+        Some(stored) => stored,
+        // The original request failed, and released the key, since we tried to claim it
+        None => {
+            return Ok((
+                StatusCode::CONFLICT,
+                "a request with this Idempotency-Key failed, please retry",
+            )
+                .into_response())
+        }
+    };
+
+    let status = match stored.response_status {
+        None => {
+            return Ok((
+                StatusCode::CONFLICT,
+                "a request with this Idempotency-Key is already in progress",
+            )
+                .into_response())
+        }
+        Some(status) => StatusCode::from_u16(status as u16)?,
+    };
+
+    let mut res = Response::new(body::boxed(body::Full::from(
+        stored.response_body.unwrap_or_default(),
+    )));
+    *res.status_mut() = status;
+    *res.headers_mut() = decode_headers(&stored.response_headers.unwrap_or_default());
+    res.headers_mut()
+        .insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    Ok(res)
+}
+
+async fn release(pool: &PgPool, scope: &Scope) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency_keys
+        WHERE key = $1 AND method = $2 AND path = $3 AND response_status IS NULL
+        "#,
+        scope.key,
+        scope.method,
+        scope.path,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Hash the request's credentials and body, to tell whether a retry is the same request.
+fn request_hash(headers: &HeaderMap, body: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for name in CREDENTIAL_HEADERS {
+        let value = headers.get(name).map_or(&[][..], HeaderValue::as_bytes);
+        // Length-prefixed, so that values can't run into each other
+        hasher.update((value.len() as u64).to_be_bytes());
+        hasher.update(value);
+    }
+    hasher.update(body);
+    hasher.finalize().to_vec()
+}
+
+fn is_state_changing(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    )
+}
+
+fn parse_key(key: &HeaderValue) -> Option<String> {
+    let key = key.to_str().ok()?;
+    let valid =
+        !key.is_empty() && key.len() <= MAX_KEY_LENGTH && key.bytes().all(|b| b.is_ascii_graphic());
+    valid.then(|| key.to_string())
+}
+
+/// Encode headers as `name: value` lines, which is unambiguous since values can't contain newlines.
+fn encode_headers(headers: &HeaderMap) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for (name, value) in headers {
+        encoded.extend_from_slice(name.as_str().as_bytes());
+        encoded.extend_from_slice(b": ");
+        encoded.extend_from_slice(value.as_bytes());
+        encoded.extend_from_slice(b"\r\n");
+    }
+    encoded
+}
+
+fn decode_headers(encoded: &[u8]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for line in encoded.split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let separator = match line.windows(2).position(|window| window == b": ") {
+            Some(separator) => separator,
+            None => continue,
+        };
+        if let (Ok(name), Ok(value)) = (
+            axum::http::header::HeaderName::from_bytes(&line[..separator]),
+            HeaderValue::from_bytes(&line[separator + 2..]),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use super::{decode_headers, encode_headers, parse_key, request_hash};
+
+    #[test]
+    fn headers_round_trip_including_repeated_names() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("text/plain"));
+        headers.append("set-cookie", HeaderValue::from_static("a=1"));
+        headers.append("set-cookie", HeaderValue::from_static("b=2: x"));
+
+        assert_eq!(decode_headers(&encode_headers(&headers)), headers);
+    }
+
+    #[test]
+    fn request_hashes_depend_on_credentials_and_body() {
+        let mut admin = HeaderMap::new();
+        admin.insert("authorization", HeaderValue::from_static("Bearer secret"));
+        let anonymous = HeaderMap::new();
+
+        assert_eq!(request_hash(&admin, b"{}"), request_hash(&admin, b"{}"));
+        assert_ne!(request_hash(&admin, b"{}"), request_hash(&anonymous, b"{}"));
+        assert_ne!(request_hash(&admin, b"{}"), request_hash(&admin, b"[]"));
+    }
+
+    #[test]
+    fn keys_must_be_short_visible_ascii() {
+        assert_eq!(
+            parse_key(&HeaderValue::from_static("4f8e2b1c-key")),
+            Some("4f8e2b1c-key".to_string())
+        );
+        assert_eq!(parse_key(&HeaderValue::from_static("")), None);
+        assert_eq!(parse_key(&HeaderValue::from_static("has space")), None);
+        assert_eq!(
+            parse_key(&HeaderValue::from_str(&"k".repeat(256)).unwrap()),
+            None
+        );
+    }
+}
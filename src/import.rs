@@ -0,0 +1,338 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use reqwest::Url;
+use sqlx::{Connection as _, PgConnection};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    bulk::{BulkSender, Outcome},
+    deliveries::EmailKind,
+    domain::{
+        CanonicalEmail, EmailCanonicalizer, NewSubscriber, SubscriberEmail, SubscriberName,
+        SubscriberStatus,
+    },
+    email_client::Email,
+    erasure,
+    signing::SigningKey,
+    subscribers::{self, Actor},
+    suppressions,
+};
+
+/// The consent source recorded for imported subscribers.
+const CONSENT_SOURCE: &str = "import";
+
+#[derive(Clone, Copy, Debug, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Import subscribers as pending and send them a confirmation email.
+    Confirm,
+
+    /// Import subscribers as confirmed, since they consented elsewhere.
+    Consented,
+}
+
+impl FromStr for ImportMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "confirm" => Ok(Self::Confirm),
+            "consented" => Ok(Self::Consented),
+            _ => Err(format!("unknown import mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ImportOptions {
+    pub mode: ImportMode,
+
+    /// How consent was obtained, recorded against each imported subscriber.
+    ///
+    /// Required when importing subscribers who already consented.
+    pub consent_note: Option<String>,
+}
+
+impl ImportOptions {
+    /// The consent note, unless it's blank.
+    fn consent_note(&self) -> Option<&str> {
+        self.consent_note
+            .as_deref()
+            .map(str::trim)
+            .filter(|note| !note.is_empty())
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ImportReport {
+    pub accepted: usize,
+    pub rejected: usize,
+
+    /// How many accepted subscribers couldn't be sent a confirmation email.
+    ///
+    /// They're still imported, as pending, and can subscribe again to get another.
+    pub unconfirmed: usize,
+
+    pub rows: Vec<RowReport>,
+}
+
+/// An import that has been written, but whose confirmation emails haven't been sent.
+#[must_use = "confirmation emails must be sent once the import is committed"]
+pub(crate) struct Import {
+    report: ImportReport,
+    confirmations: Vec<(SubscriberEmail, Uuid)>,
+}
+
+impl Import {
+    /// Send confirmation emails to the imported subscribers, returning the report.
+    ///
+    /// This must only be called once the import is committed, so that the links in the emails
+    /// work.
+    pub(crate) async fn send_confirmations(
+        self,
+        sender: &BulkSender,
+        base_url: &Url,
+    ) -> ImportReport {
+        let Self {
+            mut report,
+            confirmations,
+        } = self;
+
+        let bodies: Vec<_> = confirmations
+            .iter()
+            .map(|(_, token)| subscribers::confirmation_email_bodies(base_url, token))
+            .collect();
+        let emails: Vec<_> = confirmations
+            .iter()
+            .zip(&bodies)
+            .map(|((email, _), (html_body, text_body))| {
+                Email::new(
+                    email.clone(),
+                    EmailKind::Confirmation,
+                    subscribers::CONFIRMATION_EMAIL_SUBJECT,
+                    html_body,
+                    text_body,
+                )
+            })
+            .collect();
+
+        let outcomes = sender.send(&emails).await;
+        report.unconfirmed = outcomes
+            .iter()
+            .filter(|outcome| **outcome != Outcome::Delivered)
+            .count();
+        if report.unconfirmed > 0 {
+            warn!(
+                count = report.unconfirmed,
+                "failed to send confirmation emails for import"
+            );
+        }
+        report
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RowReport {
+    /// The 1-based index of the row, excluding the header.
+    pub row: usize,
+    pub email: String,
+    #[serde(flatten)]
+    pub outcome: RowOutcome,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "outcome", rename_all = "lowercase")]
+pub enum RowOutcome {
+    Accepted,
+    Rejected { reason: String },
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Invalid(String),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(error) => write!(f, "{}", error),
+            Self::Database(_) => write!(f, "a database error occurred during import"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Invalid(_) => None,
+            Self::Database(error) => Some(error),
+        }
+    }
+}
+
+impl From<sqlx::Error> for ImportError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::Database(error)
+    }
+}
+
+impl From<ImportError> for crate::Error {
+    fn from(error: ImportError) -> Self {
+        match error {
+            ImportError::Invalid(error) => Self::Validation(error),
+            ImportError::Database(error) => Self::Internal(error.into()),
+        }
+    }
+}
+
+/// Import subscribers from CSV with (at least) `name` and `email` columns.
+///
+/// Each row is imported in its own (nested) transaction, so invalid or duplicate rows are rejected
+/// individually without affecting the rest of the import. No emails are sent until
+/// [`Import::send_confirmations`], which should be called once the import is committed.
+pub(crate) async fn import(
+    conn: &mut PgConnection,
+    canonicalizer: &EmailCanonicalizer,
+    signing_key: &SigningKey,
+    csv: &[u8],
+    options: &ImportOptions,
+) -> Result<Import, ImportError> {
+    if let (ImportMode::Consented, None) = (options.mode, options.consent_note()) {
+        return Err(ImportError::Invalid(
+            "a consent note is required when importing consented subscribers".to_string(),
+        ));
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(csv);
+    let headers = reader
+        .headers()
+        .map_err(|error| ImportError::Invalid(format!("invalid CSV: {}", error)))?;
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.eq_ignore_ascii_case(name))
+            .ok_or_else(|| ImportError::Invalid(format!("missing CSV column: {}", name)))
+    };
+    let (name_column, email_column) = (column("name")?, column("email")?);
+
+    let mut report = ImportReport::default();
+    let mut confirmations = Vec::new();
+    let mut seen = HashMap::new();
+
+    for (index, record) in reader.records().enumerate() {
+        let row = index + 1;
+        let (email, outcome) = match record {
+            Err(error) => (String::new(), Err(format!("invalid CSV: {}", error))),
+            Ok(record) => {
+                let email = record.get(email_column).unwrap_or_default().to_string();
+                let name = record.get(name_column).unwrap_or_default().to_string();
+                let outcome = match parse(name, &email) {
+                    Err(error) => Err(error),
+                    Ok(subscriber) => {
+                        let canonical_email = canonicalizer.canonicalize(&subscriber.email);
+                        match seen.insert(canonical_email.clone(), row) {
+                            Some(first) => Err(format!("duplicate of row {}", first)),
+                            None => import_row(
+                                conn,
+                                signing_key,
+                                &subscriber,
+                                &canonical_email,
+                                options,
+                            )
+                            .await?
+                            .map(|token| {
+                                if let Some(token) = token {
+                                    confirmations.push((subscriber.email, token));
+                                }
+                            }),
+                        }
+                    }
+                };
+                (email, outcome)
+            }
+        };
+
+        let outcome = match outcome {
+            Ok(()) => {
+                report.accepted += 1;
+                RowOutcome::Accepted
+            }
+            Err(reason) => {
+                report.rejected += 1;
+                RowOutcome::Rejected { reason }
+            }
+        };
+        report.rows.push(RowReport {
+            row,
+            email,
+            outcome,
+        });
+    }
+
+    Ok(Import {
+        report,
+        confirmations,
+    })
+}
+
+fn parse(name: String, email: &str) -> Result<NewSubscriber, String> {
+    Ok(NewSubscriber {
+        email: SubscriberEmail::parse(email).map_err(|error| error.to_string())?,
+        name: SubscriberName::parse(name).map_err(|error| error.to_string())?,
+    })
+}
+
+/// Import a single valid row, returning the token to confirm the subscriber with, if they need
+/// confirming, or the reason if it was rejected.
+async fn import_row(
+    conn: &mut PgConnection,
+    signing_key: &SigningKey,
+    subscriber: &NewSubscriber,
+    canonical_email: &CanonicalEmail,
+    options: &ImportOptions,
+) -> Result<Result<Option<Uuid>, String>, sqlx::Error> {
+    let mut tx = conn.begin().await?;
+
+    if subscribers::email_exists(&mut tx, &subscriber.email, canonical_email).await? {
+        return Ok(Err("already subscribed".to_string()));
+    }
+    if suppressions::contains(&mut tx, &subscriber.email, canonical_email).await? {
+        return Ok(Err("address is on the suppression list".to_string()));
+    }
+    if erasure::is_erased(&mut tx, signing_key, canonical_email).await? {
+        return Ok(Err(
+            "subscriber's data was erased at their request".to_string()
+        ));
+    }
+
+    let (status, cause) = match options.mode {
+        ImportMode::Confirm => (SubscriberStatus::Pending, "imported"),
+        ImportMode::Consented => (SubscriberStatus::Confirmed, "imported with consent"),
+    };
+    let subscriber_id = subscribers::insert_subscriber(
+        &mut tx,
+        subscriber,
+        canonical_email,
+        status,
+        Actor::Admin,
+        cause,
+    )
+    .await?;
+    if let Some(note) = options.consent_note() {
+        subscribers::insert_consent(&mut tx, &subscriber_id, CONSENT_SOURCE, note).await?;
+    }
+
+    let token = match options.mode {
+        ImportMode::Confirm => {
+            Some(subscribers::insert_subscription_token(&mut tx, &subscriber_id).await?)
+        }
+        ImportMode::Consented => None,
+    };
+
+    tx.commit().await?;
+    Ok(Ok(token))
+}
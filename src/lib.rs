@@ -1,8 +1,29 @@
 mod app;
+mod auth;
+mod blog_feed;
+mod bulk;
+mod circuit_breaker;
 mod config;
+mod deliveries;
 mod domain;
 mod email_client;
+mod erasure;
+pub mod export;
+mod feeds;
+mod healthcheck;
+mod html;
+mod idempotency;
+pub mod import;
+pub mod migration_check;
+mod migrations;
+mod newsletters;
+mod policy;
+mod rfc3339;
 mod routes;
+mod scheduler;
+mod signing;
+mod subscribers;
+mod suppressions;
 pub mod telemetry;
 
 use std::{fmt, sync::Arc};
@@ -14,8 +35,10 @@ use axum::{
 
 pub use self::{
     app::{App, AppBaseUrl, Server},
-    config::Config,
+    config::{Config, ConfigBuilder, DatabaseConfig},
     email_client::EmailClient,
+    healthcheck::{healthcheck, HealthcheckError},
+    migrations::{MigrationState, MigrationStatus, Migrations},
 };
 
 pub(crate) type Tx = axum_sqlx_tx::Tx<sqlx::Postgres, Error>;
@@ -1,20 +1,270 @@
-use std::net::Ipv4Addr;
+use std::{io::Write as _, net::Ipv4Addr, path::PathBuf};
 
+use futures::TryStreamExt as _;
+use time::OffsetDateTime;
 use tracing::info;
-use zero2prod::App;
+use zero2prod::{
+    export::{ExportFilter, ExportFormat},
+    import::{ImportMode, ImportOptions, RowOutcome},
+    App, Migrations,
+};
+
+const DEFAULT_ADDRESS: (Ipv4Addr, u16) = (Ipv4Addr::LOCALHOST, 8000);
+
+#[derive(clap::Parser)]
+#[clap(version, about)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Start the server (the default)
+    Serve {
+        /// Start without running migrations, e.g. when they're run by a separate release job
+        #[clap(long)]
+        no_migrate: bool,
+    },
+
+    /// Manage database migrations
+    #[clap(subcommand)]
+    Migrate(MigrateCommand),
+
+    /// Manage subscribers
+    #[clap(subcommand)]
+    Subscribers(SubscribersCommand),
+
+    /// Check the health of a running server, exiting with 0 if it's healthy or 1 otherwise
+    Healthcheck {
+        /// Also check that the server is ready to handle requests (e.g. the database is reachable)
+        #[clap(long)]
+        ready: bool,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum MigrateCommand {
+    /// Apply any pending migrations
+    Run,
+
+    /// List applied and embedded migrations
+    Status,
+
+    /// Check migrations for statements that are unsafe during a rolling deployment
+    Check {
+        /// The directory containing the migrations to check
+        #[clap(default_value = "migrations")]
+        dir: PathBuf,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum SubscribersCommand {
+    /// Write all subscribers to stdout
+    Export {
+        #[clap(long, default_value = "csv", possible_values = ["csv", "ndjson"])]
+        format: ExportFormat,
+
+        /// Only export subscribers with this status
+        #[clap(long)]
+        status: Option<String>,
+
+        /// Only export subscribers who subscribed at or after this RFC 3339 timestamp
+        #[clap(long, parse(try_from_str = parse_rfc3339))]
+        subscribed_after: Option<OffsetDateTime>,
+
+        /// Only export subscribers who subscribed before this RFC 3339 timestamp
+        #[clap(long, parse(try_from_str = parse_rfc3339))]
+        subscribed_before: Option<OffsetDateTime>,
+    },
+
+    /// Import subscribers from a CSV file with `name` and `email` columns
+    Import {
+        /// The CSV file to import
+        file: PathBuf,
+
+        /// Whether to send confirmation emails, or import subscribers who already consented
+        #[clap(long, possible_values = ["confirm", "consented"])]
+        mode: ImportMode,
+
+        /// How consent was obtained (required with `--mode consented`)
+        #[clap(long)]
+        consent_note: Option<String>,
+    },
+}
 
 #[tokio::main]
 async fn main() {
+    let cli: Cli = clap::Parser::parse();
+
+    match cli.command.unwrap_or(Command::Serve { no_migrate: false }) {
+        Command::Serve { no_migrate } => serve(no_migrate).await,
+        Command::Migrate(command) => migrate(command).await,
+        Command::Subscribers(command) => subscribers(command).await,
+        Command::Healthcheck { ready } => healthcheck(ready).await,
+    }
+}
+
+async fn serve(no_migrate: bool) {
     zero2prod::telemetry::init(env!("CARGO_PKG_NAME"), std::io::stdout);
 
     let config = zero2prod::Config::builder()
-        .address((Ipv4Addr::LOCALHOST, 8000).into())
+        .address(DEFAULT_ADDRESS.into())
         .build()
         .expect("failed to read configuration");
 
     let app = App::new(config);
-    let server = app.serve().await.expect("failed to serve app");
+    let server = if no_migrate {
+        app.start()
+    } else {
+        app.serve().await.expect("failed to serve app")
+    };
 
     info!("Listening on {}", server.local_addr());
     server.await.expect("error while running server")
 }
+
+async fn migrate(command: MigrateCommand) {
+    if let MigrateCommand::Check { dir } = command {
+        return check_migrations(&dir);
+    }
+
+    // Keep stdout for command output
+    zero2prod::telemetry::init(env!("CARGO_PKG_NAME"), std::io::stderr);
+
+    let config = zero2prod::Config::builder()
+        .build_database()
+        .expect("failed to read configuration");
+    let migrations = Migrations::new(config);
+
+    match command {
+        MigrateCommand::Run => migrations.run().await.expect("failed to run migrations"),
+        MigrateCommand::Status => {
+            let statuses = migrations
+                .status()
+                .await
+                .expect("failed to read migration status");
+            for status in statuses {
+                println!(
+                    "{:<16} {:<10} {}",
+                    status.version,
+                    status.state,
+                    status.description.as_deref().unwrap_or("-"),
+                );
+            }
+        }
+        MigrateCommand::Check { .. } => unreachable!(),
+    }
+}
+
+fn check_migrations(dir: &std::path::Path) {
+    let diagnostics =
+        zero2prod::migration_check::check_dir(dir).expect("failed to read migrations");
+
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    if !diagnostics.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+async fn subscribers(command: SubscribersCommand) {
+    // Keep stdout for command output
+    zero2prod::telemetry::init(env!("CARGO_PKG_NAME"), std::io::stderr);
+
+    if let SubscribersCommand::Import {
+        file,
+        mode,
+        consent_note,
+    } = command
+    {
+        return import_subscribers(&file, ImportOptions { mode, consent_note }).await;
+    }
+
+    let config = zero2prod::Config::builder()
+        .build_database()
+        .expect("failed to read configuration");
+
+    match command {
+        SubscribersCommand::Export {
+            format,
+            status,
+            subscribed_after,
+            subscribed_before,
+        } => {
+            let filter = ExportFilter {
+                status,
+                subscribed_after,
+                subscribed_before,
+            };
+            let mut export = Box::pin(zero2prod::export::export(config.pool(), filter, format));
+
+            let stdout = std::io::stdout();
+            let mut stdout = std::io::BufWriter::new(stdout.lock());
+            while let Some(chunk) = export
+                .try_next()
+                .await
+                .expect("failed to export subscribers")
+            {
+                stdout.write_all(&chunk).expect("failed to write export");
+            }
+            stdout.flush().expect("failed to write export");
+        }
+        SubscribersCommand::Import { .. } => unreachable!(),
+    }
+}
+
+async fn import_subscribers(file: &std::path::Path, options: ImportOptions) {
+    let csv = std::fs::read(file).expect("failed to read import file");
+
+    let config = zero2prod::Config::builder()
+        .address(DEFAULT_ADDRESS.into())
+        .build()
+        .expect("failed to read configuration");
+    let report = App::new(config)
+        .import_subscribers(&csv, &options)
+        .await
+        .expect("failed to import subscribers");
+
+    for row in &report.rows {
+        match &row.outcome {
+            RowOutcome::Accepted => println!("{:<6} {:<10} {}", row.row, "accepted", row.email),
+            RowOutcome::Rejected { reason } => println!(
+                "{:<6} {:<10} {} ({})",
+                row.row, "rejected", row.email, reason
+            ),
+        }
+    }
+    println!("{} accepted, {} rejected", report.accepted, report.rejected);
+    if report.unconfirmed > 0 {
+        println!(
+            "{} confirmation emails could not be sent",
+            report.unconfirmed
+        );
+    }
+}
+
+fn parse_rfc3339(s: &str) -> Result<OffsetDateTime, time::ParseError> {
+    OffsetDateTime::parse(s, time::Format::Rfc3339)
+}
+
+async fn healthcheck(ready: bool) {
+    // Probes only look at the exit code, so don't panic with an unexpected one
+    let address = match zero2prod::Config::builder()
+        .address(DEFAULT_ADDRESS.into())
+        .build_address()
+    {
+        Ok(address) => address,
+        Err(error) => {
+            eprintln!("unhealthy: failed to read configuration: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(error) = zero2prod::healthcheck(address, ready).await {
+        eprintln!("unhealthy: {}", error);
+        std::process::exit(1);
+    }
+}
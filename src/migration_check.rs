@@ -0,0 +1,506 @@
+//! Static checks that migrations are safe for rolling deployments.
+//!
+//! Old replicas keep running against the migrated database during a deployment, so migrations must
+//! not break the schema they rely on (see the README for details). The checker flags statements
+//! that are likely to do so. Intentional exceptions can be acknowledged with an annotation comment
+//! before (or within) the offending statement:
+//!
+//! ```sql
+//! -- migration-check: allow(drop-column)
+//! ALTER TABLE subscriptions DROP COLUMN unused;
+//! ```
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+const ANNOTATION: &str = "migration-check:";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    DropTable,
+    DropColumn,
+    Rename,
+    AlterColumnType,
+    SetNotNull,
+    DropDefault,
+    NotNullWithoutDefault,
+    Truncate,
+}
+
+impl Rule {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DropTable => "drop-table",
+            Self::DropColumn => "drop-column",
+            Self::Rename => "rename",
+            Self::AlterColumnType => "alter-column-type",
+            Self::SetNotNull => "set-not-null",
+            Self::DropDefault => "drop-default",
+            Self::NotNullWithoutDefault => "not-null-without-default",
+            Self::Truncate => "truncate",
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Self::DropTable => "dropping a table breaks replicas that still use it",
+            Self::DropColumn => "dropping a column breaks replicas that still use it",
+            Self::Rename => "renaming breaks replicas that use the old name",
+            Self::AlterColumnType => {
+                "changing a column's type may be incompatible with running replicas"
+            }
+            Self::SetNotNull => "replicas that don't set the column will fail to insert",
+            Self::DropDefault => "replicas that rely on the default will fail to insert",
+            Self::NotNullWithoutDefault => {
+                "NOT NULL columns need a DEFAULT, or replicas that don't set them will fail to insert"
+            }
+            Self::Truncate => "truncating deletes data that running replicas may rely on",
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub line: usize,
+    pub rule: Rule,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: [{}] {} (acknowledge with `-- {} allow({})`)",
+            self.path.display(),
+            self.line,
+            self.rule,
+            self.rule.message(),
+            ANNOTATION,
+            self.rule,
+        )
+    }
+}
+
+/// Check every `.sql` file in `dir`, in name order.
+pub fn check_dir(dir: &Path) -> io::Result<Vec<Diagnostic>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| matches!(path.extension(), Some(ext) if ext == "sql"));
+    paths.sort();
+
+    let mut diagnostics = Vec::new();
+    for path in paths {
+        let sql = fs::read_to_string(&path)?;
+        diagnostics.extend(check_sql(&path, &sql));
+    }
+    Ok(diagnostics)
+}
+
+/// Check the statements in `sql`, attributing diagnostics to `path`.
+pub fn check_sql(path: &Path, sql: &str) -> Vec<Diagnostic> {
+    statements(sql)
+        .iter()
+        .flat_map(|statement| {
+            violations(&statement.tokens)
+                .into_iter()
+                .filter(|(rule, _)| !statement.allowed.iter().any(|a| a == rule.name()))
+                .map(|(rule, line)| Diagnostic {
+                    path: path.to_path_buf(),
+                    line,
+                    rule,
+                })
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+enum TokenKind {
+    /// A keyword or unquoted identifier, upper-cased.
+    Word,
+    /// A quoted identifier or literal, which is never a keyword.
+    Quoted,
+    Symbol,
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    text: String,
+    line: usize,
+}
+
+impl Token {
+    fn is(&self, keyword: &str) -> bool {
+        self.kind == TokenKind::Word && self.text == keyword
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        self.kind == TokenKind::Symbol && self.text == symbol
+    }
+}
+
+#[derive(Debug, Default)]
+struct Statement {
+    tokens: Vec<Token>,
+    allowed: Vec<String>,
+}
+
+fn statements(sql: &str) -> Vec<Statement> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut statements = Vec::new();
+    let mut current = Statement::default();
+    let mut line = 1;
+    let mut i = 0;
+
+    let text = |from: usize, to: usize| chars[from..to].iter().collect::<String>();
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let start = i;
+
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && next == Some('-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            current.allowed.extend(annotations(&text(start, i)));
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i = (i + 2).min(chars.len());
+            let comment = text(start, i);
+            line += comment.matches('\n').count();
+            current.allowed.extend(annotations(&comment));
+        } else if c == '\'' || c == '"' {
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => break,
+                    Some(&q) if q == c && chars.get(i + 1) == Some(&c) => i += 2,
+                    Some(&q) if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(_) => i += 1,
+                }
+            }
+            let quoted = text(start, i);
+            current.tokens.push(Token {
+                kind: TokenKind::Quoted,
+                text: quoted.clone(),
+                line,
+            });
+            line += quoted.matches('\n').count();
+        } else if let Some(tag) = dollar_quote_tag(&chars[i..]) {
+            let tag: Vec<char> = tag.chars().collect();
+            i += tag.len();
+            while i < chars.len() && !chars[i..].starts_with(&tag) {
+                i += 1;
+            }
+            i = (i + tag.len()).min(chars.len());
+            let quoted = text(start, i);
+            current.tokens.push(Token {
+                kind: TokenKind::Quoted,
+                text: quoted.clone(),
+                line,
+            });
+            line += quoted.matches('\n').count();
+        } else if c.is_alphanumeric() || c == '_' {
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$')
+            {
+                i += 1;
+            }
+            current.tokens.push(Token {
+                kind: TokenKind::Word,
+                text: text(start, i).to_uppercase(),
+                line,
+            });
+        } else if c == ';' {
+            i += 1;
+            if !current.tokens.is_empty() {
+                statements.push(std::mem::take(&mut current));
+            } else {
+                current.allowed.clear();
+            }
+        } else {
+            i += 1;
+            current.tokens.push(Token {
+                kind: TokenKind::Symbol,
+                text: c.to_string(),
+                line,
+            });
+        }
+    }
+
+    if !current.tokens.is_empty() {
+        statements.push(current);
+    }
+    statements
+}
+
+/// The opening tag of a dollar-quoted string (e.g. `$$` or `$body$`), if `chars` starts with one.
+fn dollar_quote_tag(chars: &[char]) -> Option<String> {
+    if chars.first() != Some(&'$') {
+        return None;
+    }
+    let end = chars[1..]
+        .iter()
+        .position(|c| !(c.is_alphanumeric() || *c == '_'))?
+        + 1;
+    if chars[end] == '$' && !matches!(chars.get(1), Some(c) if c.is_ascii_digit()) {
+        Some(chars[..=end].iter().collect())
+    } else {
+        None
+    }
+}
+
+/// Rules allowed by any `migration-check: allow(...)` annotations in `comment`.
+fn annotations(comment: &str) -> Vec<String> {
+    comment
+        .match_indices(ANNOTATION)
+        .filter_map(|(index, _)| {
+            let rest = comment[index + ANNOTATION.len()..].trim_start();
+            let rest = rest.strip_prefix("allow")?.trim_start().strip_prefix('(')?;
+            let end = rest.find(')')?;
+            Some(rest[..end].split(',').map(|rule| rule.trim().to_string()))
+        })
+        .flatten()
+        .collect()
+}
+
+fn violations(tokens: &[Token]) -> Vec<(Rule, usize)> {
+    let line = tokens[0].line;
+
+    if tokens[0].is("TRUNCATE") {
+        return vec![(Rule::Truncate, line)];
+    }
+
+    if tokens[0].is("DROP") && matches!(tokens.get(1), Some(t) if t.is("TABLE")) {
+        return vec![(Rule::DropTable, line)];
+    }
+
+    if tokens[0].is("ALTER") && matches!(tokens.get(1), Some(t) if t.is("TABLE")) {
+        return alter_table_actions(&tokens[2..])
+            .into_iter()
+            .filter_map(alter_table_violation)
+            .collect();
+    }
+
+    Vec::new()
+}
+
+/// Split the tokens following `ALTER TABLE` into the comma-separated actions after the table name.
+fn alter_table_actions(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut i = 0;
+    while matches!(tokens.get(i), Some(t) if t.is("IF") || t.is("EXISTS") || t.is("ONLY")) {
+        i += 1;
+    }
+
+    // Skip the (possibly schema-qualified) table name
+    i += 1;
+    while matches!(tokens.get(i), Some(t) if t.is_symbol(".")) {
+        i += 2;
+    }
+    if matches!(tokens.get(i), Some(t) if t.is_symbol("*")) {
+        i += 1;
+    }
+
+    let mut actions = Vec::new();
+    let mut depth = 0;
+    let mut start = i;
+    for (j, token) in tokens.iter().enumerate().skip(i) {
+        if token.is_symbol("(") {
+            depth += 1;
+        } else if token.is_symbol(")") {
+            depth -= 1;
+        } else if token.is_symbol(",") && depth == 0 {
+            actions.push(&tokens[start..j]);
+            start = j + 1;
+        }
+    }
+    if start < tokens.len() {
+        actions.push(&tokens[start..]);
+    }
+    actions.retain(|action| !action.is_empty());
+    actions
+}
+
+fn alter_table_violation(action: &[Token]) -> Option<(Rule, usize)> {
+    let line = action[0].line;
+    let word = |i: usize, keyword: &str| matches!(action.get(i), Some(t) if t.is(keyword));
+
+    if word(0, "RENAME") {
+        return Some((Rule::Rename, line));
+    }
+
+    if word(0, "DROP") {
+        return if word(1, "CONSTRAINT") {
+            None
+        } else {
+            Some((Rule::DropColumn, line))
+        };
+    }
+
+    if word(0, "ALTER") {
+        // Skip `[COLUMN] name`
+        let i = if word(1, "COLUMN") { 3 } else { 2 };
+        return if word(i, "TYPE") || (word(i, "SET") && word(i + 1, "DATA")) {
+            Some((Rule::AlterColumnType, line))
+        } else if word(i, "SET") && word(i + 1, "NOT") && word(i + 2, "NULL") {
+            Some((Rule::SetNotNull, line))
+        } else if word(i, "DROP") && word(i + 1, "DEFAULT") {
+            Some((Rule::DropDefault, line))
+        } else {
+            None
+        };
+    }
+
+    if word(0, "ADD") {
+        let constraint = [
+            "CONSTRAINT",
+            "PRIMARY",
+            "UNIQUE",
+            "CHECK",
+            "FOREIGN",
+            "EXCLUDE",
+        ];
+        if constraint.iter().any(|keyword| word(1, keyword)) {
+            return None;
+        }
+        let not_null = (0..action.len()).any(|i| word(i, "NOT") && word(i + 1, "NULL"));
+        let default = (0..action.len()).any(|i| word(i, "DEFAULT"));
+        if not_null && !default {
+            return Some((Rule::NotNullWithoutDefault, line));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{check_dir, check_sql, Rule};
+
+    fn rules(sql: &str) -> Vec<(Rule, usize)> {
+        check_sql(Path::new("test.sql"), sql)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.rule, diagnostic.line))
+            .collect()
+    }
+
+    #[test]
+    fn the_repository_migrations_pass() {
+        let diagnostics = check_dir(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("migrations")
+                .as_path(),
+        )
+        .unwrap();
+        assert_eq!(diagnostics, vec![]);
+    }
+
+    #[test]
+    fn additive_changes_are_accepted() {
+        let sql = "
+            CREATE TABLE things (id uuid NOT NULL PRIMARY KEY);
+            ALTER TABLE things ADD COLUMN name TEXT;
+            ALTER TABLE things ADD COLUMN status TEXT NOT NULL DEFAULT 'new';
+            ALTER TABLE things ADD CONSTRAINT things_name_key UNIQUE (name);
+            ALTER TABLE things DROP CONSTRAINT things_name_key;
+            CREATE INDEX things_name_idx ON things (name);
+        ";
+        assert_eq!(rules(sql), vec![]);
+    }
+
+    #[test]
+    fn destructive_changes_are_flagged_with_their_line() {
+        let sql = "DROP TABLE things;
+ALTER TABLE things DROP COLUMN name;
+ALTER TABLE things RENAME COLUMN name TO title;
+ALTER TABLE things RENAME TO stuff;
+ALTER TABLE things ALTER COLUMN name TYPE integer;
+ALTER TABLE things ALTER name SET DATA TYPE integer;
+ALTER TABLE things ALTER COLUMN name SET NOT NULL;
+ALTER TABLE things ALTER COLUMN name DROP DEFAULT;
+ALTER TABLE things ADD COLUMN name TEXT NOT NULL;
+TRUNCATE things;
+";
+        assert_eq!(
+            rules(sql),
+            vec![
+                (Rule::DropTable, 1),
+                (Rule::DropColumn, 2),
+                (Rule::Rename, 3),
+                (Rule::Rename, 4),
+                (Rule::AlterColumnType, 5),
+                (Rule::AlterColumnType, 6),
+                (Rule::SetNotNull, 7),
+                (Rule::DropDefault, 8),
+                (Rule::NotNullWithoutDefault, 9),
+                (Rule::Truncate, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn each_action_of_an_alter_table_is_checked() {
+        let sql = "ALTER TABLE IF EXISTS public.things
+  ADD COLUMN a TEXT NOT NULL DEFAULT '',
+  ADD COLUMN b numeric(10, 2) NOT NULL,
+  DROP COLUMN c;";
+        assert_eq!(
+            rules(sql),
+            vec![(Rule::NotNullWithoutDefault, 3), (Rule::DropColumn, 4)]
+        );
+    }
+
+    #[test]
+    fn annotated_statements_are_allowed() {
+        let sql = "
+-- migration-check: allow(drop-column, rename)
+ALTER TABLE things DROP COLUMN name, RENAME TO stuff;
+ALTER TABLE things DROP COLUMN other; -- not an annotation for this statement
+DROP TABLE /* migration-check: allow(drop-table) */ old_things;
+";
+        assert_eq!(rules(sql), vec![(Rule::DropColumn, 4)]);
+    }
+
+    #[test]
+    fn annotations_for_other_rules_do_not_allow_a_statement() {
+        let sql = "-- migration-check: allow(rename)\nDROP TABLE things;";
+        assert_eq!(rules(sql), vec![(Rule::DropTable, 2)]);
+    }
+
+    #[test]
+    fn comments_strings_and_quoted_identifiers_are_not_statements() {
+        let sql = "
+-- DROP TABLE things;
+/* TRUNCATE things;
+   DROP TABLE things; */
+INSERT INTO things (name) VALUES ('; DROP TABLE things;');
+CREATE FUNCTION f() RETURNS void AS $$ TRUNCATE things; $$ LANGUAGE sql;
+ALTER TABLE \"DROP\" ADD COLUMN \"not null\" TEXT;
+TRUNCATE things;
+";
+        assert_eq!(rules(sql), vec![(Rule::Truncate, 8)]);
+    }
+}
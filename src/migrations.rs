@@ -0,0 +1,114 @@
+use std::fmt;
+
+use sqlx::migrate::{Migrate as _, MigrateError, Migrator};
+use tracing::warn;
+
+use crate::config::DatabaseConfig;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Runs and inspects the migrations embedded in the binary.
+pub struct Migrations {
+    pool: sqlx::PgPool,
+    ignore_missing_migrations: bool,
+}
+
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: Option<String>,
+    pub state: MigrationState,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationState {
+    /// Embedded in the binary and applied to the database.
+    Applied,
+
+    /// Embedded in the binary but not yet applied.
+    Pending,
+
+    /// Applied to the database, but with different content to the embedded migration.
+    Modified,
+
+    /// Applied to the database, but not known to the binary (e.g. after a rollback).
+    Unknown,
+}
+
+impl fmt::Display for MigrationState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            Self::Applied => "applied",
+            Self::Pending => "pending",
+            Self::Modified => "modified",
+            Self::Unknown => "unknown",
+        };
+        f.pad(state)
+    }
+}
+
+impl Migrations {
+    pub fn new(config: DatabaseConfig) -> Self {
+        Self::from_pool(config.pool(), config.ignore_missing_migrations)
+    }
+
+    pub(crate) fn from_pool(pool: sqlx::PgPool, ignore_missing_migrations: bool) -> Self {
+        Self {
+            pool,
+            ignore_missing_migrations,
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn run(&self) -> Result<(), MigrateError> {
+        MIGRATOR.run(&self.pool).await.or_else(|error| match error {
+            MigrateError::VersionMissing(_) if self.ignore_missing_migrations => {
+                warn!(
+                    ?error,
+                    "database state is ahead of that known by the app – \
+                    in a rollback scenario this is expected, but otherwise something may be wrong"
+                );
+                Ok(())
+            }
+            _ => Err(error),
+        })
+    }
+
+    /// List embedded and applied migrations, ordered by version.
+    #[tracing::instrument(skip(self))]
+    pub async fn status(&self) -> Result<Vec<MigrationStatus>, MigrateError> {
+        let mut conn = self.pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        let applied = conn.list_applied_migrations().await?;
+
+        let mut statuses: Vec<_> = MIGRATOR
+            .iter()
+            .map(|migration| {
+                let state = match applied.iter().find(|a| a.version == migration.version) {
+                    None => MigrationState::Pending,
+                    Some(a) if a.checksum == migration.checksum => MigrationState::Applied,
+                    Some(_) => MigrationState::Modified,
+                };
+                MigrationStatus {
+                    version: migration.version,
+                    description: Some(migration.description.to_string()),
+                    state,
+                }
+            })
+            .collect();
+
+        statuses.extend(
+            applied
+                .iter()
+                .filter(|a| !MIGRATOR.iter().any(|m| m.version == a.version))
+                .map(|a| MigrationStatus {
+                    version: a.version,
+                    description: None,
+                    state: MigrationState::Unknown,
+                }),
+        );
+        statuses.sort_by_key(|status| status.version);
+
+        Ok(statuses)
+    }
+}
@@ -0,0 +1,405 @@
+//! Newsletter issues, their public archive, and delivery to subscribers.
+
+use reqwest::Url;
+use sqlx::PgConnection;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    bulk::{BulkSender, Outcome},
+    deliveries::EmailKind,
+    domain::{SubscriberEmail, SubscriberStatus},
+    email_client::Email,
+    Error,
+};
+
+pub(crate) struct NewIssue {
+    pub(crate) title: String,
+    pub(crate) html_body: String,
+    pub(crate) text_body: String,
+
+    /// The issue's URL path segment, generated from the title if not given.
+    pub(crate) slug: Option<String>,
+
+    /// The blog post the issue announces, if any. Each post only ever gets one issue.
+    pub(crate) source_url: Option<String>,
+}
+
+/// A stored issue, and whether it was just created.
+pub(crate) enum Inserted {
+    Created(Issue),
+
+    /// An issue already exists for the same blog post.
+    Existing(Issue),
+}
+
+impl Inserted {
+    pub(crate) fn into_issue(self) -> Issue {
+        match self {
+            Self::Created(issue) | Self::Existing(issue) => issue,
+        }
+    }
+}
+
+pub(crate) struct Issue {
+    pub(crate) id: Uuid,
+    pub(crate) slug: String,
+    pub(crate) title: String,
+    pub(crate) html_body: String,
+    pub(crate) text_body: String,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct DeliveryReport {
+    pub(crate) delivered: usize,
+    pub(crate) suppressed: usize,
+    pub(crate) failed: usize,
+}
+
+impl NewIssue {
+    fn validate(&self) -> Result<(), Error> {
+        let required = [
+            ("title", &self.title),
+            ("html_body", &self.html_body),
+            ("text_body", &self.text_body),
+        ];
+        for (field, value) in required {
+            if value.trim().is_empty() {
+                return Err(Error::Validation(format!("{} is required", field)));
+            }
+        }
+
+        match &self.slug {
+            Some(slug) if slugify(slug) != *slug => Err(Error::Validation(format!(
+                "{} is not a valid slug, try {}",
+                slug,
+                slugify(slug)
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Store a new issue, making its slug unique if necessary.
+///
+/// If an issue already exists for the same blog post, it's returned instead.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn insert_issue(
+    conn: &mut PgConnection,
+    issue: &NewIssue,
+    published_at: Option<OffsetDateTime>,
+) -> Result<Inserted, Error> {
+    issue.validate()?;
+
+    let base = issue.slug.clone().unwrap_or_else(|| slugify(&issue.title));
+    let mut slug = base.clone();
+    for n in 2.. {
+        let row = sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM newsletter_issues WHERE slug = $1) AS "exists!""#,
+            slug,
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        if !row.exists {
+            break;
+        }
+        slug = format!("{}-{}", base, n);
+    }
+
+    let created = sqlx::query_as!(
+        Issue,
+        r#"
+        INSERT INTO newsletter_issues
+          (id, slug, title, html_body, text_body, created_at, published_at, source_url)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (source_url) DO NOTHING
+        RETURNING id, slug, title, html_body, text_body
+        "#,
+        Uuid::new_v4(),
+        slug,
+        issue.title.trim(),
+        issue.html_body,
+        issue.text_body,
+        OffsetDateTime::now_utc(),
+        published_at,
+        issue.source_url,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    if let Some(created) = created {
+        return Ok(Inserted::Created(created));
+    }
+
+    // Only a conflicting source URL is ignored, so there must be an issue for it
+    let existing = sqlx::query_as!(
+        Issue,
+        r#"
+        SELECT id, slug, title, html_body, text_body
+        FROM newsletter_issues
+        WHERE source_url = $1
+        "#,
+        issue.source_url,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(Inserted::Existing(existing))
+}
+
+/// Claim the issue that has been due to be sent for longest, publishing it.
+///
+/// Issues that are being claimed concurrently (e.g. by another replica) are skipped, so each issue is
+/// only ever claimed once.
+#[tracing::instrument(skip(conn))]
+pub(crate) async fn claim_due_issue(
+    conn: &mut PgConnection,
+    now: OffsetDateTime,
+) -> Result<Option<Issue>, sqlx::Error> {
+    sqlx::query_as!(
+        Issue,
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = $1
+        WHERE id = (
+          SELECT id
+          FROM newsletter_issues
+          WHERE published_at IS NULL AND send_at <= $1
+          ORDER BY send_at
+          LIMIT 1
+          FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, slug, title, html_body, text_body
+        "#,
+        now,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ScheduleOutcome {
+    Updated,
+    NotFound,
+    AlreadyPublished,
+}
+
+/// Set (or, with `None`, cancel) the time a draft will be sent.
+#[tracing::instrument(skip(conn))]
+pub(crate) async fn schedule(
+    conn: &mut PgConnection,
+    slug: &str,
+    send_at: Option<OffsetDateTime>,
+) -> Result<ScheduleOutcome, sqlx::Error> {
+    // Wait for the scheduler if it's claiming the issue, so we know whether it's too late
+    let issue = sqlx::query!(
+        "SELECT id, published_at FROM newsletter_issues WHERE slug = $1 FOR UPDATE",
+        slug,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match issue {
+        None => Ok(ScheduleOutcome::NotFound),
+        Some(issue) if issue.published_at.is_some() => Ok(ScheduleOutcome::AlreadyPublished),
+        Some(issue) => {
+            sqlx::query!(
+                "UPDATE newsletter_issues SET send_at = $2 WHERE id = $1",
+                issue.id,
+                send_at,
+            )
+            .execute(conn)
+            .await?;
+            Ok(ScheduleOutcome::Updated)
+        }
+    }
+}
+
+/// The public, "view in browser" URL of an issue.
+pub(crate) fn issue_url(base_url: &Url, slug: &str) -> Url {
+    base_url.join("/newsletters/").unwrap().join(slug).unwrap()
+}
+
+/// The `List-Id` header identifying the newsletter, so mail clients can filter and group issues.
+fn list_id(base_url: &Url) -> String {
+    format!(
+        "<newsletter.{}>",
+        base_url.host_str().unwrap_or("localhost")
+    )
+}
+
+/// Send an issue to every confirmed subscriber, recording the report on the issue.
+///
+/// Recipients that fail are retried once. Failures for individual subscribers are then logged and
+/// counted, rather than aborting delivery.
+#[tracing::instrument(skip_all, fields(issue_id = %issue.id))]
+pub(crate) async fn deliver(
+    conn: &mut PgConnection,
+    sender: &BulkSender,
+    base_url: &Url,
+    issue: &Issue,
+) -> Result<DeliveryReport, sqlx::Error> {
+    let recipients = sqlx::query!(
+        r#"SELECT id, email FROM subscriptions WHERE status = $1 ORDER BY subscribed_at"#,
+        SubscriberStatus::Confirmed.as_str(),
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let url = issue_url(base_url, &issue.slug);
+    let html_body = format!(
+        "<p><a href=\"{}\">View this email in your browser</a></p>\n{}",
+        url, issue.html_body
+    );
+    let text_body = format!(
+        "View this email in your browser: {}\n\n{}",
+        url, issue.text_body
+    );
+
+    let mut report = DeliveryReport::default();
+    let mut emails = Vec::with_capacity(recipients.len());
+    for recipient in recipients {
+        match SubscriberEmail::parse(recipient.email) {
+            Ok(email) => emails.push((recipient.id, email)),
+            Err(error) => {
+                warn!(subscriber_id = %recipient.id, %error, "skipping invalid stored email");
+                report.failed += 1;
+            }
+        }
+    }
+
+    let list_id = list_id(base_url);
+    let content = Content {
+        issue_id: issue.id,
+        list_id: &list_id,
+        subject: &issue.title,
+        html_body: &html_body,
+        text_body: &text_body,
+    };
+    let failed = send(sender, &content, &emails, &mut report).await;
+    if !failed.is_empty() {
+        info!(count = failed.len(), "retrying failed recipients");
+        let failed = send(sender, &content, &failed, &mut report).await;
+        for (subscriber_id, _) in &failed {
+            warn!(%subscriber_id, "failed to deliver newsletter");
+        }
+        report.failed += failed.len();
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivered_at = $2, delivered_count = $3, suppressed_count = $4, failed_count = $5
+        WHERE id = $1
+        "#,
+        issue.id,
+        OffsetDateTime::now_utc(),
+        report.delivered as i32,
+        report.suppressed as i32,
+        report.failed as i32,
+    )
+    .execute(conn)
+    .await?;
+
+    info!(?report, "delivered newsletter");
+    Ok(report)
+}
+
+/// What's sent to every recipient of an issue.
+struct Content<'a> {
+    issue_id: Uuid,
+    list_id: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+}
+
+/// Send to `recipients`, counting successes in `report` and returning the recipients that failed.
+async fn send(
+    sender: &BulkSender,
+    content: &Content<'_>,
+    recipients: &[(Uuid, SubscriberEmail)],
+    report: &mut DeliveryReport,
+) -> Vec<(Uuid, SubscriberEmail)> {
+    let emails: Vec<_> = recipients
+        .iter()
+        .map(|(_, email)| {
+            Email::new(
+                email.clone(),
+                EmailKind::Newsletter {
+                    issue_id: content.issue_id,
+                },
+                content.subject,
+                content.html_body,
+                content.text_body,
+            )
+            .header("List-Id", content.list_id)
+            .tag("newsletter")
+            .metadata("issue_id", content.issue_id.to_string())
+        })
+        .collect();
+
+    let mut failed = Vec::new();
+    for (recipient, outcome) in recipients.iter().zip(sender.send(&emails).await) {
+        match outcome {
+            Outcome::Delivered => report.delivered += 1,
+            Outcome::Suppressed => report.suppressed += 1,
+            Outcome::Failed => failed.push(recipient.clone()),
+        }
+    }
+    failed
+}
+
+/// Turn a title into a URL path segment, e.g. "Hello, World!" into "hello-world".
+fn slugify(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+
+    if slug.is_empty() {
+        "issue".to_string()
+    } else {
+        slug
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{issue_url, list_id, slugify};
+
+    #[test]
+    fn slugify_keeps_lowercase_words_separated_by_hyphens() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(
+            slugify("  Issue #12 -- Rust & Postgres  "),
+            "issue-12-rust-postgres"
+        );
+        assert_eq!(slugify("Café crème"), "café-crème");
+        assert_eq!(slugify("!!!"), "issue");
+    }
+
+    #[test]
+    fn issue_url_is_under_the_archive() {
+        let base_url = "https://example.com".parse().unwrap();
+
+        assert_eq!(
+            issue_url(&base_url, "hello-world").as_str(),
+            "https://example.com/newsletters/hello-world"
+        );
+    }
+
+    #[test]
+    fn list_id_is_namespaced_by_the_host() {
+        let base_url = "https://example.com:8080/blog/".parse().unwrap();
+
+        assert_eq!(list_id(&base_url), "<newsletter.example.com>");
+    }
+}
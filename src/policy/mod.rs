@@ -0,0 +1,165 @@
+//! Which email domains may subscribe.
+
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{domain::CanonicalEmail, Error};
+
+/// The disposable domain list bundled with the app.
+pub(crate) const BUNDLED_DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+/// Decides whether an address may subscribe, based on its domain.
+///
+/// Domains match themselves and their subdomains. The allow list takes precedence, so it can be
+/// used to accept domains that are wrongly on the deny or disposable lists.
+#[derive(Clone)]
+pub(crate) struct SubscriptionPolicy {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    allowed: HashSet<String>,
+    denied: HashSet<String>,
+    disposable: HashSet<String>,
+}
+
+impl SubscriptionPolicy {
+    pub(crate) fn new(allowed: &[String], denied: &[String], disposable: &str) -> Self {
+        let normalize = |domains: &[String]| {
+            domains
+                .iter()
+                .map(|domain| normalize_domain(domain))
+                .filter(|domain| !domain.is_empty())
+                .collect()
+        };
+
+        Self {
+            inner: Arc::new(Inner {
+                allowed: normalize(allowed),
+                denied: normalize(denied),
+                disposable: parse_domain_list(disposable),
+            }),
+        }
+    }
+
+    pub(crate) fn check(&self, email: &CanonicalEmail) -> Result<(), Error> {
+        let domain = email
+            .as_ref()
+            .rsplit_once('@')
+            .map_or(email.as_ref(), |(_, domain)| domain);
+
+        if matches(&self.inner.allowed, domain) {
+            Ok(())
+        } else if matches(&self.inner.denied, domain) {
+            Err(Error::Validation(format!(
+                "addresses at {} can't subscribe",
+                domain
+            )))
+        } else if matches(&self.inner.disposable, domain) {
+            Err(Error::Validation(format!(
+                "{} is a disposable email provider, please subscribe with a permanent address",
+                domain
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Parse a list with one domain per line, ignoring blank lines and `#` comments.
+fn parse_domain_list(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .map(normalize_domain)
+        .filter(|domain| !domain.is_empty())
+        .collect()
+}
+
+fn normalize_domain(domain: &str) -> String {
+    let domain = domain.trim().trim_end_matches('.');
+    idna::domain_to_ascii(domain).unwrap_or_else(|_| domain.to_lowercase())
+}
+
+/// Whether `domain` or any of its parent domains are in `domains`.
+fn matches(domains: &HashSet<String>, domain: &str) -> bool {
+    let mut domain = domain;
+    loop {
+        if domains.contains(domain) {
+            return true;
+        }
+        match domain.split_once('.') {
+            Some((_, parent)) => domain = parent,
+            None => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+
+    use crate::domain::{EmailCanonicalizer, SubscriberEmail};
+
+    use super::{SubscriptionPolicy, BUNDLED_DISPOSABLE_DOMAINS};
+
+    fn check(policy: &SubscriptionPolicy, email: &str) -> Result<(), String> {
+        let email = SubscriberEmail::parse(email).unwrap();
+        policy
+            .check(&EmailCanonicalizer::default().canonicalize(&email))
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn bundled_disposable_domains_are_rejected() {
+        let policy = SubscriptionPolicy::new(&[], &[], BUNDLED_DISPOSABLE_DOMAINS);
+
+        assert_err!(check(&policy, "ursula@mailinator.com"));
+        assert_err!(check(&policy, "ursula@Mailinator.COM"));
+        assert_ok!(check(&policy, "ursula@gmail.com"));
+    }
+
+    #[test]
+    fn subdomains_are_matched() {
+        let policy = SubscriptionPolicy::new(&[], &["example.com".to_string()], "");
+
+        assert_err!(check(&policy, "ursula@example.com"));
+        assert_err!(check(&policy, "ursula@mail.example.com"));
+        assert_ok!(check(&policy, "ursula@notexample.com"));
+    }
+
+    #[test]
+    fn allowed_domains_take_precedence() {
+        let policy = SubscriptionPolicy::new(
+            &["yopmail.com".to_string()],
+            &["example.com".to_string()],
+            BUNDLED_DISPOSABLE_DOMAINS,
+        );
+
+        assert_ok!(check(&policy, "ursula@yopmail.com"));
+        assert_err!(check(&policy, "ursula@example.com"));
+    }
+
+    #[test]
+    fn domain_lists_ignore_comments_blank_lines_and_case() {
+        let policy = SubscriptionPolicy::new(&[], &[], "# comment\n\n  Throwaway.example  # why\n");
+
+        assert_err!(check(&policy, "ursula@throwaway.example"));
+        assert_ok!(check(&policy, "ursula@example.com"));
+    }
+
+    #[test]
+    fn rejections_explain_the_reason() {
+        let policy = SubscriptionPolicy::new(
+            &[],
+            &["example.com".to_string()],
+            BUNDLED_DISPOSABLE_DOMAINS,
+        );
+
+        assert_eq!(
+            check(&policy, "ursula@example.com"),
+            Err("addresses at example.com can't subscribe".to_string())
+        );
+        assert!(check(&policy, "ursula@yopmail.com")
+            .unwrap_err()
+            .contains("disposable"));
+    }
+}
@@ -0,0 +1,44 @@
+//! Serde helpers for `OffsetDateTime`s as RFC 3339 strings, for use with `#[serde(with = "...")]`.
+
+use time::{Format, OffsetDateTime};
+
+pub(crate) fn serialize<S>(value: &OffsetDateTime, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_str(&value.lazy_format(Format::Rfc3339))
+}
+
+pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: String = serde::Deserialize::deserialize(deserializer)?;
+    OffsetDateTime::parse(s, Format::Rfc3339).map_err(serde::de::Error::custom)
+}
+
+pub(crate) mod option {
+    use time::{Format, OffsetDateTime};
+
+    pub(crate) fn serialize<S>(
+        value: &Option<OffsetDateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match value {
+            Some(value) => super::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+        s.map(|s| OffsetDateTime::parse(s, Format::Rfc3339).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
@@ -0,0 +1,84 @@
+use axum::{extract::Query, Json};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{auth::Admin, rfc3339, Error, Tx};
+
+/// The most deliveries returned by a single query.
+const MAX_LIMIT: i64 = 1000;
+
+const DEFAULT_LIMIT: i64 = 100;
+
+#[derive(serde::Deserialize)]
+pub(crate) struct DeliveryFilter {
+    /// Only deliveries to this address (compared case-insensitively).
+    recipient: Option<String>,
+    kind: Option<String>,
+    /// Only deliveries of the issue with this slug.
+    issue: Option<String>,
+    /// Only failed (`true`) or successful (`false`) deliveries.
+    failed: Option<bool>,
+    /// Only deliveries requested before this time, for paging back through the log.
+    #[serde(default, deserialize_with = "rfc3339::option::deserialize")]
+    before: Option<OffsetDateTime>,
+    limit: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct DeliveryRecord {
+    id: Uuid,
+    recipient: String,
+    kind: String,
+    issue: Option<String>,
+    message_id: Option<String>,
+    status: Option<i16>,
+    error: Option<String>,
+    #[serde(with = "rfc3339")]
+    requested_at: OffsetDateTime,
+    #[serde(with = "rfc3339")]
+    completed_at: OffsetDateTime,
+}
+
+/// List logged email deliveries, newest first.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn list_deliveries(
+    mut tx: Tx,
+    _: Admin,
+    Query(filter): Query<DeliveryFilter>,
+) -> Result<Json<Vec<DeliveryRecord>>, Error> {
+    let limit = filter.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(Error::Validation(format!(
+            "limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT
+          d.id, d.recipient, d.kind, i.slug AS "issue?", d.message_id, d.status, d.error,
+          d.requested_at, d.completed_at
+        FROM email_deliveries d
+        LEFT JOIN newsletter_issues i ON i.id = d.issue_id
+        WHERE ($1::text IS NULL OR lower(d.recipient) = lower($1))
+          AND ($2::text IS NULL OR d.kind = $2)
+          AND ($3::text IS NULL OR i.slug = $3)
+          AND ($4::bool IS NULL OR (d.error IS NOT NULL) = $4)
+          AND ($5::timestamptz IS NULL OR d.requested_at < $5)
+        ORDER BY d.requested_at DESC, d.id
+        LIMIT $6
+        "#,
+        filter.recipient,
+        filter.kind,
+        filter.issue,
+        filter.failed,
+        filter.before,
+        limit,
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    Ok(Json(deliveries))
+}
@@ -0,0 +1,204 @@
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use time::OffsetDateTime;
+
+use crate::{
+    auth::Admin,
+    newsletters::{self, NewIssue, ScheduleOutcome},
+    rfc3339,
+    scheduler::SchedulerWaker,
+    AppBaseUrl, Error, Tx,
+};
+
+#[derive(serde::Deserialize)]
+pub(crate) struct PublishNewsletter {
+    title: String,
+    html_body: String,
+    text_body: String,
+    slug: Option<String>,
+
+    /// Send the issue at this time, rather than immediately.
+    #[serde(default, deserialize_with = "rfc3339::option::deserialize")]
+    send_at: Option<OffsetDateTime>,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct PublishedNewsletter {
+    slug: String,
+    url: String,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "rfc3339::option::serialize"
+    )]
+    send_at: Option<OffsetDateTime>,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ScheduleRequest {
+    #[serde(with = "rfc3339")]
+    send_at: OffsetDateTime,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct Draft {
+    slug: String,
+    title: String,
+    #[serde(with = "rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(serialize_with = "rfc3339::option::serialize")]
+    send_at: Option<OffsetDateTime>,
+}
+
+/// Publish an issue to the archive and send it to all confirmed subscribers, either now or at the
+/// given `send_at` time.
+///
+/// Either way the issue is sent by the scheduler, so an issue to send now is accepted rather than
+/// delivered by the time this responds.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn publish_newsletter(
+    mut tx: Tx,
+    _: Admin,
+    Extension(base_url): Extension<AppBaseUrl>,
+    Extension(scheduler): Extension<SchedulerWaker>,
+    Json(input): Json<PublishNewsletter>,
+) -> Result<(StatusCode, Json<PublishedNewsletter>), Error> {
+    let issue = NewIssue {
+        title: input.title,
+        html_body: input.html_body,
+        text_body: input.text_body,
+        slug: input.slug,
+        source_url: None,
+    };
+    if let Some(send_at) = input.send_at {
+        check_future(send_at)?;
+    }
+
+    let issue = newsletters::insert_issue(&mut tx, &issue, None)
+        .await?
+        .into_issue();
+    let send_at = input.send_at.unwrap_or_else(OffsetDateTime::now_utc);
+    newsletters::schedule(&mut tx, &issue.slug, Some(send_at)).await?;
+    send_after_commit(tx, &scheduler, input.send_at.is_none()).await?;
+
+    let status = match input.send_at {
+        Some(_) => StatusCode::CREATED,
+        None => StatusCode::ACCEPTED,
+    };
+    Ok((
+        status,
+        Json(PublishedNewsletter {
+            url: newsletters::issue_url(&base_url, &issue.slug).to_string(),
+            slug: issue.slug,
+            send_at: input.send_at,
+        }),
+    ))
+}
+
+/// List issues that are waiting to be approved (e.g. those created from blog posts) or sent at a
+/// scheduled time.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn list_drafts(mut tx: Tx, _: Admin) -> Result<Json<Vec<Draft>>, Error> {
+    let drafts = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT slug, title, created_at, send_at
+        FROM newsletter_issues
+        WHERE published_at IS NULL
+        ORDER BY created_at
+        "#,
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    Ok(Json(drafts))
+}
+
+/// Approve a draft, publishing it to the archive and sending it to all confirmed subscribers.
+///
+/// As with new issues, the draft is sent by the scheduler once this has responded.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn publish_draft(
+    mut tx: Tx,
+    _: Admin,
+    Extension(base_url): Extension<AppBaseUrl>,
+    Extension(scheduler): Extension<SchedulerWaker>,
+    Path(slug): Path<String>,
+) -> Result<Response, Error> {
+    match newsletters::schedule(&mut tx, &slug, Some(OffsetDateTime::now_utc())).await? {
+        ScheduleOutcome::Updated => {}
+        ScheduleOutcome::NotFound | ScheduleOutcome::AlreadyPublished => {
+            return Ok(StatusCode::NOT_FOUND.into_response())
+        }
+    }
+    send_after_commit(tx, &scheduler, true).await?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(PublishedNewsletter {
+            url: newsletters::issue_url(&base_url, &slug).to_string(),
+            slug,
+            send_at: None,
+        }),
+    )
+        .into_response())
+}
+
+/// Schedule a draft to be sent, or reschedule it if it hasn't been sent yet.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn schedule_newsletter(
+    mut tx: Tx,
+    _: Admin,
+    Path(slug): Path<String>,
+    Json(input): Json<ScheduleRequest>,
+) -> Result<Response, Error> {
+    check_future(input.send_at)?;
+    let outcome = newsletters::schedule(&mut tx, &slug, Some(input.send_at)).await?;
+    Ok(schedule_response(outcome))
+}
+
+/// Cancel a scheduled send, leaving the issue as a draft.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn cancel_schedule(
+    mut tx: Tx,
+    _: Admin,
+    Path(slug): Path<String>,
+) -> Result<Response, Error> {
+    let outcome = newsletters::schedule(&mut tx, &slug, None).await?;
+    Ok(schedule_response(outcome))
+}
+
+/// Commit, then wake the scheduler if an issue is due now.
+///
+/// Sending only after committing means an issue is never sent without being recorded, and a
+/// send can't hold the request's transaction open.
+async fn send_after_commit(tx: Tx, scheduler: &SchedulerWaker, due_now: bool) -> Result<(), Error> {
+    tx.commit().await?;
+    if due_now {
+        scheduler.wake();
+    }
+    Ok(())
+}
+
+fn check_future(send_at: OffsetDateTime) -> Result<(), Error> {
+    if send_at <= OffsetDateTime::now_utc() {
+        Err(Error::Validation(
+            "send_at must be in the future".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn schedule_response(outcome: ScheduleOutcome) -> Response {
+    match outcome {
+        ScheduleOutcome::Updated => StatusCode::NO_CONTENT.into_response(),
+        ScheduleOutcome::NotFound => StatusCode::NOT_FOUND.into_response(),
+        ScheduleOutcome::AlreadyPublished => {
+            (StatusCode::CONFLICT, "the issue has already been sent").into_response()
+        }
+    }
+}
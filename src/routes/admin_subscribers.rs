@@ -0,0 +1,98 @@
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::Admin,
+    bulk::BulkSender,
+    domain::{EmailCanonicalizer, SubscriberStatus},
+    erasure,
+    export::{self, ExportFilter, ExportFormat},
+    import::{self, ImportOptions, ImportReport},
+    signing::SigningKey,
+    subscribers::{self, Actor},
+    AppBaseUrl, Error, Tx,
+};
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ExportParams {
+    format: Option<ExportFormat>,
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn export_subscribers(
+    _: Admin,
+    Extension(pool): Extension<sqlx::PgPool>,
+    Query(params): Query<ExportParams>,
+    Query(filter): Query<ExportFilter>,
+) -> Result<Response, Error> {
+    if let Some(status) = &filter.status {
+        status
+            .parse::<SubscriberStatus>()
+            .map_err(|_| Error::Validation(format!("unknown subscriber status: {}", status)))?;
+    }
+
+    let format = params.format.unwrap_or(ExportFormat::Csv);
+    let disposition = format!(
+        "attachment; filename=\"subscribers.{}\"",
+        format.extension()
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        StreamBody::new(export::export(pool, filter, format)),
+    )
+        .into_response())
+}
+
+#[allow(clippy::too_many_arguments)] // extractors
+#[tracing::instrument(skip_all)]
+pub(crate) async fn import_subscribers(
+    mut tx: Tx,
+    _: Admin,
+    Extension(base_url): Extension<AppBaseUrl>,
+    Extension(sender): Extension<BulkSender>,
+    Extension(canonicalizer): Extension<EmailCanonicalizer>,
+    Extension(signing_key): Extension<SigningKey>,
+    Query(options): Query<ImportOptions>,
+    csv: Bytes,
+) -> Result<Json<ImportReport>, Error> {
+    let import = import::import(&mut tx, &canonicalizer, &signing_key, &csv, &options).await?;
+    tx.commit().await?;
+
+    Ok(Json(import.send_confirmations(&sender, &base_url).await))
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn erase_subscriber(
+    mut tx: Tx,
+    _: Admin,
+    Extension(signing_key): Extension<SigningKey>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<StatusCode, Error> {
+    if erasure::erase(&mut tx, &signing_key, &subscriber_id, Actor::Admin).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn subscriber_events(
+    mut tx: Tx,
+    _: Admin,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Response, Error> {
+    match subscribers::list_subscription_events(&mut tx, &subscriber_id).await? {
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+        Some(events) => Ok(Json(events).into_response()),
+    }
+}
@@ -0,0 +1,86 @@
+use axum::{extract::Path, http::StatusCode, Extension, Json};
+use time::OffsetDateTime;
+
+use crate::{
+    auth::Admin,
+    domain::{EmailCanonicalizer, SubscriberEmail},
+    rfc3339, suppressions, Error, Tx,
+};
+
+#[derive(serde::Serialize)]
+pub(crate) struct Suppression {
+    email: String,
+    reason: String,
+    #[serde(with = "rfc3339")]
+    created_at: OffsetDateTime,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct NewSuppression {
+    email: String,
+    reason: String,
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn list_suppressions(
+    mut tx: Tx,
+    _: Admin,
+) -> Result<Json<Vec<Suppression>>, Error> {
+    let suppressions = sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email, reason, created_at
+        FROM suppressions
+        ORDER BY created_at DESC, email
+        "#,
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    Ok(Json(suppressions))
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn add_suppression(
+    mut tx: Tx,
+    _: Admin,
+    Json(input): Json<NewSuppression>,
+) -> Result<StatusCode, Error> {
+    let email = SubscriberEmail::parse(input.email)?;
+    if input.reason.trim().is_empty() {
+        return Err(Error::Validation("a reason is required".to_string()));
+    }
+
+    if suppressions::insert(&mut tx, &email, &input.reason).await? {
+        Ok(StatusCode::CREATED)
+    } else {
+        Ok(StatusCode::OK)
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn remove_suppression(
+    mut tx: Tx,
+    _: Admin,
+    Extension(canonicalizer): Extension<EmailCanonicalizer>,
+    Path(email): Path<String>,
+) -> Result<StatusCode, Error> {
+    let email = SubscriberEmail::parse(email)?;
+    let key = suppressions::key(&email);
+    let canonical_email = canonicalizer.canonicalize(&email);
+
+    // Remove entries keyed on the current canonical form too, as for `suppressions::contains`
+    let result = sqlx::query!(
+        r#"DELETE FROM suppressions WHERE email IN ($1, $2)"#,
+        key.as_ref(),
+        canonical_email.as_ref(),
+    )
+    .execute(&mut tx)
+    .await?;
+
+    if result.rows_affected() > 0 {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::{Duration, SystemTime};
+
+use axum::{
+    extract::TypedHeader,
+    headers::{CacheControl, ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use sha2::{Digest as _, Sha256};
+
+use crate::{
+    feeds::{FeedFormat, FeedIssue},
+    AppBaseUrl, Error, Tx,
+};
+
+/// The number of most recent issues included in feeds.
+const FEED_SIZE: i64 = 20;
+
+/// How long feed readers and caches may reuse a feed without revalidating it.
+const MAX_AGE: Duration = Duration::from_secs(15 * 60);
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn rss_feed(
+    tx: Tx,
+    base_url: Extension<AppBaseUrl>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+    if_modified_since: Option<TypedHeader<IfModifiedSince>>,
+) -> Result<Response, Error> {
+    feed(
+        FeedFormat::Rss,
+        tx,
+        base_url,
+        if_none_match,
+        if_modified_since,
+    )
+    .await
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn atom_feed(
+    tx: Tx,
+    base_url: Extension<AppBaseUrl>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+    if_modified_since: Option<TypedHeader<IfModifiedSince>>,
+) -> Result<Response, Error> {
+    feed(
+        FeedFormat::Atom,
+        tx,
+        base_url,
+        if_none_match,
+        if_modified_since,
+    )
+    .await
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn json_feed(
+    tx: Tx,
+    base_url: Extension<AppBaseUrl>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+    if_modified_since: Option<TypedHeader<IfModifiedSince>>,
+) -> Result<Response, Error> {
+    feed(
+        FeedFormat::Json,
+        tx,
+        base_url,
+        if_none_match,
+        if_modified_since,
+    )
+    .await
+}
+
+async fn feed(
+    format: FeedFormat,
+    mut tx: Tx,
+    Extension(base_url): Extension<AppBaseUrl>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+    if_modified_since: Option<TypedHeader<IfModifiedSince>>,
+) -> Result<Response, Error> {
+    let issues = sqlx::query_as!(
+        FeedIssue,
+        r#"
+        SELECT id, slug, title, html_body, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE published_at IS NOT NULL
+        ORDER BY published_at DESC, id
+        LIMIT $1
+        "#,
+        FEED_SIZE,
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    let body = format.render(&base_url, &issues);
+    let etag: ETag = format!("\"{:x}\"", Sha256::digest(body.as_bytes()))
+        .parse()
+        .unwrap();
+    let last_modified = issues
+        .iter()
+        .map(|issue| SystemTime::from(issue.published_at))
+        .max();
+
+    let mut headers = HeaderMap::new();
+    headers.typed_insert(etag.clone());
+    headers.typed_insert(CacheControl::new().with_public().with_max_age(MAX_AGE));
+    if let Some(last_modified) = last_modified {
+        headers.typed_insert(LastModified::from(last_modified));
+    }
+
+    // If-None-Match takes precedence, as it's more precise (RFC 7232, section 6)
+    let not_modified = match (if_none_match, if_modified_since, last_modified) {
+        (Some(TypedHeader(if_none_match)), _, _) => !if_none_match.precondition_passes(&etag),
+        (None, Some(TypedHeader(if_modified_since)), Some(last_modified)) => {
+            !if_modified_since.is_modified(last_modified)
+        }
+        _ => false,
+    };
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    headers.insert(header::CONTENT_TYPE, format.content_type().parse().unwrap());
+    Ok((headers, body).into_response())
+}
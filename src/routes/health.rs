@@ -1,6 +1,46 @@
+use axum::{Extension, Json};
 use hyper::StatusCode;
+use tracing::warn;
+
+use crate::{circuit_breaker::CircuitState, EmailClient};
+
+#[derive(serde::Serialize)]
+pub(crate) struct Readiness {
+    database: &'static str,
+    /// The email provider's circuit breaker state, which is reported but doesn't affect readiness,
+    /// since the app can do useful work without sending email.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<CircuitState>,
+}
 
 #[tracing::instrument]
 pub(crate) async fn health() -> StatusCode {
     StatusCode::NO_CONTENT
 }
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn ready(
+    Extension(pool): Extension<sqlx::PgPool>,
+    Extension(email_client): Extension<EmailClient>,
+) -> (StatusCode, Json<Readiness>) {
+    let email = email_client.circuit_state();
+    match sqlx::query("SELECT 1").execute(&pool).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(Readiness {
+                database: "ok",
+                email,
+            }),
+        ),
+        Err(error) => {
+            warn!(?error, "database is unavailable");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(Readiness {
+                    database: "unavailable",
+                    email,
+                }),
+            )
+        }
+    }
+}
@@ -1,7 +1,27 @@
+mod admin_deliveries;
+mod admin_newsletters;
+mod admin_subscribers;
+mod admin_suppressions;
+mod feeds;
 mod health;
+mod newsletters;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data_export;
+mod subscriptions_erasure;
+mod webhooks_postmark;
+mod webhooks_publish;
 
+pub(crate) use admin_deliveries::*;
+pub(crate) use admin_newsletters::*;
+pub(crate) use admin_subscribers::*;
+pub(crate) use admin_suppressions::*;
+pub(crate) use feeds::*;
 pub(crate) use health::*;
+pub(crate) use newsletters::*;
 pub(crate) use subscriptions::*;
 pub(crate) use subscriptions_confirm::*;
+pub(crate) use subscriptions_data_export::*;
+pub(crate) use subscriptions_erasure::*;
+pub(crate) use webhooks_postmark::*;
+pub(crate) use webhooks_publish::*;
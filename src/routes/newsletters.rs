@@ -0,0 +1,140 @@
+use std::{fmt::Write as _, num::NonZeroU32};
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use time::OffsetDateTime;
+
+use crate::{
+    html::{layout, Escaped},
+    Error, Tx,
+};
+
+/// The number of issues listed on each page of the archive.
+const PAGE_SIZE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ListParams {
+    page: Option<NonZeroU32>,
+}
+
+struct IssueSummary {
+    slug: String,
+    title: String,
+    published_at: OffsetDateTime,
+}
+
+struct PublishedIssue {
+    title: String,
+    html_body: String,
+    published_at: OffsetDateTime,
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn list_newsletters(
+    mut tx: Tx,
+    Query(params): Query<ListParams>,
+) -> Result<Html<String>, Error> {
+    let page = params.page.map_or(1, NonZeroU32::get);
+
+    // Fetch one extra issue to find out if there's another page
+    let mut issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT slug, title, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE published_at IS NOT NULL
+        ORDER BY published_at DESC, id
+        LIMIT $1 OFFSET $2
+        "#,
+        PAGE_SIZE + 1,
+        (i64::from(page) - 1) * PAGE_SIZE,
+    )
+    .fetch_all(&mut tx)
+    .await?;
+    let has_older = issues.len() as i64 > PAGE_SIZE;
+    issues.truncate(PAGE_SIZE as usize);
+
+    let mut body = String::from("<h1>Newsletter archive</h1>\n");
+    if issues.is_empty() {
+        body.push_str("<p>There are no issues here yet.</p>\n");
+    } else {
+        body.push_str("<ul>\n");
+        for issue in &issues {
+            writeln!(
+                body,
+                r#"<li><a href="/newsletters/{}">{}</a> <time datetime="{}">{}</time></li>"#,
+                Escaped(&issue.slug),
+                Escaped(&issue.title),
+                issue.published_at.format(time::Format::Rfc3339),
+                issue.published_at.format("%F"),
+            )
+            .unwrap();
+        }
+        body.push_str("</ul>\n");
+    }
+
+    body.push_str("<p>\n");
+    if page > 1 {
+        writeln!(
+            body,
+            r#"<a href="/newsletters?page={}">Newer</a>"#,
+            page - 1
+        )
+        .unwrap();
+    }
+    if has_older {
+        writeln!(
+            body,
+            r#"<a href="/newsletters?page={}">Older</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+    body.push_str("</p>\n");
+
+    Ok(layout("Newsletter archive", &body))
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn show_newsletter(
+    mut tx: Tx,
+    Path(slug): Path<String>,
+) -> Result<Response, Error> {
+    let issue = sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT title, html_body, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE slug = $1 AND published_at IS NOT NULL
+        "#,
+        slug,
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+
+    let issue = match issue {
+        None => {
+            return Ok((
+                StatusCode::NOT_FOUND,
+                layout("Not found", "<p>There's no issue here.</p>"),
+            )
+                .into_response())
+        }
+        Some(issue) => issue,
+    };
+
+    // Issue bodies are written by admins, or sanitized when they come from the blog, so they're
+    // trusted
+    let body = format!(
+        "<article>\n<h1>{}</h1>\n<p><time datetime=\"{}\">{}</time></p>\n{}\n</article>",
+        Escaped(&issue.title),
+        issue.published_at.format(time::Format::Rfc3339),
+        issue.published_at.format("%F"),
+        issue.html_body,
+    );
+
+    Ok(layout(&issue.title, &body).into_response())
+}
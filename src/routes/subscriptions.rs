@@ -1,17 +1,34 @@
-use axum::{extract::Form, http::StatusCode, Extension};
-use reqwest::Url;
-use time::OffsetDateTime;
-use uuid::Uuid;
+use axum::{
+    extract::Form,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use tracing::info;
 
 use crate::{
-    domain::{self, NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client, AppBaseUrl, EmailClient, Error, Tx,
+    domain::{
+        self, EmailCanonicalizer, NewSubscriber, SubscriberEmail, SubscriberName, SubscriberStatus,
+    },
+    policy::SubscriptionPolicy,
+    subscribers::{self, Actor},
+    AppBaseUrl, EmailClient, Error, Tx,
 };
 
 #[derive(serde::Deserialize)]
 pub(crate) struct Subscriber {
     name: String,
     email: String,
+
+    /// Subscribe even if the email looks like it has a typo.
+    #[serde(default)]
+    confirm_anyway: bool,
+}
+
+#[derive(serde::Serialize)]
+struct TypoSuggestion {
+    error: String,
+    suggestion: String,
 }
 
 impl TryFrom<Subscriber> for NewSubscriber {
@@ -30,75 +47,50 @@ pub(crate) async fn subscribe(
     mut tx: Tx,
     base_url: Extension<AppBaseUrl>,
     email_client: Extension<EmailClient>,
+    Extension(canonicalizer): Extension<EmailCanonicalizer>,
+    Extension(policy): Extension<SubscriptionPolicy>,
     Form(form): Form<Subscriber>,
-) -> Result<StatusCode, Error> {
-    let subscriber = form.try_into()?;
+) -> Result<Response, Error> {
+    let confirm_anyway = form.confirm_anyway;
+    let subscriber: NewSubscriber = form.try_into()?;
 
-    let subscriber_id = insert_subscriber(&mut tx, &subscriber).await?;
-    let token = insert_subscription_token(&mut tx, &subscriber_id).await?;
-    send_confirmation_email(&base_url, &email_client, subscriber, &token).await?;
+    // Give the subscriber a chance to fix typos, since they'd never receive the confirmation email
+    if let (false, Some(suggestion)) = (confirm_anyway, subscriber.email.suggestion()) {
+        return Ok((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(TypoSuggestion {
+                error: format!(
+                    "did you mean {}? resubmit with confirm_anyway=true to use {}",
+                    suggestion.as_ref(),
+                    subscriber.email.as_ref()
+                ),
+                suggestion: suggestion.as_ref().to_string(),
+            }),
+        )
+            .into_response());
+    }
 
-    Ok(StatusCode::OK)
-}
+    let canonical_email = canonicalizer.canonicalize(&subscriber.email);
+    policy.check(&canonical_email)?;
 
-async fn insert_subscriber(tx: &mut Tx, input: &NewSubscriber) -> Result<Uuid, sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at)
-        VALUES ($1, $2, $3, $4)
-        RETURNING id
-        "#,
-        Uuid::new_v4(),
-        input.email.as_ref(),
-        input.name.as_ref(),
-        OffsetDateTime::now_utc(),
-    )
-    .fetch_one(tx)
-    .await
-    .map(|row| row.id)
-}
+    // Subscribing again succeeds without doing anything, so as not to reveal who's subscribed
+    if subscribers::email_exists(&mut tx, &subscriber.email, &canonical_email).await? {
+        info!("ignoring subscription for an existing subscriber");
+        return Ok(StatusCode::OK.into_response());
+    }
 
-#[tracing::instrument(skip_all)]
-async fn insert_subscription_token(tx: &mut Tx, subscriber_id: &Uuid) -> Result<Uuid, sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO subscription_tokens (id, subscriber_id)
-        VALUES ($1, $2)
-        RETURNING id
-        "#,
-        Uuid::new_v4(),
-        subscriber_id,
+    let subscriber_id = subscribers::insert_subscriber(
+        &mut tx,
+        &subscriber,
+        &canonical_email,
+        SubscriberStatus::Pending,
+        Actor::Subscriber,
+        "subscribed",
     )
-    .fetch_one(tx)
-    .await
-    .map(|row| row.id)
-}
-
-#[tracing::instrument(skip_all)]
-async fn send_confirmation_email(
-    base_url: &Url,
-    email_client: &EmailClient,
-    subscriber: NewSubscriber,
-    token: &Uuid,
-) -> Result<(), email_client::Error> {
-    let mut confirmation_link = base_url.join("/subscriptions/confirm").unwrap();
-    confirmation_link
-        .query_pairs_mut()
-        .append_pair("token", &token.to_string());
-
-    let html_body = format!(
-        "Welcome to our newsletter!<br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link,
-    );
-
-    let text_body = format!(
-        "Welcome to our newsletter!\n\
-        Visit {} to confirm your subscription.",
-        confirmation_link,
-    );
+    .await?;
+    let token = subscribers::insert_subscription_token(&mut tx, &subscriber_id).await?;
+    subscribers::send_confirmation_email(&base_url, &email_client, &subscriber.email, &token)
+        .await?;
 
-    email_client
-        .send_email(subscriber.email, "Welcome!", &html_body, &text_body)
-        .await
+    Ok(StatusCode::OK.into_response())
 }
@@ -1,7 +1,11 @@
 use axum::{extract::Query, http::StatusCode};
 use uuid::Uuid;
 
-use crate::{domain::SubscriberStatus, Error, Tx};
+use crate::{
+    domain::SubscriberStatus,
+    subscribers::{self, Actor},
+    Error, Tx,
+};
 
 #[tracing::instrument(skip_all)]
 pub(crate) async fn confirm(mut tx: Tx, params: Query<Params>) -> Result<StatusCode, Error> {
@@ -10,7 +14,14 @@ pub(crate) async fn confirm(mut tx: Tx, params: Query<Params>) -> Result<StatusC
         Some(id) => id,
     };
 
-    confirm_subscription(&mut tx, &subscriber_id).await?;
+    subscribers::transition_status(
+        &mut tx,
+        &subscriber_id,
+        SubscriberStatus::Confirmed,
+        Actor::Subscriber,
+        "confirmed subscription",
+    )
+    .await??;
 
     Ok(StatusCode::OK)
 }
@@ -34,20 +45,3 @@ async fn get_subscriber_id(
 
     Ok(row.map(|row| row.subscriber_id))
 }
-
-#[tracing::instrument(skip_all)]
-async fn confirm_subscription(tx: &mut Tx, subscriber_id: &Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        UPDATE subscriptions
-        SET status = $1
-        WHERE subscriptions.id = $2
-        "#,
-        SubscriberStatus::Confirmed.as_str(),
-        subscriber_id,
-    )
-    .execute(tx)
-    .await?;
-
-    Ok(())
-}
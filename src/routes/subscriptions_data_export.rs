@@ -0,0 +1,261 @@
+use axum::{
+    extract::{Form, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use time::{Duration, OffsetDateTime};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    deliveries::EmailKind,
+    domain::{EmailCanonicalizer, SubscriberEmail},
+    email_client::Email,
+    rfc3339,
+    signing::SigningKey,
+    subscribers::{self, SubscriptionEvent},
+    AppBaseUrl, EmailClient, Error, Tx,
+};
+
+/// The purpose data export tokens are signed for.
+const PURPOSE: &str = "data-export";
+
+/// How long data export links remain valid.
+const LINK_TTL: Duration = Duration::hours(24);
+
+#[derive(serde::Deserialize)]
+pub(crate) struct DataExportRequest {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct Params {
+    token: String,
+}
+
+/// Everything we hold about a subscriber.
+#[derive(serde::Serialize)]
+pub(crate) struct SubscriberData {
+    #[serde(with = "rfc3339")]
+    exported_at: OffsetDateTime,
+    subscription: Subscription,
+    subscription_tokens: Vec<SubscriptionToken>,
+    subscription_events: Vec<SubscriptionEvent>,
+    consents: Vec<Consent>,
+    email_events: Vec<EmailEvent>,
+    email_deliveries: Vec<EmailDelivery>,
+}
+
+#[derive(serde::Serialize)]
+struct Subscription {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    #[serde(with = "rfc3339")]
+    subscribed_at: OffsetDateTime,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriptionToken {
+    id: Uuid,
+}
+
+#[derive(serde::Serialize)]
+struct Consent {
+    source: String,
+    note: String,
+    #[serde(with = "rfc3339")]
+    recorded_at: OffsetDateTime,
+}
+
+#[derive(serde::Serialize)]
+struct EmailEvent {
+    kind: String,
+    payload: serde_json::Value,
+    #[serde(with = "rfc3339")]
+    received_at: OffsetDateTime,
+}
+
+#[derive(serde::Serialize)]
+struct EmailDelivery {
+    kind: String,
+    message_id: Option<String>,
+    status: Option<i16>,
+    error: Option<String>,
+    #[serde(with = "rfc3339")]
+    requested_at: OffsetDateTime,
+    #[serde(with = "rfc3339")]
+    completed_at: OffsetDateTime,
+}
+
+/// Email a signed data export link to a subscriber.
+///
+/// This always succeeds for a valid email, so that it can't be used to discover who is subscribed.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn request_data_export(
+    mut tx: Tx,
+    Extension(base_url): Extension<AppBaseUrl>,
+    Extension(email_client): Extension<EmailClient>,
+    Extension(signing_key): Extension<SigningKey>,
+    Extension(canonicalizer): Extension<EmailCanonicalizer>,
+    Form(form): Form<DataExportRequest>,
+) -> Result<StatusCode, Error> {
+    let email = SubscriberEmail::parse(form.email)?;
+
+    let canonical_email = canonicalizer.canonicalize(&email);
+    let subscriber_id =
+        match subscribers::find_subscriber_id(&mut tx, &email, &canonical_email).await? {
+            None => return Ok(StatusCode::OK),
+            Some(id) => id,
+        };
+
+    let token = signing_key.sign(
+        PURPOSE,
+        &subscriber_id.to_string(),
+        OffsetDateTime::now_utc() + LINK_TTL,
+    );
+    let mut link = base_url.join("/subscriptions/data-export").unwrap();
+    link.query_pairs_mut().append_pair("token", &token);
+
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> to download the data we hold about you.<br />\
+        The link expires in 24 hours.",
+        link,
+    );
+    let text_body = format!(
+        "Visit {} to download the data we hold about you.\n\
+        The link expires in 24 hours.",
+        link,
+    );
+
+    if let Err(error) = email_client
+        .send_email(&Email::new(
+            email,
+            EmailKind::DataExport,
+            "Your data export",
+            &html_body,
+            &text_body,
+        ))
+        .await
+    {
+        warn!(%subscriber_id, ?error, "failed to send data export email");
+    }
+
+    Ok(StatusCode::OK)
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn data_export(
+    mut tx: Tx,
+    Extension(signing_key): Extension<SigningKey>,
+    Query(params): Query<Params>,
+) -> Result<Response, Error> {
+    let subscriber_id = match signing_key
+        .verify(PURPOSE, &params.token, OffsetDateTime::now_utc())
+        .ok()
+        .and_then(|subject| subject.parse::<Uuid>().ok())
+    {
+        None => return Ok(StatusCode::UNAUTHORIZED.into_response()),
+        Some(id) => id,
+    };
+
+    let data = match get_subscriber_data(&mut tx, &subscriber_id).await? {
+        None => return Ok(StatusCode::NOT_FOUND.into_response()),
+        Some(data) => data,
+    };
+
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"subscriber-data.json\"",
+        )],
+        Json(data),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_subscriber_data(
+    tx: &mut Tx,
+    subscriber_id: &Uuid,
+) -> Result<Option<SubscriberData>, sqlx::Error> {
+    let subscription = match sqlx::query_as!(
+        Subscription,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    {
+        None => return Ok(None),
+        Some(subscription) => subscription,
+    };
+
+    let subscription_tokens = sqlx::query_as!(
+        SubscriptionToken,
+        r#"SELECT id FROM subscription_tokens WHERE subscriber_id = $1 ORDER BY id"#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // The subscriber was found above, in the same transaction
+    let subscription_events = subscribers::list_subscription_events(&mut *tx, subscriber_id)
+        .await?
+        .unwrap_or_default();
+
+    let consents = sqlx::query_as!(
+        Consent,
+        r#"
+        SELECT source, note, recorded_at
+        FROM consents
+        WHERE subscriber_id = $1
+        ORDER BY recorded_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let email_events = sqlx::query_as!(
+        EmailEvent,
+        r#"
+        SELECT kind, payload, received_at
+        FROM email_events
+        WHERE subscriber_id = $1
+        ORDER BY received_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let email_deliveries = sqlx::query_as!(
+        EmailDelivery,
+        r#"
+        SELECT kind, message_id, status, error, requested_at, completed_at
+        FROM email_deliveries
+        WHERE lower(recipient) = lower($1)
+        ORDER BY requested_at
+        "#,
+        subscription.email,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    Ok(Some(SubscriberData {
+        exported_at: OffsetDateTime::now_utc(),
+        subscription,
+        subscription_tokens,
+        subscription_events,
+        consents,
+        email_events,
+        email_deliveries,
+    }))
+}
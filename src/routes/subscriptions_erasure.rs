@@ -0,0 +1,141 @@
+use axum::{
+    extract::{Form, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use time::{Duration, OffsetDateTime};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    deliveries::EmailKind,
+    domain::{EmailCanonicalizer, SubscriberEmail},
+    email_client::Email,
+    erasure,
+    html::{layout, Escaped},
+    signing::SigningKey,
+    subscribers::{self, Actor},
+    AppBaseUrl, EmailClient, Error, Tx,
+};
+
+/// The purpose erasure tokens are signed for.
+const PURPOSE: &str = "erasure";
+
+/// How long erasure links remain valid.
+const LINK_TTL: Duration = Duration::hours(24);
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ErasureRequest {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct ErasureConfirmation {
+    token: String,
+}
+
+/// Email a signed erasure link to a subscriber.
+///
+/// This always succeeds for a valid email, so that it can't be used to discover who is subscribed.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn request_erasure(
+    mut tx: Tx,
+    Extension(base_url): Extension<AppBaseUrl>,
+    Extension(email_client): Extension<EmailClient>,
+    Extension(signing_key): Extension<SigningKey>,
+    Extension(canonicalizer): Extension<EmailCanonicalizer>,
+    Form(form): Form<ErasureRequest>,
+) -> Result<StatusCode, Error> {
+    let email = SubscriberEmail::parse(form.email)?;
+
+    let canonical_email = canonicalizer.canonicalize(&email);
+    let subscriber_id =
+        match subscribers::find_subscriber_id(&mut tx, &email, &canonical_email).await? {
+            None => return Ok(StatusCode::OK),
+            Some(id) => id,
+        };
+
+    let token = signing_key.sign(
+        PURPOSE,
+        &subscriber_id.to_string(),
+        OffsetDateTime::now_utc() + LINK_TTL,
+    );
+    let mut link = base_url.join("/subscriptions/erasure/confirm").unwrap();
+    link.query_pairs_mut().append_pair("token", &token);
+
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> to permanently erase your subscription and all the data \
+        we hold about you.<br />\
+        The link expires in 24 hours.",
+        link,
+    );
+    let text_body = format!(
+        "Visit {} to permanently erase your subscription and all the data we hold about you.\n\
+        The link expires in 24 hours.",
+        link,
+    );
+
+    if let Err(error) = email_client
+        .send_email(&Email::new(
+            email,
+            EmailKind::Erasure,
+            "Erase your data",
+            &html_body,
+            &text_body,
+        ))
+        .await
+    {
+        warn!(%subscriber_id, ?error, "failed to send erasure email");
+    }
+
+    Ok(StatusCode::OK)
+}
+
+/// Ask the subscriber to confirm erasure.
+///
+/// Erasure itself needs a `POST`, so that it isn't triggered by link scanners or prefetching.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn erasure_form(
+    Extension(signing_key): Extension<SigningKey>,
+    Query(params): Query<ErasureConfirmation>,
+) -> Response {
+    if verify(&signing_key, &params.token).is_none() {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let body = format!(
+        r#"<p>This will permanently erase your subscription and all the data we hold about you.</p>
+<form method="post" action="/subscriptions/erasure/confirm">
+  <input type="hidden" name="token" value="{}">
+  <button type="submit">Erase my data</button>
+</form>"#,
+        Escaped(&params.token),
+    );
+    layout("Erase your data", &body).into_response()
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn confirm_erasure(
+    mut tx: Tx,
+    Extension(signing_key): Extension<SigningKey>,
+    Form(form): Form<ErasureConfirmation>,
+) -> Result<Response, Error> {
+    let subscriber_id = match verify(&signing_key, &form.token) {
+        None => return Ok(StatusCode::UNAUTHORIZED.into_response()),
+        Some(id) => id,
+    };
+
+    if erasure::erase(&mut tx, &signing_key, &subscriber_id, Actor::Subscriber).await? {
+        Ok(layout("Erase your data", "<p>Your data has been erased.</p>").into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
+
+fn verify(signing_key: &SigningKey, token: &str) -> Option<Uuid> {
+    signing_key
+        .verify(PURPOSE, token, OffsetDateTime::now_utc())
+        .ok()
+        .and_then(|subject| subject.parse().ok())
+}
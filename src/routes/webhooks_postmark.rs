@@ -0,0 +1,159 @@
+use axum::{http::StatusCode, Extension, Json};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    auth::PostmarkWebhook,
+    domain::{EmailCanonicalizer, SubscriberEmail, SubscriberStatus},
+    subscribers::{self, Actor},
+    suppressions, Error, Tx,
+};
+
+/// The subset of Postmark's webhook payloads that we act on.
+///
+/// See https://postmarkapp.com/developer/webhooks/webhooks-overview.
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "RecordType")]
+enum PostmarkEvent {
+    #[serde(rename_all = "PascalCase")]
+    Bounce {
+        #[serde(rename = "Type")]
+        kind: String,
+        email: String,
+        #[serde(default)]
+        inactive: bool,
+    },
+    #[serde(rename_all = "PascalCase")]
+    SpamComplaint { email: String },
+    #[serde(rename_all = "PascalCase")]
+    SubscriptionChange {
+        recipient: String,
+        suppress_sending: bool,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl PostmarkEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Bounce { .. } => "bounce",
+            Self::SpamComplaint { .. } => "spam_complaint",
+            Self::SubscriptionChange { .. } => "subscription_change",
+            Self::Other => "other",
+        }
+    }
+
+    fn email(&self) -> Option<&str> {
+        match self {
+            Self::Bounce { email, .. } | Self::SpamComplaint { email } => Some(email),
+            Self::SubscriptionChange { recipient, .. } => Some(recipient),
+            Self::Other => None,
+        }
+    }
+
+    /// The status the affected subscriber should be moved to, if any.
+    fn status(&self) -> Option<SubscriberStatus> {
+        match self {
+            Self::Bounce { kind, inactive, .. } if *inactive || kind == "HardBounce" => {
+                Some(SubscriberStatus::Bounced)
+            }
+            Self::SpamComplaint { .. } => Some(SubscriberStatus::Complained),
+            Self::SubscriptionChange {
+                suppress_sending: true,
+                ..
+            } => Some(SubscriberStatus::Unsubscribed),
+            _ => None,
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn postmark_webhook(
+    mut tx: Tx,
+    _: PostmarkWebhook,
+    Extension(canonicalizer): Extension<EmailCanonicalizer>,
+    Json(payload): Json<serde_json::Value>,
+) -> Result<StatusCode, Error> {
+    let event: PostmarkEvent = serde_json::from_value(payload.clone())
+        .map_err(|error| Error::Validation(error.to_string()))?;
+
+    // Postmark retries webhooks that aren't acknowledged, and would never accept this one
+    let email = match event.email().map(SubscriberEmail::parse) {
+        None => return Ok(StatusCode::OK),
+        Some(Err(error)) => {
+            warn!(
+                kind = event.kind(),
+                %error,
+                "received postmark event with an invalid recipient"
+            );
+            return Ok(StatusCode::OK);
+        }
+        Some(Ok(email)) => email,
+    };
+    let canonical_email = canonicalizer.canonicalize(&email);
+
+    // Suppression applies to the address, whether or not it belongs to a current subscriber
+    if let Some(status) = event.status() {
+        suppressions::insert(&mut tx, &email, status.as_str()).await?;
+    }
+
+    // Postmark retries webhooks that aren't acknowledged, so unknown recipients are still a success
+    let subscriber_id =
+        match subscribers::find_subscriber_id(&mut tx, &email, &canonical_email).await? {
+            None => {
+                warn!(
+                    kind = event.kind(),
+                    "received postmark event for unknown recipient"
+                );
+                return Ok(StatusCode::OK);
+            }
+            Some(id) => id,
+        };
+
+    insert_email_event(&mut tx, &subscriber_id, event.kind(), &payload).await?;
+
+    if let Some(status) = event.status() {
+        info!(%subscriber_id, status = status.as_str(), "suppressing subscriber");
+        let transition = subscribers::transition_status(
+            &mut tx,
+            &subscriber_id,
+            status,
+            Actor::Postmark,
+            event.kind(),
+        )
+        .await?;
+
+        // The address is suppressed regardless, so there's nothing more to do
+        if let Err(error) = transition {
+            warn!(%subscriber_id, %error, "ignoring status change from postmark event");
+        }
+    }
+
+    Ok(StatusCode::OK)
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_email_event(
+    tx: &mut Tx,
+    subscriber_id: &Uuid,
+    kind: &str,
+    payload: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (id, subscriber_id, kind, payload, received_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        kind,
+        payload,
+        OffsetDateTime::now_utc(),
+    )
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use time::OffsetDateTime;
+use tracing::info;
+
+use crate::{
+    auth::{PublishWebhook, PUBLISH_WEBHOOK_TOLERANCE},
+    html::{sanitize, to_text, Escaped},
+    newsletters::{self, Inserted, NewIssue},
+    scheduler::SchedulerWaker,
+    AppBaseUrl, Error, Tx,
+};
+
+/// A post that has just gone live on the blog.
+#[derive(serde::Deserialize)]
+pub(crate) struct PublishedPost {
+    title: String,
+    url: String,
+    excerpt: Option<String>,
+
+    /// The full post, which is sent instead of the excerpt if given.
+    html: Option<String>,
+
+    /// Send the issue immediately, rather than leaving a draft for an admin to approve.
+    #[serde(default)]
+    send: bool,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct CreatedIssue {
+    slug: String,
+    url: String,
+    published: bool,
+}
+
+impl PublishedPost {
+    fn new_issue(&self) -> Result<NewIssue, Error> {
+        let (html_body, text_body) = match (&self.html, &self.excerpt) {
+            // Sanitized like feed HTML, in case the blog, or whoever holds the secret, is
+            // compromised
+            (Some(html), _) => {
+                let html = sanitize(html.trim());
+                let text = to_text(&html);
+                (html, text)
+            }
+            (None, Some(excerpt)) => (
+                format!("<p>{}</p>", Escaped(excerpt.trim())),
+                excerpt.trim().to_string(),
+            ),
+            (None, None) => {
+                return Err(Error::Validation(
+                    "either html or excerpt is required".to_string(),
+                ))
+            }
+        };
+
+        Ok(NewIssue {
+            title: self.title.clone(),
+            html_body: format!(
+                "{}\n<p><a href=\"{}\">Read this post on the blog</a></p>",
+                html_body,
+                Escaped(&self.url)
+            ),
+            text_body: format!("{}\n\nRead this post on the blog: {}", text_body, self.url),
+            slug: None,
+            source_url: Some(self.url.clone()),
+        })
+    }
+}
+
+/// Turn a post announced by the blog's CI into a newsletter issue.
+///
+/// A post that already has an issue, e.g. from the blog feed poller, is left as it is, and the
+/// existing issue is returned.
+///
+/// Issues to send are handed to the scheduler once committed, so the response doesn't wait for
+/// delivery.
+#[tracing::instrument(skip_all, fields(nonce = %webhook.nonce))]
+pub(crate) async fn publish_webhook(
+    mut tx: Tx,
+    webhook: PublishWebhook,
+    Extension(base_url): Extension<AppBaseUrl>,
+    Extension(scheduler): Extension<SchedulerWaker>,
+) -> Result<Response, Error> {
+    let post: PublishedPost = serde_json::from_slice(&webhook.body)
+        .map_err(|error| Error::Validation(format!("invalid post: {}", error)))?;
+
+    // Nonces are forgotten once their signed timestamp is too old to be accepted, which may be
+    // well after they were received if the request was signed with a timestamp in the future
+    let now = OffsetDateTime::now_utc();
+    sqlx::query!(
+        "DELETE FROM publish_webhook_nonces WHERE signed_at < $1",
+        now - PUBLISH_WEBHOOK_TOLERANCE,
+    )
+    .execute(&mut tx)
+    .await?;
+    let replayed = sqlx::query!(
+        r#"
+        INSERT INTO publish_webhook_nonces (nonce, received_at, signed_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        webhook.nonce,
+        now,
+        webhook.signed_at,
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected()
+        == 0;
+    if replayed {
+        return Ok((StatusCode::CONFLICT, "nonce has already been used").into_response());
+    }
+
+    let issue = match newsletters::insert_issue(&mut tx, &post.new_issue()?, None).await? {
+        Inserted::Created(issue) => issue,
+        Inserted::Existing(issue) => {
+            let published = sqlx::query!(
+                r#"
+                SELECT published_at IS NOT NULL OR send_at IS NOT NULL AS "published!"
+                FROM newsletter_issues
+                WHERE id = $1
+                "#,
+                issue.id,
+            )
+            .fetch_one(&mut tx)
+            .await?
+            .published;
+            info!(slug = %issue.slug, post_url = %post.url, "post already has an issue");
+
+            // Commit to remember the nonce
+            tx.commit().await?;
+            return Ok(Json(CreatedIssue {
+                url: newsletters::issue_url(&base_url, &issue.slug).to_string(),
+                slug: issue.slug,
+                published,
+            })
+            .into_response());
+        }
+    };
+    if post.send {
+        newsletters::schedule(&mut tx, &issue.slug, Some(now)).await?;
+    }
+    info!(slug = %issue.slug, post_url = %post.url, published = post.send, "created issue for post");
+
+    // Commit before sending, so that a retry after a failed commit can't send the post twice
+    tx.commit().await?;
+    let status = if post.send {
+        scheduler.wake();
+        StatusCode::ACCEPTED
+    } else {
+        StatusCode::CREATED
+    };
+
+    Ok((
+        status,
+        Json(CreatedIssue {
+            url: newsletters::issue_url(&base_url, &issue.slug).to_string(),
+            slug: issue.slug,
+            published: post.send,
+        }),
+    )
+        .into_response())
+}
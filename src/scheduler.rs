@@ -0,0 +1,86 @@
+//! Sending scheduled newsletter issues once they're due.
+
+use std::{sync::Arc, time::Duration};
+
+use reqwest::Url;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tokio::sync::Notify;
+use tracing::{error, info};
+
+use crate::{bulk::BulkSender, newsletters, Error};
+
+pub(crate) struct Scheduler {
+    pool: PgPool,
+    sender: BulkSender,
+    base_url: Url,
+    interval: Duration,
+    wake: Arc<Notify>,
+}
+
+/// Wakes the scheduler to check for due issues before its next interval, e.g. once an issue that
+/// should be sent now has been committed.
+#[derive(Clone)]
+pub(crate) struct SchedulerWaker(Arc<Notify>);
+
+impl SchedulerWaker {
+    pub(crate) fn wake(&self) {
+        self.0.notify_one();
+    }
+}
+
+impl Scheduler {
+    pub(crate) fn new(pool: PgPool, sender: BulkSender, base_url: Url, interval: Duration) -> Self {
+        Self {
+            pool,
+            sender,
+            base_url,
+            interval,
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    pub(crate) fn waker(&self) -> SchedulerWaker {
+        SchedulerWaker(self.wake.clone())
+    }
+
+    /// Check for due issues forever, whenever woken or the interval passes, logging any failures.
+    pub(crate) async fn run(self) {
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.wake.notified() => {}
+            }
+            if let Err(error) = self.send_due_issues().await {
+                error!(%error, "failed to send scheduled issues");
+            }
+        }
+    }
+
+    /// Publish and send every issue that's due, returning how many were sent.
+    ///
+    /// Each issue is claimed (and published) before it's delivered, so it's sent at most once
+    /// even if delivery is interrupted.
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn send_due_issues(&self) -> Result<usize, Error> {
+        let mut sent = 0;
+        loop {
+            let mut conn = self.pool.acquire().await?;
+            let issue =
+                match newsletters::claim_due_issue(&mut conn, OffsetDateTime::now_utc()).await? {
+                    None => return Ok(sent),
+                    Some(issue) => issue,
+                };
+
+            info!(slug = %issue.slug, "sending scheduled issue");
+            match newsletters::deliver(&mut conn, &self.sender, &self.base_url, &issue).await {
+                Ok(_) => sent += 1,
+                // Carry on with any other due issues, rather than leaving them until next time
+                Err(error) => error!(slug = %issue.slug, %error, "failed to deliver issue"),
+            }
+        }
+    }
+}
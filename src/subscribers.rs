@@ -0,0 +1,318 @@
+//! Database and email operations on subscribers that are shared between routes and commands.
+
+use reqwest::Url;
+use sqlx::PgConnection;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    deliveries::EmailKind,
+    domain::{self, CanonicalEmail, NewSubscriber, SubscriberEmail, SubscriberStatus},
+    email_client::{self, Email},
+    rfc3339, EmailClient,
+};
+
+/// Who caused a change to a subscriber.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Actor {
+    Subscriber,
+    Admin,
+    Postmark,
+}
+
+impl Actor {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Subscriber => "subscriber",
+            Self::Admin => "admin",
+            Self::Postmark => "postmark",
+        }
+    }
+}
+
+#[tracing::instrument(skip(conn, input))]
+pub(crate) async fn insert_subscriber(
+    conn: &mut PgConnection,
+    input: &NewSubscriber,
+    canonical_email: &CanonicalEmail,
+    status: SubscriberStatus,
+    actor: Actor,
+    cause: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let subscriber_id = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, email_canonical, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id
+        "#,
+        Uuid::new_v4(),
+        input.email.as_ref(),
+        canonical_email.as_ref(),
+        input.name.as_ref(),
+        OffsetDateTime::now_utc(),
+        status.as_str(),
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .id;
+
+    insert_subscription_event(conn, &subscriber_id, None, status, actor, cause).await?;
+
+    Ok(subscriber_id)
+}
+
+/// Move a subscriber to a new status, recording the transition.
+///
+/// Returns `Ok(false)` if the subscriber already has the status, or an error if the transition is
+/// not allowed.
+#[tracing::instrument(skip(conn))]
+pub(crate) async fn transition_status(
+    conn: &mut PgConnection,
+    subscriber_id: &Uuid,
+    to: SubscriberStatus,
+    actor: Actor,
+    cause: &str,
+) -> Result<Result<bool, domain::Error>, crate::Error> {
+    let row = sqlx::query!(
+        r#"SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    let from: SubscriberStatus = row.status.parse()?;
+
+    if from == to {
+        return Ok(Ok(false));
+    }
+    if let Err(error) = from.transition(to) {
+        return Ok(Err(error));
+    }
+
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $1 WHERE id = $2"#,
+        to.as_str(),
+        subscriber_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    insert_subscription_event(conn, subscriber_id, Some(from), to, actor, cause).await?;
+
+    Ok(Ok(true))
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct SubscriptionEvent {
+    from_status: Option<String>,
+    to_status: String,
+    actor: String,
+    cause: String,
+    #[serde(with = "rfc3339")]
+    occurred_at: OffsetDateTime,
+}
+
+/// List a subscriber's status transitions, oldest first, or `None` if they don't exist.
+#[tracing::instrument(skip(conn))]
+pub(crate) async fn list_subscription_events(
+    conn: &mut PgConnection,
+    subscriber_id: &Uuid,
+) -> Result<Option<Vec<SubscriptionEvent>>, sqlx::Error> {
+    let exists = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM subscriptions WHERE id = $1) AS "exists!""#,
+        subscriber_id,
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .exists;
+    if !exists {
+        return Ok(None);
+    }
+
+    let events = sqlx::query_as!(
+        SubscriptionEvent,
+        r#"
+        SELECT from_status, to_status, actor, cause, occurred_at
+        FROM subscription_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at, id
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(Some(events))
+}
+
+#[tracing::instrument(skip(conn))]
+async fn insert_subscription_event(
+    conn: &mut PgConnection,
+    subscriber_id: &Uuid,
+    from: Option<SubscriberStatus>,
+    to: SubscriberStatus,
+    actor: Actor,
+    cause: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_events
+          (id, subscriber_id, from_status, to_status, actor, cause, occurred_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        from.map(SubscriberStatus::as_str),
+        to.as_str(),
+        actor.as_str(),
+        cause,
+        OffsetDateTime::now_utc(),
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Whether a subscriber already exists with an equivalent email.
+///
+/// Subscribers without a canonical email (see [`find_subscriber_id`]) are matched on their
+/// lower-cased email instead.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn email_exists(
+    conn: &mut PgConnection,
+    email: &SubscriberEmail,
+    canonical_email: &CanonicalEmail,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+          SELECT 1
+          FROM subscriptions
+          WHERE email_canonical = $1
+             OR (email_canonical IS NULL AND lower(trim(email)) = lower($2))
+        ) AS "exists!"
+        "#,
+        canonical_email.as_ref(),
+        email.as_ref(),
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(row.exists)
+}
+
+/// Find the subscriber with an equivalent email.
+///
+/// Some subscribers have no canonical email, because they collided with another subscriber when
+/// the column was added, so they're matched on their lower-cased email instead. If several
+/// subscribers match, the one with a canonical email is preferred, then the oldest.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn find_subscriber_id(
+    conn: &mut PgConnection,
+    email: &SubscriberEmail,
+    canonical_email: &CanonicalEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT id
+        FROM subscriptions
+        WHERE email_canonical = $1
+           OR (email_canonical IS NULL AND lower(trim(email)) = lower($2))
+        ORDER BY email_canonical IS NULL, subscribed_at
+        LIMIT 1
+        "#,
+        canonical_email.as_ref(),
+        email.as_ref(),
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(row.map(|row| row.id))
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn insert_subscription_token(
+    conn: &mut PgConnection,
+    subscriber_id: &Uuid,
+) -> Result<Uuid, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (id, subscriber_id)
+        VALUES ($1, $2)
+        RETURNING id
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+    )
+    .fetch_one(conn)
+    .await
+    .map(|row| row.id)
+}
+
+/// Record how consent to email a subscriber was obtained.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn insert_consent(
+    conn: &mut PgConnection,
+    subscriber_id: &Uuid,
+    source: &str,
+    note: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consents (id, subscriber_id, source, note, recorded_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        source,
+        note,
+        OffsetDateTime::now_utc(),
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn send_confirmation_email(
+    base_url: &Url,
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    token: &Uuid,
+) -> Result<(), email_client::Error> {
+    let (html_body, text_body) = confirmation_email_bodies(base_url, token);
+    email_client
+        .send_email(&Email::new(
+            email.clone(),
+            EmailKind::Confirmation,
+            CONFIRMATION_EMAIL_SUBJECT,
+            &html_body,
+            &text_body,
+        ))
+        .await
+}
+
+pub(crate) const CONFIRMATION_EMAIL_SUBJECT: &str = "Welcome!";
+
+/// The HTML and text bodies of a confirmation email with a link for `token`.
+pub(crate) fn confirmation_email_bodies(base_url: &Url, token: &Uuid) -> (String, String) {
+    let mut confirmation_link = base_url.join("/subscriptions/confirm").unwrap();
+    confirmation_link
+        .query_pairs_mut()
+        .append_pair("token", &token.to_string());
+
+    let html_body = format!(
+        "Welcome to our newsletter!<br />\
+        Click <a href=\"{}\">here</a> to confirm your subscription.",
+        confirmation_link,
+    );
+
+    let text_body = format!(
+        "Welcome to our newsletter!\n\
+        Visit {} to confirm your subscription.",
+        confirmation_link,
+    );
+
+    (html_body, text_body)
+}
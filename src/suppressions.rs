@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+
+use sqlx::{postgres::PgExecutor, PgConnection};
+use time::OffsetDateTime;
+
+use crate::domain::{CanonicalEmail, EmailCanonicalizer, SubscriberEmail};
+
+/// Handle to the global list of addresses that must never be emailed.
+///
+/// Entries are keyed on the address with only the canonicalization rules that always apply (see
+/// [`key`]), so they keep matching when optional rules are turned on or off. They are kept even if
+/// the matching subscriber is deleted.
+#[derive(Clone)]
+pub(crate) struct Suppressions {
+    pool: sqlx::PgPool,
+    canonicalizer: EmailCanonicalizer,
+}
+
+impl Suppressions {
+    pub(crate) fn new(pool: sqlx::PgPool, canonicalizer: EmailCanonicalizer) -> Self {
+        Self {
+            pool,
+            canonicalizer,
+        }
+    }
+
+    pub(crate) async fn contains(&self, email: &SubscriberEmail) -> Result<bool, sqlx::Error> {
+        contains(&self.pool, email, &self.canonicalizer.canonicalize(email)).await
+    }
+
+    /// Whether each of `emails` is on the suppression list, in the same order, with one query.
+    pub(crate) async fn contains_each(
+        &self,
+        emails: &[&SubscriberEmail],
+    ) -> Result<Vec<bool>, sqlx::Error> {
+        // Like `contains`, each address is looked up by its key and its current canonical form
+        let candidates: Vec<_> = emails
+            .iter()
+            .map(|email| {
+                (
+                    key(email).as_ref().to_string(),
+                    self.canonicalizer.canonicalize(email).as_ref().to_string(),
+                )
+            })
+            .collect();
+        let lookups: Vec<_> = candidates
+            .iter()
+            .flat_map(|(key, canonical)| [key.clone(), canonical.clone()])
+            .collect();
+
+        let suppressed: HashSet<_> = sqlx::query!(
+            "SELECT email FROM suppressions WHERE email = ANY($1)",
+            &lookups[..],
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| row.email)
+        .collect();
+
+        Ok(candidates
+            .iter()
+            .map(|(key, canonical)| suppressed.contains(key) || suppressed.contains(canonical))
+            .collect())
+    }
+}
+
+/// The key for an address on the suppression list.
+///
+/// This is the canonical address under the default rules, which don't depend on configuration.
+pub(crate) fn key(email: &SubscriberEmail) -> CanonicalEmail {
+    EmailCanonicalizer::default().canonicalize(email)
+}
+
+/// Whether an address is on the suppression list.
+///
+/// Entries added before suppressions were keyed with [`key`] used the canonical address under the
+/// rules at the time, so the address's current canonical form is checked too.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn contains<'c>(
+    executor: impl PgExecutor<'c>,
+    email: &SubscriberEmail,
+    canonical_email: &CanonicalEmail,
+) -> Result<bool, sqlx::Error> {
+    let key = key(email);
+    let row = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM suppressions WHERE email IN ($1, $2)) AS "exists!""#,
+        key.as_ref(),
+        canonical_email.as_ref(),
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(row.exists)
+}
+
+/// Add an address to the suppression list, returning `false` if it was already present.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn insert(
+    conn: &mut PgConnection,
+    email: &SubscriberEmail,
+    reason: &str,
+) -> Result<bool, sqlx::Error> {
+    let key = key(email);
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email, reason, created_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        key.as_ref(),
+        reason,
+        OffsetDateTime::now_utc(),
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -75,6 +75,7 @@ pub(crate) fn id_layer<S>() -> IdLayer<S> {
     IdLayer(PhantomData)
 }
 
+#[allow(clippy::type_complexity)]
 pub(crate) fn trace_layer() -> TraceLayer<
     SharedClassifier<Classifier>,
     impl (FnMut(&Request<Body>) -> Span) + Clone,
@@ -84,7 +85,7 @@ pub(crate) fn trace_layer() -> TraceLayer<
     DefaultOnEos,
     impl FnMut(Arc<Report>, Duration, &Span) + Clone,
 > {
-    TraceLayer::new(SharedClassifier::new(Classifier::default()))
+    TraceLayer::new(SharedClassifier::new(Classifier))
         .make_span_with(|request: &Request<Body>| {
             let id = request
                 .extensions()
@@ -0,0 +1,4 @@
+mod deliveries;
+mod newsletters;
+mod subscribers;
+mod suppressions;
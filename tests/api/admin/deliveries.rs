@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use reqwest::Method;
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{AcceptBatch, TestApp};
+
+#[tokio::test]
+async fn deliveries_rejects_requests_without_the_admin_token() {
+    let app = TestApp::spawn().await;
+
+    let response = reqwest::get(app.base_url.join("/admin/deliveries").unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn sent_emails_are_logged_with_the_provider_message_id() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "To": "ursula_le_guin@gmail.com",
+            "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+            "ErrorCode": 0,
+            "Message": "OK",
+        })))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let deliveries = list_deliveries(&app, "").await;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0]["recipient"], "ursula_le_guin@gmail.com");
+    assert_eq!(deliveries[0]["kind"], "confirmation");
+    assert_eq!(
+        deliveries[0]["message_id"],
+        "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"
+    );
+    assert_eq!(deliveries[0]["status"], 200);
+    assert!(deliveries[0]["error"].is_null());
+    assert!(deliveries[0]["issue"].is_null());
+    assert!(deliveries[0]["requested_at"].is_string());
+    assert!(deliveries[0]["completed_at"].is_string());
+}
+
+#[tokio::test]
+async fn rejected_emails_are_logged_with_the_provider_error() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(json!({
+            "ErrorCode": 406,
+            "Message": "You tried to send to a recipient that has been marked as inactive.",
+        })))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+
+    let deliveries = list_deliveries(&app, "?failed=true").await;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0]["status"], 422);
+    assert!(deliveries[0]["message_id"].is_null());
+    assert_eq!(
+        deliveries[0]["error"],
+        "You tried to send to a recipient that has been marked as inactive."
+    );
+    assert!(list_deliveries(&app, "?failed=false").await.is_empty());
+}
+
+#[tokio::test]
+async fn timed_out_emails_are_logged_without_a_status() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+
+    let deliveries = list_deliveries(&app, "").await;
+    assert_eq!(deliveries.len(), 1);
+    assert!(deliveries[0]["status"].is_null());
+    assert!(deliveries[0]["error"]
+        .as_str()
+        .unwrap()
+        .contains("timed out"));
+}
+
+#[tokio::test]
+async fn deliveries_can_be_filtered_by_recipient_and_issue() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("octavia_butler@gmail.com")
+        .await;
+    Mock::given(path("/email/batch"))
+        .respond_with(AcceptBatch)
+        .mount(&app.email_server)
+        .await;
+    let slug = app.publish_newsletter("Issue one", "<p>Body</p>").await;
+    app.publish_newsletter("Issue two", "<p>Body</p>").await;
+
+    let deliveries = list_deliveries(
+        &app,
+        &format!("?recipient=Ursula_Le_Guin%40gmail.com&issue={}", slug),
+    )
+    .await;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0]["recipient"], "ursula_le_guin@gmail.com");
+    assert_eq!(deliveries[0]["kind"], "newsletter");
+    assert_eq!(deliveries[0]["issue"], slug);
+
+    assert_eq!(list_deliveries(&app, "?kind=newsletter").await.len(), 4);
+    assert_eq!(list_deliveries(&app, "?kind=confirmation").await.len(), 2);
+    assert_eq!(list_deliveries(&app, "?limit=3").await.len(), 3);
+}
+
+#[tokio::test]
+async fn suppressed_recipients_in_a_batch_are_logged_without_being_sent() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("octavia_butler@gmail.com")
+        .await;
+    sqlx::query!(
+        "INSERT INTO suppressions (email, reason, created_at) VALUES ($1, 'test', now())",
+        "octavia_butler@gmail.com",
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email/batch"))
+        .respond_with(AcceptBatch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let slug = app.publish_newsletter("Issue one", "<p>Body</p>").await;
+
+    let report = app.wait_for_delivery(&slug).await;
+    assert_eq!(report.delivered, 1);
+    assert_eq!(report.suppressed, 1);
+
+    let deliveries = list_deliveries(&app, "?kind=newsletter&failed=true").await;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0]["recipient"], "octavia_butler@gmail.com");
+    assert_eq!(
+        deliveries[0]["error"],
+        "the recipient has opted out of all email"
+    );
+    assert_eq!(
+        list_deliveries(&app, "?kind=newsletter&failed=false")
+            .await
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn out_of_range_limits_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    for limit in ["0", "1001"] {
+        let response = app
+            .admin_request(Method::GET, &format!("/admin/deliveries?limit={}", limit))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 422, "limit={}", limit);
+    }
+}
+
+async fn list_deliveries(app: &TestApp, query: &str) -> Vec<serde_json::Value> {
+    app.admin_request(Method::GET, &format!("/admin/deliveries{}", query))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
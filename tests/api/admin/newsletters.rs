@@ -0,0 +1,459 @@
+use reqwest::Method;
+use time::{Duration, OffsetDateTime};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{AcceptBatch, TestApp};
+
+#[tokio::test]
+async fn publish_rejects_requests_without_the_admin_token() {
+    let app = TestApp::spawn().await;
+
+    let response = reqwest::Client::new()
+        .post(app.base_url.join("/admin/newsletters").unwrap())
+        .json(&serde_json::json!({
+            "title": "Title",
+            "html_body": "<p>Body</p>",
+            "text_body": "Body",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn publish_delivers_to_confirmed_subscribers_with_a_view_in_browser_link() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.create_unconfirmed_subscriber("octavia_butler@gmail.com")
+        .await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptBatch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .admin_request(Method::POST, "/admin/newsletters")
+        .json(&serde_json::json!({
+            "title": "Issue one",
+            "html_body": "<p>Body</p>",
+            "text_body": "Body",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 202);
+    let published: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(published["slug"], "issue-one");
+    let report = app.wait_for_delivery("issue-one").await;
+    assert_eq!(report.delivered, 1);
+    assert_eq!(report.failed, 0);
+
+    let email = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let batch: serde_json::Value = serde_json::from_slice(&email.body).unwrap();
+    assert_eq!(batch.as_array().unwrap().len(), 1);
+    let email = &batch[0];
+    assert_eq!(email["To"], "ursula_le_guin@gmail.com");
+    assert_eq!(email["Subject"], "Issue one");
+    let url = published["url"].as_str().unwrap();
+    assert!(url.ends_with("/newsletters/issue-one"));
+    assert!(email["HtmlBody"].as_str().unwrap().contains(url));
+    assert!(email["TextBody"].as_str().unwrap().contains(url));
+    assert_eq!(
+        email["Headers"],
+        serde_json::json!([{ "Name": "List-Id", "Value": "<newsletter.127.0.0.1>" }])
+    );
+    assert_eq!(email["Tag"], "newsletter");
+    assert!(email["Metadata"]["issue_id"].is_string());
+}
+
+#[tokio::test]
+async fn publish_retries_only_the_recipients_that_failed() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("octavia_butler@gmail.com")
+        .await;
+
+    Mock::given(path("/email/batch"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            { "To": "ursula_le_guin@gmail.com", "MessageID": "1", "ErrorCode": 0, "Message": "OK" },
+            { "ErrorCode": 300, "Message": "Invalid email request" },
+        ])))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .respond_with(AcceptBatch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.publish_newsletter("Issue one", "<p>Body</p>").await;
+
+    let retry = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let retry: serde_json::Value = serde_json::from_slice(&retry.body).unwrap();
+    assert_eq!(retry.as_array().unwrap().len(), 1);
+    assert_eq!(retry[0]["To"], "octavia_butler@gmail.com");
+}
+
+#[tokio::test]
+async fn publish_reports_recipients_that_fail_twice() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email/batch"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .admin_request(Method::POST, "/admin/newsletters")
+        .json(&serde_json::json!({
+            "title": "Issue one",
+            "html_body": "<p>Body</p>",
+            "text_body": "Body",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 202);
+    let report = app.wait_for_delivery("issue-one").await;
+    assert_eq!(report.delivered, 0);
+    assert_eq!(report.failed, 1);
+}
+
+#[tokio::test]
+async fn publish_waits_out_rate_limits() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email/batch"))
+        .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "1"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .respond_with(AcceptBatch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let started = std::time::Instant::now();
+    app.publish_newsletter("Issue one", "<p>Body</p>").await;
+
+    assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn publish_sends_at_most_the_configured_rate() {
+    let app = TestApp::spawn_with(|config| config.email_max_rate(2)).await;
+    for email in [
+        "ursula_le_guin@gmail.com",
+        "octavia_butler@gmail.com",
+        "nk_jemisin@gmail.com",
+    ] {
+        app.create_confirmed_subscriber(email).await;
+    }
+    Mock::given(path("/email/batch"))
+        .respond_with(AcceptBatch)
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let started = std::time::Instant::now();
+    app.publish_newsletter("Issue one", "<p>Body</p>").await;
+
+    // Two messages go in the first batch, and the last one a second later
+    assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn publish_makes_slugs_unique() {
+    let app = TestApp::spawn().await;
+
+    let first = app.publish_newsletter("Same title", "<p>One</p>").await;
+    let second = app.publish_newsletter("Same title", "<p>Two</p>").await;
+
+    assert_eq!(first, "same-title");
+    assert_eq!(second, "same-title-2");
+}
+
+#[tokio::test]
+async fn publish_rejects_invalid_issues() {
+    let app = TestApp::spawn().await;
+
+    for body in [
+        serde_json::json!({ "title": " ", "html_body": "<p>Body</p>", "text_body": "Body" }),
+        serde_json::json!({ "title": "Title", "html_body": "", "text_body": "Body" }),
+        serde_json::json!({
+            "title": "Title",
+            "html_body": "<p>Body</p>",
+            "text_body": "Body",
+            "slug": "Not a slug",
+        }),
+    ] {
+        let response = app
+            .admin_request(Method::POST, "/admin/newsletters")
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 422, "{}", body);
+    }
+}
+
+#[tokio::test]
+async fn drafts_are_listed_and_delivered_once_published() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (id, slug, title, html_body, text_body, created_at)
+        VALUES ($1, 'draft', 'Draft', '<p>Body</p>', 'Body', now())
+        "#,
+        uuid::Uuid::new_v4(),
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let drafts: serde_json::Value = app
+        .admin_request(Method::GET, "/admin/newsletters/drafts")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(drafts[0]["slug"], "draft");
+    assert_eq!(drafts.as_array().unwrap().len(), 1);
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptBatch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .admin_request(Method::POST, "/admin/newsletters/draft/publish")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+    let published: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(published["slug"], "draft");
+    assert_eq!(app.wait_for_delivery("draft").await.delivered, 1);
+
+    // Published issues are no longer drafts
+    let response = app
+        .admin_request(Method::POST, "/admin/newsletters/draft/publish")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = reqwest::get(app.base_url.join("/newsletters/draft").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_sent_once_due() {
+    let app = TestApp::spawn_with(|config| {
+        config.scheduler_interval(std::time::Duration::from_millis(50))
+    })
+    .await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptBatch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let send_at = OffsetDateTime::now_utc() + Duration::seconds(1);
+    let response = app
+        .admin_request(Method::POST, "/admin/newsletters")
+        .json(&serde_json::json!({
+            "title": "Good morning",
+            "html_body": "<p>Body</p>",
+            "text_body": "Body",
+            "send_at": send_at.format(time::Format::Rfc3339),
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+    let scheduled: serde_json::Value = response.json().await.unwrap();
+    assert!(scheduled["send_at"].is_string());
+    assert!(scheduled.get("delivered").is_none());
+
+    // Scheduled issues aren't public until they're sent
+    let response = reqwest::get(app.base_url.join("/newsletters/good-morning").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    wait_until_published(&app, "good-morning").await;
+
+    // Delivery happens after publishing, so wait for it (after the confirmation email) to finish
+    for _ in 0..100 {
+        if app.email_server.received_requests().await.unwrap().len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+
+    let response = reqwest::get(app.base_url.join("/newsletters/good-morning").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // It's too late to change the schedule
+    let response = app
+        .admin_request(Method::DELETE, "/admin/newsletters/good-morning/schedule")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_rescheduled_or_cancelled() {
+    let app = TestApp::spawn_with(|config| {
+        config.scheduler_interval(std::time::Duration::from_millis(50))
+    })
+    .await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptBatch)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .admin_request(Method::POST, "/admin/newsletters")
+        .json(&serde_json::json!({
+            "title": "Good morning",
+            "html_body": "<p>Body</p>",
+            "text_body": "Body",
+            "send_at": (OffsetDateTime::now_utc() + Duration::seconds(1)).format(time::Format::Rfc3339),
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .admin_request(Method::PUT, "/admin/newsletters/good-morning/schedule")
+        .json(&serde_json::json!({
+            "send_at": (OffsetDateTime::now_utc() + Duration::hours(1)).format(time::Format::Rfc3339),
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+
+    let drafts: serde_json::Value = app
+        .admin_request(Method::GET, "/admin/newsletters/drafts")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(drafts[0]["send_at"].is_string());
+
+    let response = app
+        .admin_request(Method::DELETE, "/admin/newsletters/good-morning/schedule")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+
+    // Wait past the original time
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    let issue = sqlx::query!("SELECT published_at, send_at FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert!(issue.published_at.is_none());
+    assert!(issue.send_at.is_none());
+}
+
+#[tokio::test]
+async fn schedules_must_be_in_the_future_and_for_existing_issues() {
+    let app = TestApp::spawn().await;
+    let past = (OffsetDateTime::now_utc() - Duration::minutes(1)).format(time::Format::Rfc3339);
+    let future = (OffsetDateTime::now_utc() + Duration::hours(1)).format(time::Format::Rfc3339);
+
+    let response = app
+        .admin_request(Method::POST, "/admin/newsletters")
+        .json(&serde_json::json!({
+            "title": "Too late",
+            "html_body": "<p>Body</p>",
+            "text_body": "Body",
+            "send_at": past,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 422);
+
+    let response = app
+        .admin_request(Method::PUT, "/admin/newsletters/nope/schedule")
+        .json(&serde_json::json!({ "send_at": future }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+async fn wait_until_published(app: &TestApp, slug: &str) {
+    for _ in 0..100 {
+        let issue = sqlx::query!(
+            "SELECT published_at FROM newsletter_issues WHERE slug = $1",
+            slug
+        )
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+        if issue.published_at.is_some() {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    panic!("{} was not published", slug);
+}
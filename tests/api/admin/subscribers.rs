@@ -0,0 +1,4 @@
+mod erase;
+mod events;
+mod export;
+mod import;
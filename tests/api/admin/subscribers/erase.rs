@@ -0,0 +1,73 @@
+use reqwest::Method;
+use uuid::Uuid;
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn erase_rejects_requests_without_the_admin_token() {
+    let app = TestApp::spawn().await;
+
+    let response = reqwest::Client::new()
+        .delete(
+            app.base_url
+                .join(&format!("/admin/subscribers/{}", Uuid::new_v4()))
+                .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn erase_deletes_the_subscriber_and_blocks_reimport() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    let subscriber_id: Uuid = sqlx::query_scalar("SELECT id FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+
+    let response = erase(&app, &subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let response = erase(&app, &subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let erasure = sqlx::query!("SELECT subscriber_id, requested_by FROM erasures")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(erasure.subscriber_id, subscriber_id);
+    assert_eq!(erasure.requested_by, "admin");
+
+    let report: serde_json::Value = app
+        .admin_request(
+            Method::POST,
+            "/admin/subscribers/import?mode=consented&consent_note=test",
+        )
+        .body("name,email\nle guin,Ursula_Le_Guin@gmail.com\n")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["rejected"], 1);
+    assert_eq!(
+        report["rows"][0]["reason"],
+        "subscriber's data was erased at their request"
+    );
+}
+
+async fn erase(app: &TestApp, subscriber_id: &Uuid) -> reqwest::Response {
+    app.admin_request(
+        Method::DELETE,
+        &format!("/admin/subscribers/{}", subscriber_id),
+    )
+    .send()
+    .await
+    .unwrap()
+}
@@ -0,0 +1,173 @@
+use reqwest::Method;
+use uuid::Uuid;
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn events_rejects_requests_without_the_admin_token() {
+    let app = TestApp::spawn().await;
+
+    let response = reqwest::get(
+        app.base_url
+            .join(&format!("/admin/subscribers/{}/events", Uuid::new_v4()))
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn events_returns_404_for_unknown_subscribers() {
+    let app = TestApp::spawn().await;
+
+    let response = events(&app, &Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn events_returns_the_subscribers_timeline() {
+    let app = TestApp::spawn().await;
+    let links = app
+        .create_unconfirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    reqwest::get(links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Confirming again doesn't record another transition
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.post_postmark_webhook(&serde_json::json!({
+        "RecordType": "SpamComplaint",
+        "Email": "ursula_le_guin@gmail.com",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let events: Vec<serde_json::Value> = events(&app, &subscriber_id(&app).await)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let transitions: Vec<_> = events
+        .iter()
+        .map(|event| {
+            (
+                event["from_status"].as_str(),
+                event["to_status"].as_str().unwrap(),
+                event["actor"].as_str().unwrap(),
+                event["cause"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        transitions,
+        [
+            (None, "pending", "subscriber", "subscribed"),
+            (
+                Some("pending"),
+                "confirmed",
+                "subscriber",
+                "confirmed subscription"
+            ),
+            (
+                Some("confirmed"),
+                "complained",
+                "postmark",
+                "spam_complaint"
+            ),
+        ]
+    );
+    assert!(events.iter().all(|event| event["occurred_at"].is_string()));
+}
+
+#[tokio::test]
+async fn events_returns_an_empty_timeline_for_subscribers_without_events() {
+    let app = TestApp::spawn().await;
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula_le_guin@gmail.com', 'le guin', now(), 'confirmed')
+        "#,
+        subscriber_id,
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let response = events(&app, &subscriber_id).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let events: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert!(events.is_empty());
+}
+
+#[tokio::test]
+async fn disallowed_transitions_are_rejected_and_not_recorded() {
+    let app = TestApp::spawn().await;
+    let links = app
+        .create_unconfirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.post_postmark_webhook(&serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "Email": "ursula_le_guin@gmail.com",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // A bounced subscriber can't be confirmed...
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 422);
+
+    // ...or unsubscribed, but the webhook is still acknowledged
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "SubscriptionChange",
+            "Recipient": "ursula_le_guin@gmail.com",
+            "SuppressSending": true,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let events: Vec<serde_json::Value> = events(&app, &subscriber_id(&app).await)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let statuses: Vec<_> = events
+        .iter()
+        .map(|event| event["to_status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, ["pending", "bounced"]);
+}
+
+async fn subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query_scalar("SELECT id FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+}
+
+async fn events(app: &TestApp, subscriber_id: &Uuid) -> reqwest::Response {
+    app.admin_request(
+        Method::GET,
+        &format!("/admin/subscribers/{}/events", subscriber_id),
+    )
+    .send()
+    .await
+    .unwrap()
+}
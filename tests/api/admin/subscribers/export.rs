@@ -0,0 +1,131 @@
+use reqwest::Method;
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn export_rejects_requests_without_the_admin_token() {
+    let app = TestApp::spawn().await;
+
+    let response = reqwest::get(app.base_url.join("/admin/subscribers/export").unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn export_returns_all_subscribers_as_csv_by_default() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.create_unconfirmed_subscriber("octavia_butler@gmail.com")
+        .await;
+
+    let response = export(&app, "").await;
+
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/csv; charset=utf-8"
+    );
+    let body = response.text().await.unwrap();
+    let lines: Vec<_> = body.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "name,email,status,subscribed_at");
+    assert!(lines[1].starts_with("le guin,ursula_le_guin@gmail.com,confirmed,"));
+    assert!(lines[2].starts_with("le guin,octavia_butler@gmail.com,pending,"));
+}
+
+#[tokio::test]
+async fn export_returns_ndjson_when_requested() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    let response = export(&app, "format=ndjson").await;
+
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    let subscribers: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0]["name"], "le guin");
+    assert_eq!(subscribers[0]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscribers[0]["status"], "confirmed");
+    assert!(subscribers[0]["subscribed_at"].is_string());
+}
+
+#[tokio::test]
+async fn export_can_be_filtered_by_status() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.create_unconfirmed_subscriber("octavia_butler@gmail.com")
+        .await;
+
+    let body = export(&app, "format=ndjson&status=pending")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(body.lines().count(), 1);
+    assert!(body.contains("octavia_butler@gmail.com"));
+}
+
+#[tokio::test]
+async fn export_can_be_filtered_by_subscription_date() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    sqlx::query!("UPDATE subscriptions SET subscribed_at = '2020-06-01T00:00:00Z'")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    app.create_confirmed_subscriber("octavia_butler@gmail.com")
+        .await;
+
+    let before = export(&app, "format=ndjson&subscribed_before=2021-01-01T00:00:00Z")
+        .await
+        .text()
+        .await
+        .unwrap();
+    let after = export(&app, "format=ndjson&subscribed_after=2021-01-01T00:00:00Z")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(before.lines().count(), 1);
+    assert!(before.contains("ursula_le_guin@gmail.com"));
+    assert_eq!(after.lines().count(), 1);
+    assert!(after.contains("octavia_butler@gmail.com"));
+}
+
+#[tokio::test]
+async fn export_rejects_invalid_filters_with_a_4xx() {
+    let app = TestApp::spawn().await;
+
+    for query in ["status=unknown", "format=xml", "subscribed_after=yesterday"] {
+        let response = app
+            .admin_request(Method::GET, &format!("/admin/subscribers/export?{}", query))
+            .send()
+            .await
+            .unwrap();
+        assert!(
+            response.status().is_client_error(),
+            "did not get a 4xx for {}",
+            query
+        );
+    }
+}
+
+async fn export(app: &TestApp, query: &str) -> reqwest::Response {
+    app.admin_request(Method::GET, &format!("/admin/subscribers/export?{}", query))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+}
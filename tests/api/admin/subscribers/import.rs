@@ -0,0 +1,224 @@
+use reqwest::Method;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{AcceptBatch, TestApp};
+
+#[tokio::test]
+async fn import_rejects_requests_without_the_admin_token() {
+    let app = TestApp::spawn().await;
+
+    let response = reqwest::Client::new()
+        .post(
+            app.base_url
+                .join("/admin/subscribers/import?mode=confirm")
+                .unwrap(),
+        )
+        .body("name,email\n")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn import_in_confirm_mode_sends_confirmation_emails() {
+    let app = TestApp::spawn().await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptBatch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let report = import(
+        &app,
+        "mode=confirm",
+        "name,email\nle guin,ursula_le_guin@gmail.com\nbutler,octavia_butler@gmail.com\n",
+    )
+    .await;
+
+    assert_eq!(report["accepted"], 2);
+    assert_eq!(report["rejected"], 0);
+    assert_eq!(report["unconfirmed"], 0);
+    assert_eq!(statuses(&app).await, ["pending", "pending"]);
+
+    // The emails are sent after the import is committed, so their links work
+    let request = &app.email_server.received_requests().await.unwrap()[0];
+    let emails: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(emails.len(), 2);
+    for email in emails {
+        let link = linkify::LinkFinder::new()
+            .links(email["TextBody"].as_str().unwrap())
+            .next()
+            .unwrap()
+            .as_str()
+            .parse::<reqwest::Url>()
+            .unwrap();
+        let mut confirm = app.base_url.join(link.path()).unwrap();
+        confirm.set_query(link.query());
+        reqwest::get(confirm)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+    assert_eq!(statuses(&app).await, ["confirmed", "confirmed"]);
+}
+
+#[tokio::test]
+async fn import_in_consented_mode_confirms_subscribers_and_records_consent() {
+    let app = TestApp::spawn().await;
+
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let report = import(
+        &app,
+        "mode=consented&consent_note=opted+in+on+the+old+platform",
+        "email,name\nursula_le_guin@gmail.com,le guin\n",
+    )
+    .await;
+
+    assert_eq!(report["accepted"], 1);
+    assert_eq!(statuses(&app).await, ["confirmed"]);
+
+    let consent = sqlx::query!("SELECT source, note FROM consents")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(consent.source, "import");
+    assert_eq!(consent.note, "opted in on the old platform");
+}
+
+#[tokio::test]
+async fn import_in_consented_mode_requires_a_consent_note() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .admin_request(Method::POST, "/admin/subscribers/import?mode=consented")
+        .body("name,email\nle guin,ursula_le_guin@gmail.com\n")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 422);
+    assert!(statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn import_rejects_a_file_without_the_required_columns() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .admin_request(Method::POST, "/admin/subscribers/import?mode=confirm")
+        .body("email\nursula_le_guin@gmail.com\n")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[tokio::test]
+async fn import_reports_rejected_rows_and_imports_the_rest() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("existing@gmail.com").await;
+    sqlx::query!(
+        "INSERT INTO suppressions (email, reason, created_at) VALUES ($1, 'test', now())",
+        "suppressed@gmail.com",
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let report = import(
+        &app,
+        "mode=consented&consent_note=test",
+        "name,email\n\
+        le guin,ursula_le_guin@gmail.com\n\
+        no email,\n\
+        ,nameless@gmail.com\n\
+        bad email,definitely-not-an-email\n\
+        duplicate,Ursula_Le_Guin@gmail.com\n\
+        existing,EXISTING@gmail.com\n\
+        suppressed,suppressed@gmail.com\n",
+    )
+    .await;
+
+    assert_eq!(report["accepted"], 1);
+    assert_eq!(report["rejected"], 6);
+
+    let rows = report["rows"].as_array().unwrap();
+    assert_eq!(rows.len(), 7);
+    assert_eq!(rows[0]["row"], 1);
+    assert_eq!(rows[0]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(rows[0]["outcome"], "accepted");
+    for row in &rows[1..] {
+        assert_eq!(row["outcome"], "rejected", "{}", row);
+        assert!(row["reason"].is_string(), "{}", row);
+    }
+    assert_eq!(rows[4]["reason"], "duplicate of row 1");
+    assert_eq!(rows[5]["reason"], "already subscribed");
+
+    // The existing subscriber plus the one accepted row
+    assert_eq!(statuses(&app).await, ["confirmed", "confirmed"]);
+}
+
+#[tokio::test]
+async fn import_keeps_subscribers_whose_confirmation_email_fails() {
+    let app = TestApp::spawn().await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let report = import(
+        &app,
+        "mode=confirm",
+        "name,email\nle guin,ursula_le_guin@gmail.com\n",
+    )
+    .await;
+
+    assert_eq!(report["accepted"], 1);
+    assert_eq!(report["unconfirmed"], 1);
+    assert_eq!(report["rows"][0]["outcome"], "accepted");
+    assert_eq!(statuses(&app).await, ["pending"]);
+}
+
+async fn import(app: &TestApp, query: &str, csv: &'static str) -> serde_json::Value {
+    app.admin_request(
+        Method::POST,
+        &format!("/admin/subscribers/import?{}", query),
+    )
+    .header("content-type", "text/csv")
+    .body(csv)
+    .send()
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap()
+    .json()
+    .await
+    .unwrap()
+}
+
+async fn statuses(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT status FROM subscriptions ORDER BY subscribed_at")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.status)
+        .collect()
+}
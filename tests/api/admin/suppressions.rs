@@ -0,0 +1,172 @@
+use reqwest::Method;
+use serde_json::json;
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn admin_suppression_endpoints_reject_requests_without_the_admin_token() {
+    let app = TestApp::spawn().await;
+    let client = reqwest::Client::new();
+    let url = app.base_url.join("/admin/suppressions").unwrap();
+
+    let requests = vec![
+        ("missing token", client.get(url.clone())),
+        ("wrong token", client.get(url.clone()).bearer_auth("wrong")),
+        (
+            "basic auth",
+            client.get(url.clone()).basic_auth("admin", Some("wrong")),
+        ),
+    ];
+
+    for (problem, request) in requests {
+        let response = request.send().await.unwrap();
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "did not get 401 with {}",
+            problem
+        );
+    }
+}
+
+#[tokio::test]
+async fn added_suppressions_are_listed_with_normalized_emails() {
+    let app = TestApp::spawn().await;
+
+    let response = add_suppression(&app, "Ursula_Le_Guin@Gmail.com", "legal request").await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let suppressions: serde_json::Value = app
+        .admin_request(Method::GET, "/admin/suppressions")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let suppressions = suppressions.as_array().unwrap();
+    assert_eq!(suppressions.len(), 1);
+    assert_eq!(suppressions[0]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(suppressions[0]["reason"], "legal request");
+    assert!(suppressions[0]["created_at"].is_string());
+}
+
+#[tokio::test]
+async fn suppressions_do_not_depend_on_optional_canonicalization_rules() {
+    let app = TestApp::spawn_with(|config| config.canonicalize_gmail_dots(true)).await;
+
+    add_suppression(&app, "Ursula.Le.Guin@gmail.com", "legal request").await;
+
+    let email = sqlx::query!("SELECT email FROM suppressions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .email;
+    assert_eq!(email, "ursula.le.guin@gmail.com");
+}
+
+#[tokio::test]
+async fn suppressions_keyed_on_the_current_canonical_email_still_apply() {
+    let app = TestApp::spawn_with(|config| config.canonicalize_gmail_dots(true)).await;
+    sqlx::query!(
+        "INSERT INTO suppressions (email, reason, created_at) VALUES ($1, 'test', now())",
+        "ursulaleguin@gmail.com",
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let body = "name=le%20guin&email=Ursula.Le.Guin%40gmail.com";
+    let response = app.post_subscriptions(body).await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    let response = app
+        .admin_request(
+            Method::DELETE,
+            "/admin/suppressions/Ursula.Le.Guin@gmail.com",
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+}
+
+#[tokio::test]
+async fn adding_an_existing_suppression_returns_a_200() {
+    let app = TestApp::spawn().await;
+
+    add_suppression(&app, "ursula_le_guin@gmail.com", "legal request").await;
+    let response = add_suppression(&app, "ursula_le_guin@gmail.com", "again").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn adding_an_invalid_suppression_returns_a_422() {
+    let app = TestApp::spawn().await;
+
+    let bodies = vec![
+        ("invalid email", "definitely-not-an-email", "legal request"),
+        ("empty reason", "ursula_le_guin@gmail.com", " "),
+    ];
+
+    for (problem, email, reason) in bodies {
+        let response = add_suppression(&app, email, reason).await;
+        assert_eq!(
+            response.status().as_u16(),
+            422,
+            "did not get 422 with {}",
+            problem
+        );
+    }
+}
+
+#[tokio::test]
+async fn removed_suppressions_are_no_longer_listed() {
+    let app = TestApp::spawn().await;
+    add_suppression(&app, "ursula_le_guin@gmail.com", "legal request").await;
+
+    let response = app
+        .admin_request(
+            Method::DELETE,
+            "/admin/suppressions/Ursula_Le_Guin@gmail.com",
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM suppressions"#)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn removing_an_unknown_suppression_returns_a_404() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .admin_request(
+            Method::DELETE,
+            "/admin/suppressions/ursula_le_guin@gmail.com",
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+async fn add_suppression(app: &TestApp, email: &str, reason: &str) -> reqwest::Response {
+    app.admin_request(Method::POST, "/admin/suppressions")
+        .json(&json!({ "email": email, "reason": reason }))
+        .send()
+        .await
+        .unwrap()
+}
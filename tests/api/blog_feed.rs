@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::helpers::{AcceptBatch, TestApp};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+const ENTRIES: &str = "SELECT COUNT(*) FROM blog_feed_entries";
+
+#[tokio::test]
+async fn new_blog_posts_become_drafts() {
+    let blog_server = MockServer::start().await;
+    mount_feed(&blog_server, &["first"]).await;
+    let app = spawn_polling(&blog_server, false).await;
+
+    // Posts that existed before the first poll are only recorded as seen
+    wait_for_count(&app, ENTRIES, 1).await;
+
+    mount_feed(&blog_server, &["first", "second"]).await;
+    wait_for_count(&app, ENTRIES, 2).await;
+
+    let issues =
+        sqlx::query!("SELECT title, html_body, text_body, published_at FROM newsletter_issues")
+            .fetch_all(&app.pool)
+            .await
+            .unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].title, "Post second");
+    assert!(issues[0].published_at.is_none());
+    assert!(issues[0]
+        .html_body
+        .contains(r#"<a href="https://blog.example/second">Read the full post</a>"#));
+    assert!(issues[0].text_body.starts_with("Summary of second"));
+}
+
+#[tokio::test]
+async fn new_blog_posts_are_sent_when_auto_send_is_enabled() {
+    let blog_server = MockServer::start().await;
+    mount_feed(&blog_server, &[]).await;
+    let app = spawn_polling(&blog_server, true).await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptBatch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // An empty feed still counts as the first poll
+    wait_for_count(&app, "SELECT COUNT(*) FROM blog_feeds", 1).await;
+    mount_feed(&blog_server, &["first"]).await;
+    wait_for_count(
+        &app,
+        "SELECT COUNT(*) FROM newsletter_issues WHERE delivered_at IS NOT NULL",
+        1,
+    )
+    .await;
+
+    let issue = sqlx::query!("SELECT published_at, delivered_count FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert!(issue.published_at.is_some());
+    assert_eq!(issue.delivered_count, Some(1));
+}
+
+#[tokio::test]
+async fn posts_announced_by_the_publish_webhook_are_not_duplicated() {
+    let blog_server = MockServer::start().await;
+    mount_feed(&blog_server, &[]).await;
+    let app = spawn_polling(&blog_server, true).await;
+    wait_for_count(&app, "SELECT COUNT(*) FROM blog_feeds", 1).await;
+
+    let post = serde_json::json!({
+        "title": "Post first",
+        "url": "https://blog.example/first",
+        "excerpt": "Summary of first",
+    });
+    let timestamp = time::OffsetDateTime::now_utc().unix_timestamp();
+    let response = app.post_publish_webhook(&post, timestamp, "nonce-1").await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    mount_feed(&blog_server, &["first"]).await;
+    wait_for_count(&app, ENTRIES, 1).await;
+
+    let issue = sqlx::query!("SELECT id, send_at FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    // The webhook's draft isn't sent, even though the feed sends new posts automatically
+    assert!(issue.send_at.is_none());
+    let entry = sqlx::query!("SELECT issue_id FROM blog_feed_entries")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(entry.issue_id, Some(issue.id));
+}
+
+async fn spawn_polling(blog_server: &MockServer, auto_send: bool) -> TestApp {
+    let feed_url = format!("{}/feed.xml", blog_server.uri());
+    TestApp::spawn_with(|config| {
+        config
+            .blog_feed_url(feed_url.parse().unwrap())
+            .blog_feed_poll_interval(POLL_INTERVAL)
+            .blog_feed_auto_send(auto_send)
+    })
+    .await
+}
+
+/// Serve an RSS feed with a post for each slug, replacing any previous feed.
+async fn mount_feed(blog_server: &MockServer, slugs: &[&str]) {
+    let items: String = slugs
+        .iter()
+        .map(|slug| {
+            format!(
+                "<item><title>Post {slug}</title><link>https://blog.example/{slug}</link>\
+                <guid>https://blog.example/{slug}</guid>\
+                <description>Summary of {slug}</description></item>",
+                slug = slug
+            )
+        })
+        .collect();
+    let feed = format!(
+        r#"<?xml version="1.0"?><rss version="2.0"><channel><title>Blog</title>{}</channel></rss>"#,
+        items
+    );
+
+    blog_server.reset().await;
+    Mock::given(path("/feed.xml"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(feed, "application/rss+xml"))
+        .mount(blog_server)
+        .await;
+}
+
+/// Wait until a `COUNT(*)` query returns at least `count`.
+async fn wait_for_count(app: &TestApp, query: &str, count: i64) {
+    for _ in 0..100 {
+        let (actual,): (i64,) = sqlx::query_as(query).fetch_one(&app.pool).await.unwrap();
+        if actual >= count {
+            return;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    panic!("timed out waiting for {} to reach {}", query, count);
+}
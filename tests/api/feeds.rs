@@ -0,0 +1,125 @@
+use reqwest::header;
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn feeds_have_the_right_content_types() {
+    let app = TestApp::spawn().await;
+    app.publish_newsletter("First issue", "<p>One</p>").await;
+
+    for (path, content_type) in [
+        ("/feed.rss", "application/rss+xml; charset=utf-8"),
+        ("/feed.atom", "application/atom+xml; charset=utf-8"),
+        ("/feed.json", "application/feed+json; charset=utf-8"),
+    ] {
+        let response = get(&app, path, &[]).await;
+
+        assert_eq!(response.status().as_u16(), 200, "{}", path);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], content_type);
+        assert!(response.headers().contains_key(header::ETAG));
+        assert!(response.headers().contains_key(header::LAST_MODIFIED));
+        assert!(response.headers()[header::CACHE_CONTROL]
+            .to_str()
+            .unwrap()
+            .contains("public"));
+    }
+}
+
+#[tokio::test]
+async fn feeds_link_to_issues_with_absolute_urls() {
+    let app = TestApp::spawn().await;
+    app.publish_newsletter("First issue", "<p>One</p>").await;
+    app.publish_newsletter("Second issue", "<p>Two</p>").await;
+
+    let rss = get(&app, "/feed.rss", &[]).await.text().await.unwrap();
+    assert!(rss.contains("<link>http://127.0.0.1:0/newsletters/first-issue</link>"));
+
+    let atom = get(&app, "/feed.atom", &[]).await.text().await.unwrap();
+    assert!(atom.contains(r#"<link href="http://127.0.0.1:0/newsletters/second-issue"/>"#));
+
+    let json: serde_json::Value = get(&app, "/feed.json", &[]).await.json().await.unwrap();
+    let items = json["items"].as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(
+        items[0]["url"],
+        "http://127.0.0.1:0/newsletters/second-issue"
+    );
+    assert_eq!(
+        items[1]["url"],
+        "http://127.0.0.1:0/newsletters/first-issue"
+    );
+}
+
+#[tokio::test]
+async fn feeds_return_304_when_the_etag_matches() {
+    let app = TestApp::spawn().await;
+    app.publish_newsletter("First issue", "<p>One</p>").await;
+
+    let response = get(&app, "/feed.atom", &[]).await;
+    let etag = response.headers()[header::ETAG]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let response = get(&app, "/feed.atom", &[(header::IF_NONE_MATCH, &etag)]).await;
+    assert_eq!(response.status().as_u16(), 304);
+    assert_eq!(response.headers()[header::ETAG], etag.as_str());
+    assert!(response.text().await.unwrap().is_empty());
+
+    // A new issue changes the feed
+    app.publish_newsletter("Second issue", "<p>Two</p>").await;
+    let response = get(&app, "/feed.atom", &[(header::IF_NONE_MATCH, &etag)]).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn feeds_return_304_when_not_modified_since() {
+    let app = TestApp::spawn().await;
+    app.publish_newsletter("First issue", "<p>One</p>").await;
+
+    let response = get(&app, "/feed.rss", &[]).await;
+    let last_modified = response.headers()[header::LAST_MODIFIED]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let response = get(
+        &app,
+        "/feed.rss",
+        &[(header::IF_MODIFIED_SINCE, &last_modified)],
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 304);
+
+    let response = get(
+        &app,
+        "/feed.rss",
+        &[(header::IF_MODIFIED_SINCE, "Sat, 01 Jan 2000 00:00:00 GMT")],
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn feeds_without_issues_are_empty() {
+    let app = TestApp::spawn().await;
+
+    let response = get(&app, "/feed.json", &[]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!response.headers().contains_key(header::LAST_MODIFIED));
+    let json: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(json["items"], serde_json::json!([]));
+}
+
+async fn get(
+    app: &TestApp,
+    path: &str,
+    headers: &[(header::HeaderName, &str)],
+) -> reqwest::Response {
+    let mut request = reqwest::Client::new().get(app.base_url.join(path).unwrap());
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    request.send().await.unwrap()
+}
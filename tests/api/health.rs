@@ -14,3 +14,40 @@ async fn health_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[tokio::test]
+async fn ready_works() {
+    let app = TestApp::spawn().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(app.base_url.join("/health/ready").unwrap())
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(response.status().is_success());
+    let readiness: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(readiness["database"], "ok");
+    assert_eq!(readiness["email"], "closed");
+}
+
+#[tokio::test]
+async fn healthcheck_succeeds_for_a_healthy_server() {
+    let app = TestApp::spawn().await;
+
+    let result = zero2prod::healthcheck(app.address, true).await;
+
+    assert!(result.is_ok(), "{:?}", result);
+}
+
+#[tokio::test]
+async fn healthcheck_fails_if_nothing_is_listening() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    drop(listener);
+
+    let result = zero2prod::healthcheck(address, false).await;
+
+    assert!(result.is_err());
+}
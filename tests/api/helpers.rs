@@ -3,11 +3,27 @@ use std::net::Ipv4Addr;
 use reqwest::Url;
 use sqlx::{Connection as _, Executor as _};
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+pub(crate) const ADMIN_TOKEN: &str = "correct-horse-battery-staple";
+pub(crate) const POSTMARK_WEBHOOK_USERNAME: &str = "postmark";
+pub(crate) const POSTMARK_WEBHOOK_PASSWORD: &str = "hunter2";
+pub(crate) const PUBLISH_WEBHOOK_SECRET: &str = "publish-secret";
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct DeliveryReport {
+    pub(crate) delivered: i32,
+    pub(crate) suppressed: i32,
+    pub(crate) failed: i32,
+}
 
 static TRACING_ENABLED: std::sync::Once = std::sync::Once::new();
 
 pub(crate) struct TestApp {
+    pub(crate) address: std::net::SocketAddr,
     pub(crate) port: u16,
     pub(crate) pool: sqlx::PgPool,
     pub(crate) base_url: Url,
@@ -16,6 +32,13 @@ pub(crate) struct TestApp {
 
 impl TestApp {
     pub(crate) async fn spawn() -> Self {
+        Self::spawn_with(|config| config).await
+    }
+
+    /// Spawn the app with extra configuration.
+    pub(crate) async fn spawn_with(
+        configure: impl FnOnce(zero2prod::ConfigBuilder) -> zero2prod::ConfigBuilder,
+    ) -> Self {
         TRACING_ENABLED.call_once(|| {
             if std::env::var("TEST_LOG").is_ok() {
                 zero2prod::telemetry::init("test", std::io::stdout);
@@ -26,16 +49,25 @@ impl TestApp {
 
         let email_server = MockServer::start().await;
 
-        let config = zero2prod::Config::builder()
-            .address((Ipv4Addr::LOCALHOST, 0).into())
-            // FIXME: we don't know what address to use 😭
-            .base_url("http://127.0.0.1:0".parse().unwrap())
-            .email_base_url(email_server.uri().parse().unwrap())
-            .email_sender("test@test.test".parse().unwrap())
-            .email_authorization_token("foo".to_string())
-            .email_send_timeout(std::time::Duration::from_millis(200))
-            .build()
-            .expect("failed to builder configuration");
+        let config = configure(
+            zero2prod::Config::builder()
+                .address((Ipv4Addr::LOCALHOST, 0).into())
+                // FIXME: we don't know what address to use 😭
+                .base_url("http://127.0.0.1:0".parse().unwrap())
+                .email_base_url(email_server.uri().parse().unwrap())
+                .email_sender("test@test.test".parse().unwrap())
+                .email_authorization_token("foo".to_string())
+                .email_send_timeout(std::time::Duration::from_millis(200))
+                // Tests that want retries can ask for them, so that failures are seen immediately
+                .email_retry_deadline(std::time::Duration::ZERO)
+                .admin_token(ADMIN_TOKEN.to_string())
+                .postmark_webhook_username(POSTMARK_WEBHOOK_USERNAME.to_string())
+                .postmark_webhook_password(POSTMARK_WEBHOOK_PASSWORD.to_string())
+                .signing_key("signing-key".to_string())
+                .publish_webhook_secret(PUBLISH_WEBHOOK_SECRET.to_string()),
+        )
+        .build()
+        .expect("failed to builder configuration");
 
         // Create a unique test database
         let database = Uuid::new_v4().to_string();
@@ -53,6 +85,7 @@ impl TestApp {
         tokio::spawn(server);
 
         Self {
+            address: addr,
             port: addr.port(),
             pool,
             base_url: format!("http://{}/", addr).parse().unwrap(),
@@ -70,6 +103,133 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    /// Start a request to an admin endpoint, authenticated with the admin token.
+    pub(crate) fn admin_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+    ) -> reqwest::RequestBuilder {
+        reqwest::Client::new()
+            .request(method, self.base_url.join(path).unwrap())
+            .bearer_auth(ADMIN_TOKEN)
+    }
+
+    pub(crate) async fn post_postmark_webhook(
+        &self,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(self.base_url.join("/webhooks/postmark").unwrap())
+            .basic_auth(POSTMARK_WEBHOOK_USERNAME, Some(POSTMARK_WEBHOOK_PASSWORD))
+            .json(body)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    /// Post to the publish webhook, signed with the given timestamp and nonce.
+    pub(crate) async fn post_publish_webhook(
+        &self,
+        body: &serde_json::Value,
+        timestamp: i64,
+        nonce: &str,
+    ) -> reqwest::Response {
+        let body = body.to_string();
+        let signature = publish_webhook_signature(PUBLISH_WEBHOOK_SECRET, timestamp, nonce, &body);
+        reqwest::Client::new()
+            .post(self.base_url.join("/webhooks/publish").unwrap())
+            .header("x-webhook-timestamp", timestamp.to_string())
+            .header("x-webhook-nonce", nonce)
+            .header("x-webhook-signature", signature)
+            .header("content-type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    /// Publish a newsletter issue and wait for it to be sent, returning its slug.
+    pub(crate) async fn publish_newsletter(&self, title: &str, html_body: &str) -> String {
+        let response: serde_json::Value = self
+            .admin_request(reqwest::Method::POST, "/admin/newsletters")
+            .json(&serde_json::json!({
+                "title": title,
+                "html_body": html_body,
+                "text_body": "Plain text body",
+            }))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let slug = response["slug"].as_str().unwrap().to_string();
+        self.wait_for_delivery(&slug).await;
+        slug
+    }
+
+    /// Wait for the scheduler to finish sending an issue, returning its delivery report.
+    pub(crate) async fn wait_for_delivery(&self, slug: &str) -> DeliveryReport {
+        for _ in 0..500 {
+            let issue = sqlx::query_as!(
+                DeliveryReport,
+                r#"
+                SELECT
+                  delivered_count AS "delivered!",
+                  suppressed_count AS "suppressed!",
+                  failed_count AS "failed!"
+                FROM newsletter_issues
+                WHERE slug = $1 AND delivered_at IS NOT NULL
+                "#,
+                slug,
+            )
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap();
+            if let Some(report) = issue {
+                return report;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("timed out waiting for {} to be delivered", slug);
+    }
+
+    /// Subscribe the given email, returning the link from the confirmation email.
+    pub(crate) async fn create_unconfirmed_subscriber(&self, email: &str) -> ConfirmationLinks {
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+
+        let body = serde_urlencoded::to_string([("name", "le guin"), ("email", email)]).unwrap();
+        self.post_subscriptions(body)
+            .await
+            .error_for_status()
+            .unwrap();
+
+        let email_request = self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        self.get_confirmation_links(&email_request)
+    }
+
+    pub(crate) async fn create_confirmed_subscriber(&self, email: &str) {
+        let links = self.create_unconfirmed_subscriber(email).await;
+        reqwest::get(links.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
     pub(crate) fn get_confirmation_links(&self, request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
 
@@ -95,6 +255,27 @@ impl TestApp {
     }
 }
 
+/// Respond to a batch send as if the provider accepted every email.
+pub(crate) struct AcceptBatch;
+
+impl wiremock::Respond for AcceptBatch {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let emails: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<_> = emails
+            .iter()
+            .map(|email| {
+                serde_json::json!({
+                    "To": email["To"],
+                    "MessageID": Uuid::new_v4().to_string(),
+                    "ErrorCode": 0,
+                    "Message": "OK",
+                })
+            })
+            .collect();
+        ResponseTemplate::new(200).set_body_json(results)
+    }
+}
+
 pub(crate) struct ConfirmationLinks {
     pub(crate) html: Url,
     pub(crate) text: Url,
@@ -112,3 +293,16 @@ async fn create_database(
 
     Ok(())
 }
+
+pub(crate) fn publish_webhook_signature(
+    secret: &str,
+    timestamp: i64,
+    nonce: &str,
+    body: &str,
+) -> String {
+    use hmac::{Mac as _, NewMac as _};
+
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{}.{}.{}", timestamp, nonce, body).as_bytes());
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
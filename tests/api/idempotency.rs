@@ -0,0 +1,277 @@
+use std::time::Duration;
+
+use reqwest::Method;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::TestApp;
+
+const BODY: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+async fn subscribe(app: &TestApp, key: &str, body: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(app.base_url.join("/subscriptions").unwrap())
+        .header("content-type", "application/x-www-form-urlencoded")
+        .header("idempotency-key", key)
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn subscription_count(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn retries_replay_the_first_response_without_repeating_side_effects() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let first = subscribe(&app, "key-1", BODY).await;
+    assert_eq!(first.status().as_u16(), 200);
+    assert!(first.headers().get("idempotent-replayed").is_none());
+
+    let retry = subscribe(&app, "key-1", BODY).await;
+    assert_eq!(retry.status().as_u16(), 200);
+    assert_eq!(retry.headers()["idempotent-replayed"], "true");
+    assert_eq!(subscription_count(&app).await, 1);
+
+    // Without a key, the duplicate runs again, but finds the existing subscriber
+    let response = app.post_subscriptions(BODY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get("idempotent-replayed").is_none());
+    assert_eq!(subscription_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn responses_are_replayed_verbatim() {
+    let app = TestApp::spawn().await;
+    let publish = || {
+        app.admin_request(Method::POST, "/admin/newsletters")
+            .header("idempotency-key", "key-1")
+            .json(&serde_json::json!({
+                "title": "Issue one",
+                "html_body": "<p>Body</p>",
+                "text_body": "Body",
+            }))
+            .send()
+    };
+
+    let first = publish().await.unwrap();
+    let retry = publish().await.unwrap();
+
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(retry.status(), first.status());
+    assert_eq!(
+        retry.headers()["content-type"],
+        first.headers()["content-type"]
+    );
+    assert_eq!(retry.text().await.unwrap(), first.text().await.unwrap());
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn replays_need_the_original_credentials() {
+    let app = TestApp::spawn().await;
+    let body = serde_json::json!({
+        "title": "Issue one",
+        "html_body": "<p>Body</p>",
+        "text_body": "Body",
+    });
+    let response = app
+        .admin_request(Method::POST, "/admin/newsletters")
+        .header("idempotency-key", "key-1")
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+
+    for token in [None, Some("wrong-token")] {
+        let mut request = reqwest::Client::new()
+            .post(app.base_url.join("/admin/newsletters").unwrap())
+            .header("idempotency-key", "key-1")
+            .json(&body);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.unwrap();
+
+        assert_eq!(response.status().as_u16(), 422, "{:?}", token);
+        assert!(response.headers().get("idempotent-replayed").is_none());
+    }
+}
+
+#[tokio::test]
+async fn keys_are_scoped_to_the_route() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe(&app, "key-1", BODY).await;
+    let response = app
+        .admin_request(Method::POST, "/admin/newsletters")
+        .header("idempotency-key", "key-1")
+        .json(&serde_json::json!({
+            "title": "Issue one",
+            "html_body": "<p>Body</p>",
+            "text_body": "Body",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 202);
+    assert!(response.headers().get("idempotent-replayed").is_none());
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_request_is_rejected() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe(&app, "key-1", BODY).await;
+    let response = subscribe(
+        &app,
+        "key-1",
+        "name=octavia&email=octavia_butler%40gmail.com",
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(subscription_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn concurrent_duplicates_are_rejected() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(150)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let first = subscribe(&app, "key-1", BODY);
+    let second = async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        subscribe(&app, "key-1", BODY).await
+    };
+    let (first, second) = tokio::join!(first, second);
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn server_errors_are_not_stored() {
+    let app = TestApp::spawn().await;
+    let failing = Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let response = subscribe(&app, "key-1", BODY).await;
+    assert_eq!(response.status().as_u16(), 500);
+    drop(failing);
+
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = subscribe(&app, "key-1", BODY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get("idempotent-replayed").is_none());
+    assert_eq!(subscription_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn keys_expire() {
+    let app =
+        TestApp::spawn_with(|config| config.idempotency_key_ttl(Duration::from_millis(100))).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe(&app, "key-1", BODY).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The retry runs again, rather than being replayed
+    let response = subscribe(&app, "key-1", BODY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get("idempotent-replayed").is_none());
+}
+
+#[tokio::test]
+async fn invalid_keys_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    let response = subscribe(&app, "not a valid key", BODY).await;
+
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(subscription_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn oversized_requests_are_rejected() {
+    let app = TestApp::spawn_with(|config| config.idempotency_max_body_size(256)).await;
+    let body = format!("{}&padding={}", BODY, "x".repeat(256));
+
+    let response = subscribe(&app, "key-1", &body).await;
+
+    assert_eq!(response.status().as_u16(), 413);
+    assert_eq!(subscription_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn oversized_responses_are_sent_but_not_stored() {
+    let app = TestApp::spawn_with(|config| config.idempotency_max_body_size(256)).await;
+    let import = || {
+        app.admin_request(
+            Method::POST,
+            "/admin/subscribers/import?mode=consented&consent_note=test",
+        )
+        .header("idempotency-key", "key-1")
+        .body(
+            "name,email\n\
+            le guin,ursula_le_guin@gmail.com\n\
+            butler,octavia_butler@gmail.com\n\
+            jemisin,nk_jemisin@gmail.com\n\
+            wolfe,gene_wolfe@gmail.com\n",
+        )
+        .send()
+    };
+
+    let first = import().await.unwrap();
+    assert_eq!(first.status().as_u16(), 200);
+    let report: serde_json::Value = first.json().await.unwrap();
+    assert_eq!(report["rows"].as_array().unwrap().len(), 4);
+
+    // The import committed, so the key stays claimed rather than letting it run again
+    let retry = import().await.unwrap();
+    assert_eq!(retry.status().as_u16(), 409);
+    assert_eq!(subscription_count(&app).await, 4);
+}
@@ -1,3 +1,9 @@
+mod admin;
+mod blog_feed;
+mod feeds;
 mod health;
 mod helpers;
+mod idempotency;
+mod newsletters;
 mod subscriptions;
+mod webhooks;
@@ -0,0 +1,76 @@
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn archive_lists_published_issues_newest_first() {
+    let app = TestApp::spawn().await;
+    app.publish_newsletter("First issue", "<p>One</p>").await;
+    app.publish_newsletter("Second <issue>", "<p>Two</p>").await;
+
+    let response = get(&app, "/newsletters").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/html; charset=utf-8"
+    );
+    let body = response.text().await.unwrap();
+    let first = body.find(r#"<a href="/newsletters/first-issue">First issue</a>"#);
+    let second = body.find(r#"<a href="/newsletters/second-issue">Second &lt;issue&gt;</a>"#);
+    assert!(matches!((first, second), (Some(first), Some(second)) if second < first));
+    assert!(!body.contains("Older"));
+}
+
+#[tokio::test]
+async fn archive_is_paginated() {
+    let app = TestApp::spawn().await;
+    for n in 1..=21 {
+        app.publish_newsletter(&format!("Issue {}", n), "<p>Body</p>")
+            .await;
+    }
+
+    let first_page = get(&app, "/newsletters").await.text().await.unwrap();
+    assert_eq!(first_page.matches("<li>").count(), 20);
+    assert!(first_page.contains(r#"<a href="/newsletters?page=2">Older</a>"#));
+    assert!(!first_page.contains("Newer"));
+
+    let second_page = get(&app, "/newsletters?page=2").await.text().await.unwrap();
+    assert_eq!(second_page.matches("<li>").count(), 1);
+    assert!(second_page.contains(r#"<a href="/newsletters/issue-1">Issue 1</a>"#));
+    assert!(second_page.contains(r#"<a href="/newsletters?page=1">Newer</a>"#));
+    assert!(!second_page.contains("Older"));
+
+    let response = get(&app, "/newsletters?page=0").await;
+    assert!(response.status().is_client_error());
+}
+
+#[tokio::test]
+async fn issue_pages_render_the_stored_html_in_the_layout() {
+    let app = TestApp::spawn().await;
+    let slug = app
+        .publish_newsletter("Hello, World!", "<p>The <em>first</em> issue.</p>")
+        .await;
+    assert_eq!(slug, "hello-world");
+
+    let response = get(&app, "/newsletters/hello-world").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<title>Hello, World!</title>"));
+    assert!(body.contains("<p>The <em>first</em> issue.</p>"));
+    assert!(body.contains(r#"<a href="/newsletters">Newsletter archive</a>"#));
+}
+
+#[tokio::test]
+async fn unknown_issues_return_404() {
+    let app = TestApp::spawn().await;
+
+    let response = get(&app, "/newsletters/nope").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+async fn get(app: &TestApp, path: &str) -> reqwest::Response {
+    reqwest::get(app.base_url.join(path).unwrap())
+        .await
+        .unwrap()
+}
@@ -1,4 +1,6 @@
 mod confirm;
+mod data_export;
+mod erasure;
 
 use axum::http::StatusCode;
 use wiremock::{
@@ -148,6 +150,204 @@ async fn the_link_returned_by_subscribe_confirms_a_subscriber() {
     assert_eq!(saved.status, "confirmed");
 }
 
+#[tokio::test]
+async fn subscribe_rejects_suppressed_addresses_without_sending_email() {
+    let app = TestApp::spawn().await;
+    sqlx::query!(
+        "INSERT INTO suppressions (email, reason, created_at) VALUES ($1, 'test', now())",
+        "ursula_le_guin@gmail.com",
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=Ursula_Le_Guin%40gmail.com";
+    let response = app.post_subscriptions(body).await;
+    assert_status("suppressed", StatusCode::UNPROCESSABLE_ENTITY, response).await;
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn subscribe_keeps_the_original_spelling_and_stores_a_canonical_email() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=Ursula_Le_Guin%40Gmail.com";
+    app.post_subscriptions(body).await;
+
+    let saved = sqlx::query!("SELECT email, email_canonical FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "Ursula_Le_Guin@Gmail.com");
+    assert_eq!(
+        saved.email_canonical.as_deref(),
+        Some("ursula_le_guin@gmail.com")
+    );
+}
+
+#[tokio::test]
+async fn subscribe_does_not_create_duplicates_that_differ_by_case() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+    assert_status("new", StatusCode::OK, response).await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40GMAIL.com")
+        .await;
+    assert_status("case variant", StatusCode::OK, response).await;
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn subscribe_does_not_create_duplicates_that_differ_by_gmail_dots() {
+    let app = TestApp::spawn_with(|config| config.canonicalize_gmail_dots(true)).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for email in ["ursulaleguin%40gmail.com", "ursula.le.guin%40gmail.com"] {
+        let response = app
+            .post_subscriptions(format!("name=le%20guin&email={}", email))
+            .await;
+        assert_status(email, StatusCode::OK, response).await;
+    }
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn existing_case_variant_duplicates_are_reported_as_collisions() {
+    let app = TestApp::spawn().await;
+    for email in ["ursula_le_guin@gmail.com", "Ursula_Le_Guin@gmail.com"] {
+        // As written before canonical emails existed
+        sqlx::query!(
+            "INSERT INTO subscriptions (id, email, name, subscribed_at) VALUES ($1, $2, 'le guin', now())",
+            uuid::Uuid::new_v4(),
+            email,
+        )
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    }
+
+    let collisions = sqlx::query!(
+        r#"SELECT email_canonical AS "email_canonical!", subscriber_ids AS "subscriber_ids!" FROM subscription_email_collisions"#
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap();
+
+    assert_eq!(collisions.len(), 1);
+    assert_eq!(collisions[0].email_canonical, "ursula_le_guin@gmail.com");
+    assert_eq!(collisions[0].subscriber_ids.len(), 2);
+}
+
+#[tokio::test]
+async fn subscribe_rejects_disposable_email_domains() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40mailinator.com")
+        .await;
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.text().await.unwrap().contains("disposable"));
+}
+
+#[tokio::test]
+async fn subscribe_suggests_corrections_for_domain_typos() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmial.com")
+        .await;
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["suggestion"], "ursula_le_guin@gmail.com");
+    assert!(body["error"].is_string());
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn subscribe_accepts_suspected_typos_when_confirmed_anyway() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmial.com&confirm_anyway=true")
+        .await;
+    assert_status("confirmed anyway", StatusCode::OK, response).await;
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmial.com");
+}
+
 async fn assert_status(problem: &str, expected: StatusCode, response: reqwest::Response) {
     assert_eq!(
         expected,
@@ -159,3 +359,34 @@ got {}: {:?}",
         response.text().await,
     );
 }
+
+#[tokio::test]
+async fn subscribe_fails_fast_while_the_email_provider_is_down() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .email_circuit_window(2)
+            .email_circuit_open_duration(std::time::Duration::from_secs(60))
+    })
+    .await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    for _ in 0..3 {
+        let response = app.post_subscriptions(body).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let readiness: serde_json::Value = reqwest::get(app.base_url.join("/health/ready").unwrap())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(readiness["email"], "open");
+}
@@ -0,0 +1,113 @@
+use reqwest::Url;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn requesting_a_data_export_emails_a_download_link() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    let link = request_data_export(&app, "Ursula_Le_Guin@gmail.com").await;
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"subscriber-data.json\""
+    );
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscription"]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(data["subscription"]["name"], "le guin");
+    assert_eq!(data["subscription"]["status"], "confirmed");
+    assert_eq!(data["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert!(data["consents"].as_array().unwrap().is_empty());
+    assert!(data["email_events"].as_array().unwrap().is_empty());
+    let kinds: Vec<_> = data["email_deliveries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|delivery| delivery["kind"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, ["confirmation", "data_export"]);
+    assert!(data["exported_at"].is_string());
+}
+
+#[tokio::test]
+async fn requesting_a_data_export_for_an_unknown_email_succeeds_without_sending_email() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_data_export_request(&app, "ursula_le_guin@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn data_export_rejects_tampered_tokens() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    let link = request_data_export(&app, "ursula_le_guin@gmail.com").await;
+    let token = link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    let mut tampered = link.clone();
+    tampered
+        .query_pairs_mut()
+        .clear()
+        .append_pair("token", &format!("{}x", token));
+    let response = reqwest::get(tampered).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let mut missing = link;
+    missing.set_query(None);
+    let response = reqwest::get(missing).await.unwrap();
+    assert!(response.status().is_client_error());
+}
+
+async fn post_data_export_request(app: &TestApp, email: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(app.base_url.join("/subscriptions/data-export").unwrap())
+        .form(&[("email", email)])
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Request a data export, returning the link from the email.
+async fn request_data_export(app: &TestApp, email: &str) -> Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    post_data_export_request(app, email)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
@@ -0,0 +1,211 @@
+use reqwest::Url;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn erasure_link_shows_a_confirmation_form_without_erasing() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    let link = request_erasure(&app, "ursula_le_guin@gmail.com").await;
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains(r#"<form method="post""#));
+    assert_eq!(subscriber_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn confirming_erasure_deletes_all_subscriber_data() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.post_postmark_webhook(&serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "SoftBounce",
+        "Email": "ursula_le_guin@gmail.com",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let link = request_erasure(&app, "ursula_le_guin@gmail.com").await;
+    let response = confirm_erasure(&app, &link).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 0);
+    for table in [
+        "subscription_tokens",
+        "consents",
+        "email_events",
+        "email_deliveries",
+    ] {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT count(*) FROM {}", table))
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0, "{} was not erased", table);
+    }
+
+    let erasure = sqlx::query!("SELECT requested_by FROM erasures")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(erasure.requested_by, "subscriber");
+
+    // Using the link again finds nothing to erase
+    let response = confirm_erasure(&app, &link).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn erasure_deletes_stored_responses_that_mention_the_subscriber() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("Ursula_Le_Guin@gmail.com")
+        .await;
+    for (key, body) in [
+        ("mentions-email", r#"{"email":"ursula_le_guin@gmail.com"}"#),
+        ("unrelated", r#"{"email":"octavia_butler@gmail.com"}"#),
+    ] {
+        sqlx::query!(
+            r#"
+            INSERT INTO idempotency_keys
+              (key, method, path, request_hash, created_at, response_status, response_body)
+            VALUES ($1, 'POST', '/admin/subscribers', '', now(), 201, $2)
+            "#,
+            key,
+            body.as_bytes(),
+        )
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    }
+
+    let link = request_erasure(&app, "ursula_le_guin@gmail.com").await;
+    confirm_erasure(&app, &link)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let keys: Vec<String> = sqlx::query_scalar("SELECT key FROM idempotency_keys")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(keys, ["unrelated"]);
+}
+
+#[tokio::test]
+async fn subscribers_without_a_canonical_email_can_be_erased() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("Ursula_Le_Guin@gmail.com")
+        .await;
+    // Subscribers that collided when canonical emails were added were left without one
+    sqlx::query!("UPDATE subscriptions SET email_canonical = NULL")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    let link = request_erasure(&app, "ursula_le_guin@gmail.com").await;
+    let response = confirm_erasure(&app, &link).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn erasure_rejects_invalid_tokens() {
+    let app = TestApp::spawn().await;
+
+    let response = reqwest::Client::new()
+        .post(app.base_url.join("/subscriptions/erasure/confirm").unwrap())
+        .form(&[("token", "not-a-token")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = reqwest::get(
+        app.base_url
+            .join("/subscriptions/erasure/confirm?token=<script>")
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn requesting_erasure_for_an_unknown_email_succeeds_without_sending_email() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_erasure_request(&app, "ursula_le_guin@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn post_erasure_request(app: &TestApp, email: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(app.base_url.join("/subscriptions/erasure").unwrap())
+        .form(&[("email", email)])
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Request erasure, returning the link from the email.
+async fn request_erasure(app: &TestApp, email: &str) -> Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    post_erasure_request(app, email)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+async fn confirm_erasure(app: &TestApp, link: &Url) -> reqwest::Response {
+    let token = link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    reqwest::Client::new()
+        .post(app.base_url.join("/subscriptions/erasure/confirm").unwrap())
+        .form(&[("token", token)])
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query_scalar("SELECT count(*) FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+}
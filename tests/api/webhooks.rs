@@ -0,0 +1,2 @@
+mod postmark;
+mod publish;
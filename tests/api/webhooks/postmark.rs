@@ -0,0 +1,198 @@
+use serde_json::json;
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn postmark_webhooks_without_credentials_are_rejected_with_a_401() {
+    let app = TestApp::spawn().await;
+
+    let response = reqwest::Client::new()
+        .post(app.base_url.join("/webhooks/postmark").unwrap())
+        .json(&spam_complaint("ursula_le_guin@gmail.com"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.headers().contains_key("www-authenticate"));
+}
+
+#[tokio::test]
+async fn postmark_webhooks_with_invalid_credentials_are_rejected_with_a_401() {
+    let app = TestApp::spawn().await;
+
+    let response = reqwest::Client::new()
+        .post(app.base_url.join("/webhooks/postmark").unwrap())
+        .basic_auth("postmark", Some("wrong"))
+        .json(&spam_complaint("ursula_le_guin@gmail.com"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn hard_bounces_mark_the_subscriber_as_bounced() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    let response = app
+        .post_postmark_webhook(&json!({
+            "RecordType": "Bounce",
+            "ID": 4323372036854775807_u64,
+            "Type": "HardBounce",
+            "TypeCode": 1,
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "Email": "ursula_le_guin@gmail.com",
+            "BouncedAt": "2019-11-05T16:33:54.9070259Z",
+            "Inactive": true,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(status(&app).await, "bounced");
+    assert_eq!(event_kinds(&app).await, vec!["bounce"]);
+    assert_eq!(suppressions(&app).await, vec!["ursula_le_guin@gmail.com"]);
+}
+
+#[tokio::test]
+async fn soft_bounces_are_recorded_without_changing_status() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    let response = app
+        .post_postmark_webhook(&json!({
+            "RecordType": "Bounce",
+            "Type": "SoftBounce",
+            "Email": "ursula_le_guin@gmail.com",
+            "Inactive": false,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(status(&app).await, "confirmed");
+    assert_eq!(event_kinds(&app).await, vec!["bounce"]);
+    assert!(suppressions(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn spam_complaints_mark_the_subscriber_as_complained() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    let response = app
+        .post_postmark_webhook(&spam_complaint("ursula_le_guin@gmail.com"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(status(&app).await, "complained");
+    assert_eq!(event_kinds(&app).await, vec!["spam_complaint"]);
+}
+
+#[tokio::test]
+async fn suppressing_subscription_changes_mark_the_subscriber_as_unsubscribed() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    let response = app
+        .post_postmark_webhook(&json!({
+            "RecordType": "SubscriptionChange",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "ChangedAt": "2020-02-01T10:53:34.416071Z",
+            "Recipient": "ursula_le_guin@gmail.com",
+            "Origin": "Recipient",
+            "SuppressSending": true,
+            "SuppressionReason": "ManualSuppression",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(status(&app).await, "unsubscribed");
+    assert_eq!(event_kinds(&app).await, vec!["subscription_change"]);
+}
+
+#[tokio::test]
+async fn events_for_unknown_recipients_are_acknowledged_and_suppressed() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .post_postmark_webhook(&spam_complaint("ursula_le_guin@gmail.com"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(suppressions(&app).await, vec!["ursula_le_guin@gmail.com"]);
+}
+
+#[tokio::test]
+async fn events_for_invalid_recipients_are_acknowledged() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .post_postmark_webhook(&spam_complaint("not-an-email"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(suppressions(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn unhandled_record_types_are_acknowledged() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    let response = app
+        .post_postmark_webhook(&json!({
+            "RecordType": "Open",
+            "Recipient": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(status(&app).await, "confirmed");
+    assert!(event_kinds(&app).await.is_empty());
+}
+
+fn spam_complaint(email: &str) -> serde_json::Value {
+    json!({
+        "RecordType": "SpamComplaint",
+        "ID": 42,
+        "Type": "SpamComplaint",
+        "TypeCode": 512,
+        "Email": email,
+        "BouncedAt": "2019-11-05T16:33:54.9070259Z",
+    })
+}
+
+async fn status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .expect("failed to fetch saved subscription")
+        .status
+}
+
+async fn event_kinds(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT kind FROM email_events ORDER BY received_at")
+        .fetch_all(&app.pool)
+        .await
+        .expect("failed to fetch email events")
+        .into_iter()
+        .map(|row| row.kind)
+        .collect()
+}
+
+async fn suppressions(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT email FROM suppressions ORDER BY email")
+        .fetch_all(&app.pool)
+        .await
+        .expect("failed to fetch suppressions")
+        .into_iter()
+        .map(|row| row.email)
+        .collect()
+}
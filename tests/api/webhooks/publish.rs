@@ -0,0 +1,243 @@
+use time::OffsetDateTime;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{publish_webhook_signature, AcceptBatch, TestApp};
+
+fn post() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Zero to production",
+        "url": "https://blog.example/zero-to-production",
+        "excerpt": "Shipping Rust",
+        "html": "<h1>Zero to production</h1><p>Shipping <em>Rust</em></p>",
+    })
+}
+
+fn now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+#[tokio::test]
+async fn signed_posts_become_drafts() {
+    let app = TestApp::spawn().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_publish_webhook(&post(), now(), "nonce-1").await;
+
+    assert_eq!(response.status().as_u16(), 201);
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["slug"], "zero-to-production");
+    assert_eq!(created["published"], false);
+
+    let issue =
+        sqlx::query!("SELECT title, html_body, text_body, published_at FROM newsletter_issues")
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+    assert_eq!(issue.title, "Zero to production");
+    assert!(issue.published_at.is_none());
+    assert!(issue
+        .html_body
+        .starts_with("<h1>Zero to production</h1><p>Shipping <em>Rust</em></p>"));
+    assert!(issue.html_body.contains(
+        r#"<a href="https://blog.example/zero-to-production">Read this post on the blog</a>"#
+    ));
+    assert_eq!(
+        issue.text_body,
+        "Zero to production\n\nShipping Rust\n\n\
+        Read this post on the blog: https://blog.example/zero-to-production"
+    );
+}
+
+#[tokio::test]
+async fn signed_posts_are_sent_when_requested() {
+    let app = TestApp::spawn().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptBatch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let mut body = post();
+    body["send"] = true.into();
+    let response = app.post_publish_webhook(&body, now(), "nonce-1").await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["published"], true);
+    let slug = created["slug"].as_str().unwrap();
+    assert_eq!(app.wait_for_delivery(slug).await.delivered, 1);
+}
+
+#[tokio::test]
+async fn post_html_is_sanitized() {
+    let app = TestApp::spawn().await;
+    let mut body = post();
+    body["html"] = r#"<p onclick="steal()">Shipping</p><script>steal()</script>"#.into();
+
+    let response = app.post_publish_webhook(&body, now(), "nonce-1").await;
+
+    assert_eq!(response.status().as_u16(), 201);
+    let issue = sqlx::query!("SELECT html_body, text_body FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert!(issue.html_body.starts_with("<p>Shipping</p>\n"));
+    assert!(!issue.text_body.contains("steal"));
+}
+
+#[tokio::test]
+async fn posts_that_already_have_an_issue_return_it() {
+    let app = TestApp::spawn().await;
+
+    let response = app.post_publish_webhook(&post(), now(), "nonce-1").await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let mut body = post();
+    body["title"] = "Zero to production, again".into();
+    body["send"] = true.into();
+    let response = app.post_publish_webhook(&body, now(), "nonce-2").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let existing: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(existing["slug"], "zero-to-production");
+    assert_eq!(existing["published"], false);
+
+    let issues = sqlx::query!("SELECT title, send_at FROM newsletter_issues")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].title, "Zero to production");
+    assert!(issues[0].send_at.is_none());
+}
+
+#[tokio::test]
+async fn invalid_signatures_are_rejected() {
+    let app = TestApp::spawn().await;
+    let body = post().to_string();
+    let timestamp = now();
+
+    let cases = [
+        (
+            "wrong secret",
+            publish_webhook_signature("wrong", timestamp, "nonce-1", &body),
+            "nonce-1",
+        ),
+        (
+            "different nonce",
+            publish_webhook_signature(
+                crate::helpers::PUBLISH_WEBHOOK_SECRET,
+                timestamp,
+                "nonce-2",
+                &body,
+            ),
+            "nonce-1",
+        ),
+        ("unsigned", String::new(), "nonce-1"),
+    ];
+
+    for (problem, signature, nonce) in cases {
+        let response = reqwest::Client::new()
+            .post(app.base_url.join("/webhooks/publish").unwrap())
+            .header("x-webhook-timestamp", timestamp.to_string())
+            .header("x-webhook-nonce", nonce)
+            .header("x-webhook-signature", signature)
+            .body(body.clone())
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 401, "{}", problem);
+    }
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn stale_timestamps_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    for timestamp in [now() - 10 * 60, now() + 10 * 60] {
+        let response = app
+            .post_publish_webhook(&post(), timestamp, "nonce-1")
+            .await;
+
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn replayed_nonces_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    let response = app.post_publish_webhook(&post(), now(), "nonce-1").await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_publish_webhook(&post(), now(), "nonce-1").await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn future_dated_nonces_are_remembered_until_their_timestamp_expires() {
+    let app = TestApp::spawn().await;
+    let timestamp = now() + 4 * 60;
+
+    let response = app
+        .post_publish_webhook(&post(), timestamp, "nonce-1")
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    // Pretend 6 minutes have passed by moving everything back 6 minutes, including the replayed
+    // request's timestamp. The nonce was then received longer ago than the tolerance, but its
+    // timestamp is still acceptable.
+    sqlx::query!(
+        r#"
+        UPDATE publish_webhook_nonces
+        SET received_at = received_at - interval '6 minutes',
+            signed_at = signed_at - interval '6 minutes'
+        "#
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_publish_webhook(&post(), timestamp - 6 * 60, "nonce-1")
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn posts_need_html_or_an_excerpt() {
+    let app = TestApp::spawn().await;
+    let body = serde_json::json!({
+        "title": "Untitled",
+        "url": "https://blog.example/untitled",
+    });
+
+    let response = app.post_publish_webhook(&body, now(), "nonce-1").await;
+
+    assert_eq!(response.status().as_u16(), 422);
+}